@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+
+use super::error::CalcError;
+use super::environment::Environment;
+use super::expression::{Expression, Token, Branch};
+use super::operator::{Operator, Function, AngleUnit};
+use super::number::{Number, Settings, exact_trig};
+
+/// One step of a `CompiledExpression`. Same idea as a `Token`, but variables are already looked up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Instruction {
+	Constant(f64),
+	Slot(usize),
+	/// One of the arguments of the function we're in the middle of.
+	Parameter(usize),
+	Operator(Operator),
+	/// The function, and how many arguments it takes off the stack.
+	Function(Function, usize),
+	/// A trig function of an angle in some unit that isn't radians. Those can land right on a quarter turn, so they get checked for one before converting.
+	Trig(Function, AngleUnit),
+	/// Which of the `CompiledExpression`'s `functions` to call, and how many arguments it takes off the stack.
+	Call(usize, usize),
+	/// Takes an `if`'s condition off the stack. If it's true, keep going, if it's false, go to `otherwise`, and if it's NaN, that's the answer, so go to `end`.
+	Branch { otherwise: usize, end: usize },
+	/// Goes to another instruction, for skipping past the side of an `if` that wasn't picked.
+	Jump(usize),
+}
+
+/// A function somebody defined, compiled.
+#[derive(Debug, Clone)]
+struct CompiledFunction {
+	name: String,
+	instructions: Vec<Instruction>,
+}
+
+/// An `Expression` that's been turned into something quick to calculate over and over, for graphing and such.
+/// 
+/// Variables are turned into slot numbers ahead of time, and the stack is allocated once and reused, so calculating doesn't allocate or do any `HashMap` lookups.
+/// `Expression::calculate` is still the simple version to check this one against.
+#[derive(Debug, Clone)]
+pub struct CompiledExpression {
+	instructions: Vec<Instruction>,
+	/// Every function somebody defined that this calls (or that those call, and so on).
+	functions: Vec<CompiledFunction>,
+	stack: Vec<f64>,
+}
+
+/// Keeps track of which functions have been compiled so far while compiling.
+struct Compiler<'a> {
+	slots: &'a [&'a str],
+	variables: Option<&'a Environment>,
+	functions: Vec<CompiledFunction>,
+	function_indices: HashMap<String, usize>,
+}
+
+impl<'a> Compiler<'a> {
+	/// Compiles one expression. `parameters` are the names of the arguments, if this is the body of a function.
+	/// 
+	/// Gives back the instructions and how deep the stack gets. (Not counting any function calls.)
+	fn compile(&mut self, expression: &Expression, parameters: &[String]) -> Result<(Vec<Instruction>, usize), CalcError> {
+		let mut instructions: Vec<Instruction> = Vec::new();
+		let mut depth: usize = 0;
+		let mut max_depth: usize = 0;
+		let mut arg_count: Option<usize> = None;
+		let angle_unit = self.variables.map_or(AngleUnit::Radians, Environment::angle_unit);
+		
+		// Multiplies the top of the stack by `factor`, for turning angles from one unit into another. Doing it here means calculating never has to think about units.
+		fn scale(instructions: &mut Vec<Instruction>, factor: f64) {
+			if factor != 1.0 {
+				instructions.push(Instruction::Constant(factor));
+				instructions.push(Instruction::Operator(Operator::Mul));
+			}
+		}
+		
+		let branches = expression.branches();
+		// For each `if` we're in the middle of, where its `Branch` and `Jump` are, so they can be pointed at the right places once we know them.
+		let mut pending: HashMap<usize, (usize, Option<usize>)> = HashMap::new();
+		
+		for (i, (token, span)) in expression.tokens().iter().zip(expression.spans().iter().cloned()).enumerate() {
+			let (instruction, arg_length, name) = match token {
+				Token::Constant(c) => (Instruction::Constant(*c), 0, ""),
+				Token::Integer(n) => (Instruction::Constant(f64::from_integer(n, &Settings::default()).map_err(|e| match span { Some(s) => e.with_span(s), None => e })?), 0, ""),
+				Token::Variable(v) => {
+					// Parameters hide slots, and slots hide variables.
+					if let Some(p) = parameters.iter().position(|p| p == v) {
+						(Instruction::Parameter(p), 0, "")
+					} else if let Some(slot) = self.slots.iter().position(|s| s == v) {
+						(Instruction::Slot(slot), 0, "")
+					} else {
+						match self.variables.and_then(|vars| vars.get(v)) {
+							Some(c) => (Instruction::Constant(c), 0, ""),
+							None => return Err(CalcError::UndefinedVariable { name: v.clone(), span }),
+						}
+					}
+				},
+				Token::Operator(o) => (Instruction::Operator(*o), o.get_parameters(), o.symbol()),
+				Token::Function(f) => {
+					let arg_length = if f.is_variadic() {
+						match arg_count.take() {
+							Some(n) => n,
+							None => return Err(CalcError::Parse { message: "Variadic function is missing its argument count.", span }),
+						}
+					} else {
+						f.get_parameters()
+					};
+					(Instruction::Function(*f, arg_length), arg_length, f.name())
+				},
+				Token::UserFunction(name) => {
+					let arg_length = match arg_count.take() {
+						Some(n) => n,
+						None => return Err(CalcError::Parse { message: "Function call is missing its argument count.", span }),
+					};
+					let function = match self.variables.and_then(|vars| vars.function(name)) {
+						Some(f) => f,
+						None => return Err(CalcError::UserFunction { name: name.clone(), message: "Unknown function", span }),
+					};
+					if function.parameters.len() != arg_length {
+						return Err(CalcError::UserFunction { name: name.clone(), message: "Wrong number of arguments for", span });
+					}
+					if depth < arg_length {
+						return Err(CalcError::UserFunction { name: name.clone(), message: "Not enough arguments for", span });
+					}
+					
+					let index = match self.function_indices.get(name) {
+						Some(i) => *i,
+						None => {
+							// Claim a spot first, so functions that call themselves find it.
+							let index = self.functions.len();
+							self.function_indices.insert(name.clone(), index);
+							self.functions.push(CompiledFunction { name: name.clone(), instructions: Vec::new() });
+							
+							let (body, _) = self.compile(&function.body, &function.parameters)
+								.map_err(|e| match span { Some(s) => e.with_span(s), None => e })?;
+							self.functions[index].instructions = body;
+							index
+						},
+					};
+					(Instruction::Call(index, arg_length), arg_length, "")
+				},
+				Token::ArgCount(n) => {
+					arg_count = Some(*n);
+					continue;
+				},
+				Token::Matrix { .. } | Token::Index(_) => return Err(CalcError::Domain { message: "Matrices need matrix mode.", span }),
+				_ => continue,
+			};
+			
+			if depth < arg_length {
+				return Err(CalcError::Arity { name, expected: arg_length, found: depth, span });
+			}
+			depth = depth - arg_length + 1;
+			// Scaling needs one more spot on the stack, for a moment.
+			max_depth = max_depth.max(depth + 1);
+			
+			match instruction {
+				Instruction::Operator(Operator::Angle(unit)) => scale(&mut instructions, unit.factor(angle_unit)),
+				Instruction::Function(f, _) if f.takes_angle() && angle_unit != AngleUnit::Radians => instructions.push(Instruction::Trig(f, angle_unit)),
+				Instruction::Function(f, _) if f.gives_angle() => {
+					instructions.push(instruction);
+					scale(&mut instructions, AngleUnit::Radians.factor(angle_unit));
+				},
+				// The `if` itself doesn't do anything, since one side or the other already left its answer on the stack.
+				// Without a side for false, the answer's NaN, so that's the side for false.
+				Instruction::Function(f @ (Function::If | Function::IfThen), _) if pending.contains_key(&i) => {
+					if f == Function::IfThen {
+						instructions.push(Instruction::Constant(f64::NAN));
+					}
+					let (branch, jump) = pending.remove(&i).unwrap();
+					let here = instructions.len();
+					if let Instruction::Branch { end, .. } = &mut instructions[branch] {
+						*end = here;
+					}
+					if let Some(jump) = jump {
+						instructions[jump] = Instruction::Jump(here);
+					}
+				},
+				_ => instructions.push(instruction),
+			}
+			
+			match branches[i] {
+				Some(Branch::Condition { end, .. }) => {
+					pending.insert(end, (instructions.len(), None));
+					instructions.push(Instruction::Branch { otherwise: 0, end: 0 });
+				},
+				Some(Branch::Then { end }) => {
+					let (branch, jump) = pending.get_mut(&end).unwrap();
+					*jump = Some(instructions.len());
+					instructions.push(Instruction::Jump(0));
+					let otherwise = instructions.len();
+					if let Instruction::Branch { otherwise: o, .. } = &mut instructions[*branch] {
+						*o = otherwise;
+					}
+				},
+				None => {},
+			}
+		}
+		
+		if depth != 1 {
+			return Err(CalcError::Leftover { count: depth });
+		}
+		
+		Ok((instructions, max_depth))
+	}
+}
+
+impl Expression {
+	/// Compiles this expression so it can be calculated quickly. `slots` are the names of the variables, in the order their values will be given to `CompiledExpression::calculate`.
+	/// Any other variables are looked up in `variables` now, and baked in as constants. Functions defined in `variables` get compiled too.
+	/// 
+	/// Anything that'd go wrong no matter what the variables are (undefined variables, not enough arguments, leftover results) goes wrong here instead.
+	pub fn compile(&self, slots: &[&str], variables: Option<&Environment>) -> Result<CompiledExpression, CalcError> {
+		let mut compiler = Compiler { slots, variables, functions: Vec::new(), function_indices: HashMap::new() };
+		let (instructions, max_depth) = compiler.compile(self, &[])?;
+		
+		Ok(CompiledExpression { instructions, functions: compiler.functions, stack: Vec::with_capacity(max_depth) })
+	}
+}
+
+impl CompiledExpression {
+	/// Calculates the expression. `values` go with the `slots` this was compiled with.
+	/// 
+	/// # Panics
+	/// If there are fewer `values` than slots.
+	pub fn calculate(&mut self, values: &[f64]) -> Result<f64, CalcError> {
+		self.stack.clear();
+		
+		run(&self.instructions, &self.functions, &mut self.stack, values, 0, 0)?;
+		
+		Ok(self.stack[0])
+	}
+}
+
+/// Runs some instructions, leaving the result on top of `stack`.
+/// 
+/// `base` is where the current function's arguments start on the stack, and `depth` is how many calls deep we are.
+fn run(instructions: &[Instruction], functions: &[CompiledFunction], stack: &mut Vec<f64>, values: &[f64], base: usize, depth: usize) -> Result<(), CalcError> {
+	let mut next = 0;
+	while let Some(instruction) = instructions.get(next) {
+		next += 1;
+		match *instruction {
+			Instruction::Constant(c) => stack.push(c),
+			Instruction::Slot(slot) => stack.push(values[slot]),
+			Instruction::Parameter(p) => stack.push(stack[base + p]),
+			Instruction::Operator(o) => {
+				// Compiling already made sure there's enough on the stack.
+				let start = stack.len() - o.get_parameters();
+				let result = o.calculate(&stack[start..])?;
+				stack.truncate(start);
+				stack.push(result);
+			},
+			Instruction::Function(f, arg_length) => {
+				let start = stack.len() - arg_length;
+				let result = f.calculate(&stack[start..])?;
+				stack.truncate(start);
+				stack.push(result);
+			},
+			Instruction::Trig(f, unit) => {
+				let angle = stack.pop().unwrap();
+				let result = match exact_trig(f, angle, unit) {
+					Some(exact) => exact,
+					None => f.calculate(&[angle * unit.factor(AngleUnit::Radians)])?,
+				};
+				stack.push(result);
+			},
+			Instruction::Call(index, arg_length) => {
+				let function = &functions[index];
+				if depth >= Environment::MAX_RECURSION {
+					return Err(CalcError::UserFunction { name: function.name.clone(), message: "Too much recursion in", span: None });
+				}
+				
+				// The arguments stay where they are while the function runs, so its parameters can just look back down the stack at them.
+				let start = stack.len() - arg_length;
+				run(&function.instructions, functions, stack, values, start, depth + 1)?;
+				let result = stack.pop().unwrap();
+				stack.truncate(start);
+				stack.push(result);
+			},
+			Instruction::Branch { otherwise, end } => {
+				let condition = stack.pop().unwrap();
+				if condition.is_nan() {
+					stack.push(f64::NAN);
+					next = end;
+				} else if condition == 0.0 {
+					next = otherwise;
+				}
+			},
+			Instruction::Jump(to) => next = to,
+		}
+	}
+	
+	Ok(())
+}
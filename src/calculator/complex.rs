@@ -0,0 +1,382 @@
+use std::fmt;
+use std::ops::{Add, Sub, Mul, Div, Neg};
+use core::f64::consts::{PI, E, LN_10, FRAC_PI_2};
+
+use super::error::CalcError;
+use super::number::{Number, Settings, choose};
+use super::operator::{Operator, Function, AngleUnit};
+
+/// A complex number, `re + im*i`. Calculating with these is "complex mode": `Expression::evaluate::<Complex>`.
+/// 
+/// Every `Function` has a complex version. The ones that only make sense for real numbers (like `min`, `gcd`, or `%`) still work if everything's real, and give an error otherwise.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Complex {
+	pub re: f64,
+	pub im: f64,
+}
+
+impl Complex {
+	pub const I: Complex = Complex { re: 0.0, im: 1.0 };
+	
+	pub fn new(re: f64, im: f64) -> Complex {
+		Complex { re, im }
+	}
+	
+	pub fn real(re: f64) -> Complex {
+		Complex { re, im: 0.0 }
+	}
+	
+	pub fn is_real(self) -> bool {
+		self.im == 0.0
+	}
+	
+	/// The distance from 0. (`abs`)
+	pub fn norm(self) -> f64 {
+		self.re.hypot(self.im)
+	}
+	
+	/// The angle from the positive real axis, from -π to π. (`arg`)
+	pub fn arg(self) -> f64 {
+		self.im.atan2(self.re)
+	}
+	
+	pub fn conj(self) -> Complex {
+		Complex::new(self.re, -self.im)
+	}
+	
+	/// cos(θ) + i sin(θ), which is the same as e^(iθ).
+	pub fn cis(theta: Complex) -> Complex {
+		(Complex::I * theta).exp()
+	}
+	
+	pub fn exp(self) -> Complex {
+		if self.is_real() {
+			return Complex::real(self.re.exp());
+		}
+		let r = self.re.exp();
+		Complex::new(r * self.im.cos(), r * self.im.sin())
+	}
+	
+	/// The natural log. Negative numbers (and everything else on the branch cut) get +πi, so `ln(-1)` is `πi`.
+	pub fn ln(self) -> Complex {
+		Complex::new(self.norm().ln(), self.arg())
+	}
+	
+	/// The square root with a positive real part. Done on its own (instead of with `powc`) so `sqrt(-4)` is exactly `2i`.
+	pub fn sqrt(self) -> Complex {
+		if self.is_real() && self.re >= 0.0 {
+			return Complex::real(self.re.sqrt());
+		}
+		let r = self.norm();
+		let re = ((r + self.re) / 2.0).sqrt();
+		let im = ((r - self.re) / 2.0).sqrt();
+		Complex::new(re, if self.im < 0.0 { -im } else { im })
+	}
+	
+	/// Raises this to a whole number power by multiplying, so `i^2` is exactly `-1`.
+	pub fn powi(self, n: i32) -> Complex {
+		let mut result = Complex::real(1.0);
+		let mut base = self;
+		let mut exponent = n.unsigned_abs();
+		while exponent > 0 {
+			if exponent & 1 == 1 {
+				result = result * base;
+			}
+			base = base * base;
+			exponent >>= 1;
+		}
+		if n < 0 { Complex::real(1.0) / result } else { result }
+	}
+	
+	pub fn powc(self, exponent: Complex) -> Complex {
+		if exponent.is_real() {
+			if self.is_real() && (self.re >= 0.0 || exponent.re.fract() == 0.0) {
+				return Complex::real(self.re.powf(exponent.re));
+			}
+			if exponent.re.fract() == 0.0 && exponent.re.abs() <= i32::MAX as f64 {
+				return self.powi(exponent.re as i32);
+			}
+			if exponent.re == 0.5 {
+				return self.sqrt();
+			}
+		}
+		(self.ln() * exponent).exp()
+	}
+	
+	/// The `n`th root, `x^(1/n)`.
+	pub fn root(self, n: Complex) -> Complex {
+		if n == Complex::real(2.0) {
+			self.sqrt()
+		} else {
+			self.powc(Complex::real(1.0) / n)
+		}
+	}
+	
+	pub fn sin(self) -> Complex {
+		Complex::new(self.re.sin() * self.im.cosh(), self.re.cos() * self.im.sinh())
+	}
+	
+	pub fn cos(self) -> Complex {
+		Complex::new(self.re.cos() * self.im.cosh(), -(self.re.sin() * self.im.sinh()))
+	}
+	
+	pub fn tan(self) -> Complex {
+		self.sin() / self.cos()
+	}
+	
+	// Real numbers that have real answers get them straight from f64 in all of these, since it's a bit more accurate than the formulas.
+	
+	/// -i ln(iz + sqrt(1 - z^2))
+	pub fn asin(self) -> Complex {
+		if self.is_real() && self.re.abs() <= 1.0 {
+			return Complex::real(self.re.asin());
+		}
+		-(Complex::I * (Complex::I * self + (Complex::real(1.0) - self * self).sqrt()).ln())
+	}
+	
+	/// π/2 - asin(z)
+	pub fn acos(self) -> Complex {
+		if self.is_real() && self.re.abs() <= 1.0 {
+			return Complex::real(self.re.acos());
+		}
+		Complex::real(FRAC_PI_2) - self.asin()
+	}
+	
+	/// i/2 (ln(1 - iz) - ln(1 + iz))
+	pub fn atan(self) -> Complex {
+		if self.is_real() {
+			return Complex::real(self.re.atan());
+		}
+		let one = Complex::real(1.0);
+		Complex::new(0.0, 0.5) * ((one - Complex::I * self).ln() - (one + Complex::I * self).ln())
+	}
+	
+	pub fn sinh(self) -> Complex {
+		if self.is_real() {
+			return Complex::real(self.re.sinh());
+		}
+		(self.exp() - (-self).exp()) / Complex::real(2.0)
+	}
+	
+	pub fn cosh(self) -> Complex {
+		if self.is_real() {
+			return Complex::real(self.re.cosh());
+		}
+		(self.exp() + (-self).exp()) / Complex::real(2.0)
+	}
+	
+	pub fn tanh(self) -> Complex {
+		if self.is_real() {
+			return Complex::real(self.re.tanh());
+		}
+		self.sinh() / self.cosh()
+	}
+	
+	/// ln(z + sqrt(z^2 + 1))
+	pub fn asinh(self) -> Complex {
+		if self.is_real() {
+			return Complex::real(self.re.asinh());
+		}
+		(self + (self * self + Complex::real(1.0)).sqrt()).ln()
+	}
+	
+	/// ln(z + sqrt(z + 1) sqrt(z - 1)). Splitting up the square root like that keeps the branch cut where it's supposed to be.
+	pub fn acosh(self) -> Complex {
+		if self.is_real() && self.re >= 1.0 {
+			return Complex::real(self.re.acosh());
+		}
+		let one = Complex::real(1.0);
+		(self + (self + one).sqrt() * (self - one).sqrt()).ln()
+	}
+	
+	/// (ln(1 + z) - ln(1 - z)) / 2
+	pub fn atanh(self) -> Complex {
+		if self.is_real() && self.re.abs() < 1.0 {
+			return Complex::real(self.re.atanh());
+		}
+		let one = Complex::real(1.0);
+		((one + self).ln() - (one - self).ln()) / Complex::real(2.0)
+	}
+	
+	/// The real cube root for real numbers, so `cbrt(-8)` is -2. Anything else gets the principal one.
+	pub fn cbrt(self) -> Complex {
+		if self.is_real() {
+			return Complex::real(self.re.cbrt());
+		}
+		self.powc(Complex::real(1.0 / 3.0))
+	}
+	
+	/// Gets the real parts of `args`, if none of them have an imaginary part.
+	fn real_args(args: &[Complex]) -> Result<Vec<f64>, CalcError> {
+		if args.iter().all(|a| a.is_real()) {
+			Ok(args.iter().map(|a| a.re).collect())
+		} else {
+			Err(CalcError::Domain { message: "That only works on real numbers.", span: None })
+		}
+	}
+}
+
+impl Add for Complex {
+	type Output = Complex;
+	fn add(self, other: Complex) -> Complex {
+		Complex::new(self.re + other.re, self.im + other.im)
+	}
+}
+
+impl Sub for Complex {
+	type Output = Complex;
+	fn sub(self, other: Complex) -> Complex {
+		Complex::new(self.re - other.re, self.im - other.im)
+	}
+}
+
+impl Mul for Complex {
+	type Output = Complex;
+	fn mul(self, other: Complex) -> Complex {
+		// Keeps things like inf * 2 from turning into NaN + NaNi.
+		if self.is_real() && other.is_real() {
+			return Complex::real(self.re * other.re);
+		}
+		Complex::new(self.re * other.re - self.im * other.im, self.re * other.im + self.im * other.re)
+	}
+}
+
+impl Div for Complex {
+	type Output = Complex;
+	fn div(self, other: Complex) -> Complex {
+		// Same deal as multiplying, so 1/0 is inf and not inf + NaNi.
+		if other.is_real() {
+			if self.is_real() {
+				return Complex::real(self.re / other.re);
+			}
+			return Complex::new(self.re / other.re, self.im / other.re);
+		}
+		let denominator = other.re * other.re + other.im * other.im;
+		Complex::new(
+			(self.re * other.re + self.im * other.im) / denominator,
+			(self.im * other.re - self.re * other.im) / denominator,
+		)
+	}
+}
+
+impl Neg for Complex {
+	type Output = Complex;
+	fn neg(self) -> Complex {
+		// -0i would put negative real numbers on the wrong side of the branch cut, so ln(-2) would be ln(2) - πi.
+		if self.is_real() {
+			return Complex::real(-self.re);
+		}
+		Complex::new(-self.re, -self.im)
+	}
+}
+
+impl Number for Complex {
+	fn from_f64(value: f64) -> Complex {
+		Complex::real(value)
+	}
+	
+	fn to_f64(&self) -> Option<f64> {
+		if self.is_real() { Some(self.re) } else { None }
+	}
+	
+	/// `i` is only `i` if nobody's stored something else in it.
+	fn named(name: &str) -> Option<Complex> {
+		if name == "i" { Some(Complex::I) } else { None }
+	}
+	
+	fn operate(operator: Operator, args: &[Complex], _settings: &Settings) -> Result<Complex, CalcError> {
+		match operator {
+			Operator::Add => Ok(args[0] + args[1]),
+			Operator::Sub => Ok(args[0] - args[1]),
+			Operator::Mul => Ok(args[0] * args[1]),
+			Operator::Div => Ok(args[0] / args[1]),
+			Operator::Mod |
+			Operator::BitAnd | Operator::BitOr | Operator::Xor | Operator::BitNot |
+			Operator::Shl | Operator::Shr | Operator::To => Ok(Complex::real(operator.calculate(&Complex::real_args(args)?)?)),
+			// Equal or not doesn't need an order, so those work on anything. Everything else only works when it's real.
+			Operator::Eq | Operator::Ne if !(args[0].is_real() && args[1].is_real()) => {
+				Ok(Complex::real(((args[0] == args[1]) == (operator == Operator::Eq)) as u8 as f64))
+			},
+			Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge | Operator::Eq | Operator::Ne |
+			Operator::And | Operator::Or | Operator::Not => Ok(Complex::real(operator.calculate(&Complex::real_args(args)?)?)),
+			Operator::Pow => Ok(args[0].powc(args[1])),
+			Operator::Rot => Ok(args[1].root(args[0])),
+			Operator::Unp => Ok(args[0]),
+			Operator::Unm => Ok(-args[0]),
+			Operator::Angle(unit) => Ok(args[0] * Complex::real(unit.factor(AngleUnit::Radians))),
+		}
+	}
+	
+	fn call(function: Function, args: &[Complex], _settings: &Settings) -> Result<Complex, CalcError> {
+		let one = Complex::real(1.0);
+		match function {
+			Function::Sin => Ok(args[0].sin()),
+			Function::Cos => Ok(args[0].cos()),
+			Function::Tan => Ok(args[0].tan()),
+			Function::Csc => Ok(one / args[0].sin()),
+			Function::Sec => Ok(one / args[0].cos()),
+			Function::Cot => Ok(args[0].cos() / args[0].sin()),
+			Function::Asin => Ok(args[0].asin()),
+			Function::Acos => Ok(args[0].acos()),
+			Function::Atan => Ok(args[0].atan()),
+			Function::Acsc => Ok((one / args[0]).asin()),
+			Function::Asec => Ok((one / args[0]).acos()),
+			Function::Acot => Ok(Complex::real(FRAC_PI_2) - args[0].atan()),
+			Function::Sinh => Ok(args[0].sinh()),
+			Function::Cosh => Ok(args[0].cosh()),
+			Function::Tanh => Ok(args[0].tanh()),
+			Function::Asinh => Ok(args[0].asinh()),
+			Function::Acosh => Ok(args[0].acosh()),
+			Function::Atanh => Ok(args[0].atanh()),
+			Function::Sqrt => Ok(args[0].sqrt()),
+			Function::Cbrt => Ok(args[0].cbrt()),
+			Function::Exp => Ok(args[0].exp()),
+			Function::Log => Ok(args[0].ln() / Complex::real(LN_10)),
+			Function::Ln  => Ok(args[0].ln()),
+			Function::Abs => Ok(Complex::real(args[0].norm())),
+			Function::Sgn => {
+				let norm = args[0].norm();
+				Ok(if norm == 0.0 { args[0] } else { args[0] / Complex::real(norm) })
+			},
+			Function::Pi  => Ok(Complex::real(PI)),
+			Function::E   => Ok(Complex::real(E)),
+			// Complex numbers don't have an order, so these only work when everything's real.
+			Function::Min | Function::Max | Function::Clamp |
+			Function::Atan2 | Function::Gcd | Function::Lcm => Ok(Complex::real(function.calculate(&Complex::real_args(args)?)?)),
+			Function::LogBase => Ok(args[0].ln() / args[1].ln()),
+			Function::Root => Ok(args[0].root(args[1])),
+			Function::Sum => Ok(args.iter().fold(Complex::real(0.0), |a, b| a + *b)),
+			Function::Mean => Ok(args.iter().fold(Complex::real(0.0), |a, b| a + *b) / Complex::real(args.len() as f64)),
+			Function::Hypot => Ok(Complex::real(args.iter().map(|a| a.norm()).fold(0.0, f64::hypot))),
+			Function::Re => Ok(Complex::real(args[0].re)),
+			Function::Im => Ok(Complex::real(args[0].im)),
+			Function::Arg => Ok(Complex::real(args[0].arg())),
+			Function::Conj => Ok(args[0].conj()),
+			Function::Det | Function::Transpose => Ok(args[0]),
+			Function::Inv => Ok(one / args[0]),
+			Function::Dot => Ok(args[0] * args[1]),
+			Function::Norm => Ok(Complex::real(args[0].norm())),
+			Function::Cross | Function::Solve | Function::Lstsq |
+			Function::LuL | Function::LuU | Function::LuP | Function::QrQ | Function::QrR | Function::Chol |
+			Function::Rank | Function::Eig | Function::Eigvec => Ok(Complex::real(function.calculate(&Complex::real_args(args)?)?)),
+			Function::Cis => Ok(Complex::cis(args[0])),
+			Function::If | Function::IfThen => choose(args),
+		}
+	}
+}
+
+impl fmt::Display for Complex {
+	/// Writes this like `3 + 4i`, `-2i`, or `1 - i`. Real numbers are written just like an `f64` would be.
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.is_real() {
+			return write!(f, "{}", self.re);
+		}
+		
+		let im = if self.im.abs() == 1.0 { String::new() } else { self.im.abs().to_string() };
+		if self.re == 0.0 {
+			write!(f, "{}{}i", if self.im < 0.0 { "-" } else { "" }, im)
+		} else {
+			write!(f, "{} {} {}i", self.re, if self.im < 0.0 { "-" } else { "+" }, im)
+		}
+	}
+}
@@ -0,0 +1,699 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{One, Pow, Signed, Zero};
+
+use super::error::CalcError;
+use super::number::{Number, Settings, logic, choose};
+use super::integer::bitwise;
+use super::operator::{Operator, Function, AngleUnit};
+
+/// Extra digits that things like `sin` and `ln` are calculated with, so rounding along the way doesn't show up in the answer.
+const GUARD_DIGITS: usize = 10;
+
+/// A decimal number with as many significant digits as you want. Calculating with these is "decimal mode": `Expression::evaluate::<Decimal>`.
+/// 
+/// Every result is rounded to `Settings::precision` significant digits, including `sin`, `ln`, `Pi`, and all the other transcendental stuff.
+/// Numbers typed in with a decimal point are still read as `f64`s first, so they only have the ~17 digits those can hold. Whole numbers get every digit.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Decimal {
+	/// `mantissa * 10^exponent`, with no trailing zeros in the mantissa. Zero is always `0 * 10^0`. Make these with `Decimal::new` so that stays true.
+	Finite { mantissa: BigInt, exponent: i64 },
+	/// Infinities and NaN, which work the same as they do with floats.
+	NonFinite(f64),
+}
+
+impl Decimal {
+	pub const DEFAULT_PRECISION: usize = 50;
+	
+	pub fn new(mantissa: BigInt, exponent: i64) -> Decimal {
+		if mantissa.is_zero() {
+			return Decimal::Finite { mantissa, exponent: 0 };
+		}
+		
+		let (mut mantissa, mut exponent) = (mantissa, exponent);
+		let ten = BigInt::from(10u8);
+		loop {
+			let (quotient, remainder) = mantissa.div_rem(&ten);
+			if !remainder.is_zero() {
+				break;
+			}
+			mantissa = quotient;
+			exponent += 1;
+		}
+		Decimal::Finite { mantissa, exponent }
+	}
+	
+	pub fn integer(n: impl Into<BigInt>) -> Decimal {
+		Decimal::new(n.into(), 0)
+	}
+	
+	pub fn is_zero(&self) -> bool {
+		matches!(self, Decimal::Finite { mantissa, .. } if mantissa.is_zero())
+	}
+	
+	pub fn is_negative(&self) -> bool {
+		match self {
+			Decimal::Finite { mantissa, .. } => mantissa.is_negative(),
+			Decimal::NonFinite(x) => *x < 0.0,
+		}
+	}
+	
+	pub fn is_integer(&self) -> bool {
+		matches!(self, Decimal::Finite { exponent, .. } if *exponent >= 0)
+	}
+	
+	/// The closest `f64`.
+	pub fn float(&self) -> f64 {
+		match self {
+			Decimal::Finite { mantissa, exponent } => format!("{}e{}", mantissa, exponent).parse().unwrap_or(f64::NAN),
+			Decimal::NonFinite(x) => *x,
+		}
+	}
+	
+	/// Where the decimal point goes, counting from the left of the digits. So this is 1 for 3.14, 3 for 123, and -1 for 0.0042.
+	/// Zero doesn't really have one, so it gets something smaller than anything else.
+	fn magnitude(&self) -> i64 {
+		match self {
+			Decimal::Finite { mantissa, .. } if mantissa.is_zero() => i64::MIN / 4,
+			Decimal::Finite { mantissa, exponent } => exponent + digit_count(mantissa) as i64,
+			Decimal::NonFinite(_) => i64::MAX / 4,
+		}
+	}
+	
+	/// Cuts this down to `digits` significant digits, rounding half to even.
+	fn round(self, digits: usize) -> Decimal {
+		let (mantissa, exponent) = match self {
+			Decimal::Finite { mantissa, exponent } => (mantissa, exponent),
+			other => return other,
+		};
+		
+		let extra = digit_count(&mantissa) as i64 - digits as i64;
+		if extra <= 0 {
+			return Decimal::new(mantissa, exponent);
+		}
+		
+		let divisor = pow10(extra);
+		let (mut quotient, remainder) = mantissa.div_rem(&divisor);
+		let round_away = match (remainder.abs() * 2u8).cmp(&divisor) {
+			Ordering::Greater => true,
+			Ordering::Equal => quotient.is_odd(),
+			Ordering::Less => false,
+		};
+		if round_away {
+			quotient += mantissa.signum();
+		}
+		Decimal::new(quotient, exponent + extra)
+	}
+	
+	/// Chops off everything after the decimal point.
+	fn trunc(&self) -> BigInt {
+		match self {
+			Decimal::Finite { mantissa, exponent } if *exponent >= 0 => mantissa * pow10(*exponent),
+			Decimal::Finite { mantissa, exponent } => mantissa / pow10(-exponent),
+			Decimal::NonFinite(_) => BigInt::zero(),
+		}
+	}
+}
+
+fn pow10(exponent: i64) -> BigInt {
+	Pow::pow(BigInt::from(10u8), exponent as u64)
+}
+
+fn digit_count(n: &BigInt) -> usize {
+	n.magnitude().to_string().len()
+}
+
+fn add(a: &Decimal, b: &Decimal, digits: usize) -> Decimal {
+	match (a, b) {
+		(Decimal::Finite { mantissa: m1, exponent: e1 }, Decimal::Finite { mantissa: m2, exponent: e2 }) => {
+			// When one is way too small to matter, don't bother lining them up, since that could take forever with something like 1e1000000 + 1.
+			if a.magnitude() > b.magnitude() + digits as i64 + 2 {
+				return a.clone().round(digits);
+			}
+			if b.magnitude() > a.magnitude() + digits as i64 + 2 {
+				return b.clone().round(digits);
+			}
+			
+			let exponent = (*e1).min(*e2);
+			Decimal::new(m1 * pow10(e1 - exponent) + m2 * pow10(e2 - exponent), exponent).round(digits)
+		},
+		_ => Decimal::from_f64(a.float() + b.float()),
+	}
+}
+
+fn neg(a: &Decimal) -> Decimal {
+	match a {
+		Decimal::Finite { mantissa, exponent } => Decimal::Finite { mantissa: -mantissa, exponent: *exponent },
+		Decimal::NonFinite(x) => Decimal::NonFinite(-x),
+	}
+}
+
+fn sub(a: &Decimal, b: &Decimal, digits: usize) -> Decimal {
+	add(a, &neg(b), digits)
+}
+
+fn mul(a: &Decimal, b: &Decimal, digits: usize) -> Decimal {
+	match (a, b) {
+		(Decimal::Finite { mantissa: m1, exponent: e1 }, Decimal::Finite { mantissa: m2, exponent: e2 }) => Decimal::new(m1 * m2, e1 + e2).round(digits),
+		_ => Decimal::from_f64(a.float() * b.float()),
+	}
+}
+
+fn div(a: &Decimal, b: &Decimal, digits: usize) -> Decimal {
+	match (a, b) {
+		(Decimal::Finite { mantissa: m1, exponent: e1 }, Decimal::Finite { mantissa: m2, exponent: e2 }) if !m2.is_zero() => {
+			// Enough digits for the answer, plus a couple to round with.
+			let shift = (digits as i64 + 2 + digit_count(m2) as i64 - digit_count(m1) as i64).max(0);
+			let (quotient, remainder) = (m1 * pow10(shift)).div_rem(m2);
+			if remainder.is_zero() {
+				return Decimal::new(quotient, e1 - e2 - shift).round(digits);
+			}
+			
+			// There's more after the last digit, which matters if the rest looks like exactly half. Tacking on a 1 makes it not look like that.
+			let sign = if m1.is_negative() != m2.is_negative() { -1 } else { 1 };
+			Decimal::new(quotient * 10u8 + sign, e1 - e2 - shift - 1).round(digits)
+		},
+		_ => Decimal::from_f64(a.float() / b.float()),
+	}
+}
+
+/// Whether `term` is too small to change `sum` at `digits` significant digits.
+fn negligible(term: &Decimal, sum: &Decimal, digits: usize) -> bool {
+	term.is_zero() || term.magnitude() + (digits as i64) < sum.magnitude()
+}
+
+fn sqrt(x: &Decimal, digits: usize) -> Decimal {
+	match x {
+		Decimal::Finite { mantissa, exponent } if !mantissa.is_negative() => {
+			// Scale the mantissa up to at least twice as many digits as we want (keeping the exponent even), so its integer square root has enough.
+			let mut shift = (2 * digits as i64 - digit_count(mantissa) as i64 + 2).max(0);
+			if (exponent - shift).rem_euclid(2) != 0 {
+				shift += 1;
+			}
+			Decimal::new((mantissa * pow10(shift)).sqrt(), (exponent - shift) / 2).round(digits)
+		},
+		_ => Decimal::from_f64(x.float().sqrt()),
+	}
+}
+
+/// atan(1/n) = 1/n - 1/(3n^3) + 1/(5n^5) - ...
+fn atan_inverse(n: i64, digits: usize) -> Decimal {
+	let n = Decimal::integer(n);
+	let n_squared = mul(&n, &n, digits);
+	let mut power = div(&Decimal::integer(1), &n, digits);
+	let mut sum = power.clone();
+	
+	for k in 1.. {
+		power = div(&power, &n_squared, digits);
+		let term = div(&power, &Decimal::integer(2 * k + 1), digits);
+		if negligible(&term, &sum, digits) {
+			break;
+		}
+		sum = if k % 2 == 1 { sub(&sum, &term, digits) } else { add(&sum, &term, digits) };
+	}
+	sum
+}
+
+/// Machin's formula: π = 16 atan(1/5) - 4 atan(1/239)
+fn pi(digits: usize) -> Decimal {
+	let working = digits + 3;
+	let a = mul(&Decimal::integer(16), &atan_inverse(5, working), working);
+	let b = mul(&Decimal::integer(4), &atan_inverse(239, working), working);
+	sub(&a, &b, working).round(digits)
+}
+
+fn exp(x: &Decimal, digits: usize) -> Decimal {
+	if x.is_zero() {
+		return Decimal::integer(1);
+	}
+	if x.is_negative() {
+		return div(&Decimal::integer(1), &exp(&neg(x), digits + 2), digits);
+	}
+	// The exponent of the answer wouldn't even fit in an i64.
+	if x.float() > 1e18 {
+		return Decimal::NonFinite(f64::INFINITY);
+	}
+	
+	// Halve x until it's small, so the series is quick, then square the answer back up. Each squaring doubles the rounding error, so this needs a few more digits.
+	let halvings = ((x.magnitude() + 2).max(0) as f64 * core::f64::consts::LOG2_10).ceil() as u32;
+	let working = digits + halvings as usize / 3 + 3;
+	let x = div(x, &Decimal::integer(BigInt::one() << halvings), working);
+	
+	let mut sum = Decimal::integer(1);
+	let mut term = Decimal::integer(1);
+	for k in 1.. {
+		term = div(&mul(&term, &x, working), &Decimal::integer(k), working);
+		if negligible(&term, &sum, working) {
+			break;
+		}
+		sum = add(&sum, &term, working);
+	}
+	
+	for _ in 0..halvings {
+		sum = mul(&sum, &sum, working);
+	}
+	sum.round(digits)
+}
+
+/// The natural log of a positive number.
+fn ln(x: &Decimal, digits: usize) -> Decimal {
+	let (mantissa, exponent) = match x {
+		Decimal::Finite { mantissa, exponent } if mantissa.is_positive() => (mantissa, *exponent),
+		_ => return Decimal::from_f64(x.float().ln()),
+	};
+	
+	// ln(m * 10^k) = ln(m) + k ln(10), with m between 1 and 10.
+	let k = x.magnitude() - 1;
+	let working = digits + digit_count(&BigInt::from(k)) + 2;
+	let m = Decimal::new(mantissa.clone(), exponent - k);
+	let mut result = ln_near_one(&m, working);
+	if k != 0 {
+		let ln_10 = ln_near_one(&Decimal::integer(10), working);
+		result = add(&result, &mul(&Decimal::integer(k), &ln_10, working), working);
+	}
+	result.round(digits)
+}
+
+/// The natural log of something not too far from 1. (Between 0.1 and 10, say.)
+fn ln_near_one(x: &Decimal, digits: usize) -> Decimal {
+	let working = digits + 3;
+	let one = Decimal::integer(1);
+	
+	// ln(x) = 2^j ln(x^(1/2^j)), and square roots get x close enough to 1 for the series to be quick.
+	let mut x = x.clone();
+	let mut halvings: u32 = 0;
+	while sub(&x, &one, working).magnitude() > -1 {
+		x = sqrt(&x, working);
+		halvings += 1;
+	}
+	
+	// ln(x) = 2 atanh(z) = 2 (z + z^3/3 + z^5/5 + ...), with z = (x - 1) / (x + 1)
+	let z = div(&sub(&x, &one, working), &add(&x, &one, working), working);
+	let z_squared = mul(&z, &z, working);
+	let mut power = z.clone();
+	let mut sum = z;
+	for k in 1.. {
+		power = mul(&power, &z_squared, working);
+		let term = div(&power, &Decimal::integer(2 * k + 1), working);
+		if negligible(&term, &sum, working) {
+			break;
+		}
+		sum = add(&sum, &term, working);
+	}
+	
+	mul(&sum, &Decimal::integer(BigInt::one() << (halvings + 1)), working).round(digits)
+}
+
+/// Both at once, since tan and friends need both anyway.
+fn sin_cos(x: &Decimal, digits: usize) -> (Decimal, Decimal) {
+	// Get x between -π and π first. Big x needs more digits of π, since they get multiplied up.
+	let mut x = x.clone();
+	if x.magnitude() > 0 {
+		let extra = x.magnitude() as usize;
+		let working = digits + extra;
+		let two_pi = mul(&Decimal::integer(2), &pi(working), working);
+		let turns = div(&x, &two_pi, working);
+		let half = Decimal::new(BigInt::from(if turns.is_negative() { -5 } else { 5 }), -1);
+		let turns = Decimal::integer(add(&turns, &half, working).trunc());
+		x = sub(&x, &mul(&turns, &two_pi, working), working).round(digits);
+	}
+	
+	// sin(x) = x - x^3/3! + x^5/5! - ..., and cos(x) = 1 - x^2/2! + x^4/4! - ...
+	// Both of them are at most 1, so terms smaller than the last digit of 1 don't matter.
+	let one = Decimal::integer(1);
+	let x_squared = mul(&x, &x, digits);
+	let (mut sin, mut cos) = (x.clone(), one.clone());
+	let (mut sin_term, mut cos_term) = (x, one.clone());
+	for k in 1.. {
+		cos_term = neg(&div(&mul(&cos_term, &x_squared, digits), &Decimal::integer((2 * k - 1) * (2 * k)), digits));
+		sin_term = neg(&div(&mul(&sin_term, &x_squared, digits), &Decimal::integer((2 * k) * (2 * k + 1)), digits));
+		if negligible(&cos_term, &one, digits) && negligible(&sin_term, &sin, digits) {
+			break;
+		}
+		sin = add(&sin, &sin_term, digits);
+		cos = add(&cos, &cos_term, digits);
+	}
+	(sin, cos)
+}
+
+fn atan(x: &Decimal, digits: usize) -> Decimal {
+	let one = Decimal::integer(1);
+	if x.is_zero() {
+		return x.clone();
+	}
+	if x.is_negative() {
+		return neg(&atan(&neg(x), digits));
+	}
+	if x > &one {
+		// atan(x) = π/2 - atan(1/x)
+		let half_pi = div(&pi(digits + 2), &Decimal::integer(2), digits + 2);
+		return sub(&half_pi, &atan(&div(&one, x, digits + 2), digits + 2), digits);
+	}
+	
+	// atan(x) = 2 atan(x / (1 + sqrt(1 + x^2))), which gets x small enough for the series to be quick.
+	let working = digits + 3;
+	let mut x = x.clone();
+	let mut halvings: u32 = 0;
+	while x.magnitude() > -1 {
+		let root = sqrt(&add(&one, &mul(&x, &x, working), working), working);
+		x = div(&x, &add(&one, &root, working), working);
+		halvings += 1;
+	}
+	
+	// atan(x) = x - x^3/3 + x^5/5 - ...
+	let x_squared = mul(&x, &x, working);
+	let mut power = x.clone();
+	let mut sum = x;
+	for k in 1.. {
+		power = mul(&power, &x_squared, working);
+		let term = div(&power, &Decimal::integer(2 * k + 1), working);
+		if negligible(&term, &sum, working) {
+			break;
+		}
+		sum = if k % 2 == 1 { sub(&sum, &term, working) } else { add(&sum, &term, working) };
+	}
+	
+	mul(&sum, &Decimal::integer(BigInt::one() << halvings), working).round(digits)
+}
+
+fn atan2(y: &Decimal, x: &Decimal, digits: usize) -> Decimal {
+	let working = digits + 2;
+	if x.is_zero() {
+		let half_pi = div(&pi(working), &Decimal::integer(2), digits);
+		return if y.is_zero() { Decimal::integer(0) } else if y.is_negative() { neg(&half_pi) } else { half_pi };
+	}
+	
+	let angle = atan(&div(y, x, working), working);
+	if !x.is_negative() {
+		angle.round(digits)
+	} else if y.is_negative() {
+		sub(&angle, &pi(working), digits)
+	} else {
+		add(&angle, &pi(working), digits)
+	}
+}
+
+/// `x^n` for a whole number `n`, by repeated squaring.
+fn pow_int(x: &Decimal, n: &BigInt, digits: usize) -> Decimal {
+	let working = digits + digit_count(n) + 2;
+	let mut result = Decimal::integer(1);
+	let mut base = x.clone();
+	let mut exponent = n.magnitude().clone();
+	while !exponent.is_zero() {
+		if exponent.is_odd() {
+			result = mul(&result, &base, working);
+		}
+		exponent >>= 1;
+		if !exponent.is_zero() {
+			base = mul(&base, &base, working);
+		}
+	}
+	
+	if n.is_negative() {
+		div(&Decimal::integer(1), &result, digits)
+	} else {
+		result.round(digits)
+	}
+}
+
+fn pow(x: &Decimal, y: &Decimal, digits: usize) -> Decimal {
+	if !matches!((x, y), (Decimal::Finite { .. }, Decimal::Finite { .. })) {
+		return Decimal::from_f64(x.float().powf(y.float()));
+	}
+	if y.is_integer() {
+		return pow_int(x, &y.trunc(), digits);
+	}
+	if x.is_zero() {
+		return if y.is_negative() { Decimal::NonFinite(f64::INFINITY) } else { x.clone() };
+	}
+	if x.is_negative() {
+		return Decimal::NonFinite(f64::NAN);
+	}
+	
+	// x^y = e^(y ln(x)). The error in y ln(x) gets multiplied by how big it is, so that needs more digits.
+	let size = (y.float() * x.float().ln()).abs().log10().clamp(0.0, 1e6) as usize;
+	let working = digits + GUARD_DIGITS + size;
+	exp(&mul(y, &ln(x, working), working), working).round(digits)
+}
+
+/// The `n`th root of `x`. Negative numbers have odd roots, same as in real mode.
+fn root(x: &Decimal, n: &Decimal, digits: usize) -> Decimal {
+	if n == &Decimal::integer(2) {
+		return sqrt(x, digits);
+	}
+	if n.is_integer() && !n.is_zero() && x.is_negative() {
+		return if n.trunc().is_odd() { neg(&root(&neg(x), n, digits)) } else { Decimal::NonFinite(f64::NAN) };
+	}
+	if n.is_integer() && !n.is_zero() && !x.is_zero() {
+		let working = digits + GUARD_DIGITS;
+		return exp(&div(&ln(x, working), n, working), working).round(digits);
+	}
+	pow(x, &div(&Decimal::integer(1), n, digits + GUARD_DIGITS), digits)
+}
+
+impl PartialOrd for Decimal {
+	fn partial_cmp(&self, other: &Decimal) -> Option<Ordering> {
+		let (m1, e1, m2, e2) = match (self, other) {
+			(Decimal::Finite { mantissa: m1, exponent: e1 }, Decimal::Finite { mantissa: m2, exponent: e2 }) => (m1, *e1, m2, *e2),
+			_ => return self.float().partial_cmp(&other.float()),
+		};
+		
+		if m1.sign() != m2.sign() || m1.is_zero() {
+			return Some(m1.sign().cmp(&m2.sign()));
+		}
+		let by_size = match self.magnitude().cmp(&other.magnitude()) {
+			// Same magnitude means the exponents can't be too far apart, so lining them up is fine.
+			Ordering::Equal => {
+				let exponent = e1.min(e2);
+				(m1 * pow10(e1 - exponent)).abs().cmp(&(m2 * pow10(e2 - exponent)).abs())
+			},
+			other => other,
+		};
+		Some(if m1.is_negative() { by_size.reverse() } else { by_size })
+	}
+}
+
+impl Number for Decimal {
+	/// Reads the shortest decimal that rounds to `value`, so `0.1` is exactly 0.1 and not the 0.1000000000000000055511... that it really is.
+	fn from_f64(value: f64) -> Decimal {
+		if !value.is_finite() {
+			return Decimal::NonFinite(value);
+		}
+		
+		let written = format!("{:e}", value);
+		let (digits, exponent) = written.split_once('e').unwrap();
+		let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+		let mantissa: BigInt = format!("{}{}", whole, fraction).parse().unwrap();
+		Decimal::new(mantissa, exponent.parse::<i64>().unwrap() - fraction.len() as i64)
+	}
+	
+	fn from_integer(value: &BigInt, _settings: &Settings) -> Result<Decimal, CalcError> {
+		Ok(Decimal::integer(value.clone()))
+	}
+	
+	fn to_f64(&self) -> Option<f64> {
+		Some(self.float())
+	}
+	
+	fn operate(operator: Operator, args: &[Decimal], settings: &Settings) -> Result<Decimal, CalcError> {
+		let digits = settings.precision;
+		match operator {
+			Operator::Add => Ok(add(&args[0], &args[1], digits)),
+			Operator::Sub => Ok(sub(&args[0], &args[1], digits)),
+			Operator::Mul => Ok(mul(&args[0], &args[1], digits)),
+			Operator::Div => Ok(div(&args[0], &args[1], digits)),
+			Operator::Mod => match (&args[0], &args[1]) {
+				(Decimal::Finite { mantissa: m1, exponent: e1 }, Decimal::Finite { mantissa: m2, exponent: e2 }) if !m2.is_zero() => {
+					// Same as f64's %, which is x - y * trunc(x / y). Lined up like this, it's exact.
+					let exponent = (*e1).min(*e2);
+					let (a, b) = (m1 * pow10(e1 - exponent), m2 * pow10(e2 - exponent));
+					let quotient = &a / &b;
+					Ok(Decimal::new(a - quotient * b, exponent).round(digits))
+				},
+				(x, y) => Ok(Decimal::from_f64(x.float() % y.float())),
+			},
+			Operator::Pow => Ok(pow(&args[0], &args[1], digits)),
+			Operator::Rot => Ok(root(&args[1], &args[0], digits)),
+			Operator::Unp => Ok(args[0].clone().round(digits)),
+			Operator::Unm => Ok(neg(&args[0]).round(digits)),
+			// `number::operate_in` turns angles into the right unit itself, and never passes them along.
+			Operator::Angle(_) => unreachable!("angles are handled by operate_in"),
+			Operator::BitAnd | Operator::BitOr | Operator::Xor | Operator::BitNot | Operator::Shl | Operator::Shr => {
+				if !args.iter().all(Decimal::is_integer) {
+					return Err(CalcError::Domain { message: "Bitwise operators only work on whole numbers.", span: None });
+				}
+				let integers: Vec<BigInt> = args.iter().map(Decimal::trunc).collect();
+				Ok(Decimal::new(bitwise(operator, &integers)?, 0).round(digits))
+			},
+			Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge | Operator::Eq | Operator::Ne |
+			Operator::And | Operator::Or | Operator::Not => Ok(logic(operator, args)),
+			Operator::To => Err(CalcError::Domain { message: "Converting units needs units mode.", span: None }),
+		}
+	}
+	
+	fn call(function: Function, args: &[Decimal], settings: &Settings) -> Result<Decimal, CalcError> {
+		// This one just picks a side, so a NaN on the other side shouldn't turn everything into floats.
+		if matches!(function, Function::If | Function::IfThen) {
+			return choose(args);
+		}
+		// Infinity and NaN don't have digits to be precise about, so floats can handle those.
+		if args.iter().any(|a| matches!(a, Decimal::NonFinite(_))) {
+			let floats: Vec<f64> = args.iter().map(Decimal::float).collect();
+			return Ok(Decimal::from_f64(function.calculate(&floats)?));
+		}
+		
+		let digits = settings.precision;
+		let working = digits + GUARD_DIGITS;
+		let one = Decimal::integer(1);
+		let positive = |x: &Decimal| !x.is_negative() && !x.is_zero();
+		// How many zeros there are between the decimal point and the first digit, like 3 for 0.0001.
+		let leading_zeros = |x: &Decimal| if x.is_zero() { 0 } else { (-x.magnitude()).max(0) as usize };
+		
+		match function {
+			Function::Sin => Ok(sin_cos(&args[0], working).0.round(digits)),
+			Function::Cos => Ok(sin_cos(&args[0], working).1.round(digits)),
+			Function::Tan => {
+				let (sin, cos) = sin_cos(&args[0], working);
+				Ok(div(&sin, &cos, digits))
+			},
+			Function::Csc => Ok(div(&one, &sin_cos(&args[0], working).0, digits)),
+			Function::Sec => Ok(div(&one, &sin_cos(&args[0], working).1, digits)),
+			Function::Cot => {
+				let (sin, cos) = sin_cos(&args[0], working);
+				Ok(div(&cos, &sin, digits))
+			},
+			// asin(x) = atan2(x, sqrt(1 - x^2)), and the rest are like it.
+			Function::Asin | Function::Acos if args[0] >= neg(&one) && args[0] <= one => {
+				let other = sqrt(&sub(&one, &mul(&args[0], &args[0], working), working), working);
+				Ok(if function == Function::Asin { atan2(&args[0], &other, digits) } else { atan2(&other, &args[0], digits) })
+			},
+			Function::Acsc | Function::Asec if !args[0].is_zero() => {
+				let inverse = if function == Function::Acsc { Function::Asin } else { Function::Acos };
+				Decimal::call(inverse, &[div(&one, &args[0], working)], settings)
+			},
+			Function::Atan => Ok(atan(&args[0], digits)),
+			Function::Acot => Ok(sub(&div(&pi(working), &Decimal::integer(2), working), &atan(&args[0], working), digits)),
+			Function::Sinh | Function::Cosh | Function::Tanh => {
+				// e^x - e^-x loses about as many digits as x has zeros after the decimal point, so those need to be there to lose.
+				let working = working + leading_zeros(&args[0]);
+				let (a, b) = (exp(&args[0], working), exp(&neg(&args[0]), working));
+				let (sinh, cosh) = (sub(&a, &b, working), add(&a, &b, working));
+				Ok(match function {
+					Function::Sinh => div(&sinh, &Decimal::integer(2), digits),
+					Function::Cosh => div(&cosh, &Decimal::integer(2), digits),
+					_ => div(&sinh, &cosh, digits),
+				})
+			},
+			Function::Asinh if args[0].is_negative() => Ok(neg(&Decimal::call(Function::Asinh, &[neg(&args[0])], settings)?)),
+			Function::Asinh => {
+				// ln(x + sqrt(x^2 + 1)), which is ln of something close to 1 when x is small, so that needs more digits too.
+				let working = working + leading_zeros(&args[0]);
+				let root = sqrt(&add(&mul(&args[0], &args[0], working), &one, working), working);
+				Ok(ln(&add(&args[0], &root, working), working).round(digits))
+			},
+			Function::Acosh if args[0] >= one => {
+				let root = sqrt(&sub(&mul(&args[0], &args[0], working), &one, working), working);
+				Ok(ln(&add(&args[0], &root, working), digits))
+			},
+			Function::Atanh if args[0] > neg(&one) && args[0] < one => {
+				// (ln(1 + x) - ln(1 - x)) / 2
+				let working = working + leading_zeros(&args[0]);
+				let difference = sub(&ln(&add(&one, &args[0], working), working), &ln(&sub(&one, &args[0], working), working), working);
+				Ok(div(&difference, &Decimal::integer(2), digits))
+			},
+			Function::Sqrt if !args[0].is_negative() => Ok(sqrt(&args[0], digits)),
+			Function::Cbrt => Ok(root(&args[0], &Decimal::integer(3), digits)),
+			Function::Exp => Ok(exp(&args[0], digits)),
+			Function::Log if positive(&args[0]) => Ok(div(&ln(&args[0], working), &ln(&Decimal::integer(10), working), digits)),
+			Function::Ln if positive(&args[0]) => Ok(ln(&args[0], digits)),
+			Function::LogBase if positive(&args[0]) && positive(&args[1]) => Ok(div(&ln(&args[0], working), &ln(&args[1], working), digits)),
+			Function::Abs => Ok(if args[0].is_negative() { neg(&args[0]) } else { args[0].clone() }.round(digits)),
+			Function::Sgn => Ok(Decimal::integer(if args[0].is_negative() { -1 } else { 1 })),
+			Function::Pi => Ok(pi(digits)),
+			Function::E => Ok(exp(&one, digits)),
+			Function::Min => Ok(args.iter().cloned().reduce(|a, b| if b < a { b } else { a }).unwrap().round(digits)),
+			Function::Max => Ok(args.iter().cloned().reduce(|a, b| if b > a { b } else { a }).unwrap().round(digits)),
+			Function::Clamp => {
+				let max = if args[1] > args[0] { &args[1] } else { &args[0] };
+				Ok(if args[2] < *max { args[2].clone() } else { max.clone() }.round(digits))
+			},
+			Function::Sum => Ok(args.iter().fold(Decimal::integer(0), |a, b| add(&a, b, working)).round(digits)),
+			Function::Mean => {
+				let sum = args.iter().fold(Decimal::integer(0), |a, b| add(&a, b, working));
+				Ok(div(&sum, &Decimal::integer(args.len()), digits))
+			},
+			Function::Hypot => {
+				let squares = args.iter().fold(Decimal::integer(0), |a, b| add(&a, &mul(b, b, working), working));
+				Ok(sqrt(&squares, digits))
+			},
+			Function::Gcd | Function::Lcm if args.iter().all(Decimal::is_integer) => {
+				let integers = args.iter().map(Decimal::trunc);
+				Ok(Decimal::integer(if function == Function::Gcd {
+					integers.fold(BigInt::zero(), |a, b| a.gcd(&b))
+				} else {
+					integers.fold(BigInt::one(), |a, b| a.lcm(&b))
+				}))
+			},
+			Function::Atan2 => Ok(atan2(&args[0], &args[1], digits)),
+			Function::Root => Ok(root(&args[0], &args[1], digits)),
+			Function::Re | Function::Conj | Function::Det | Function::Transpose => Ok(args[0].clone().round(digits)),
+			Function::Norm => Ok(if args[0].is_negative() { neg(&args[0]) } else { args[0].clone() }.round(digits)),
+			Function::Inv => Ok(div(&one, &args[0], digits)),
+			Function::Dot => Ok(mul(&args[0], &args[1], digits)),
+			Function::Im => Ok(Decimal::integer(0)),
+			Function::Arg => Ok(if args[0].is_negative() { pi(digits) } else { Decimal::integer(0) }),
+			// Logs of things that aren't positive, gcd of things that aren't integers, and anything complex.
+			_ => {
+				let floats: Vec<f64> = args.iter().map(Decimal::float).collect();
+				Ok(Decimal::from_f64(function.calculate(&floats)?))
+			},
+		}
+	}
+	
+	/// Keeps the guard digits, so `sin(30°)` is exactly 0.5 instead of being off in the last digit.
+	fn convert_angle(self, from: AngleUnit, to: AngleUnit, settings: &Settings) -> Result<Decimal, CalcError> {
+		if from == to || matches!(self, Decimal::NonFinite(_)) {
+			return Ok(self);
+		}
+		
+		let working = settings.precision + GUARD_DIGITS;
+		let full_turn = |unit: AngleUnit| match unit {
+			AngleUnit::Radians => mul(&Decimal::integer(2), &pi(working), working),
+			_ => Decimal::from_f64(unit.full_turn()),
+		};
+		Ok(div(&mul(&self, &full_turn(to), working), &full_turn(from), working))
+	}
+}
+
+impl fmt::Display for Decimal {
+	/// Writes every significant digit. Really big or really small numbers get an exponent, like `1.5e-30`. (Big means it'd need zeros past the last digit, and more than 21 digits in all.)
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let (mantissa, exponent) = match self {
+			Decimal::Finite { mantissa, exponent } => (mantissa, *exponent),
+			Decimal::NonFinite(x) => return write!(f, "{}", x),
+		};
+		if mantissa.is_zero() {
+			return write!(f, "0");
+		}
+		
+		let sign = if mantissa.is_negative() { "-" } else { "" };
+		let digits = mantissa.magnitude().to_string();
+		let point = self.magnitude();
+		let widest = digits.len().max(21) as i64;
+		
+		if exponent >= 0 && point <= widest {
+			write!(f, "{}{}{}", sign, digits, "0".repeat(exponent as usize))
+		} else if exponent < 0 && point > 0 {
+			write!(f, "{}{}.{}", sign, &digits[..point as usize], &digits[point as usize..])
+		} else if exponent < 0 && point > -10 {
+			write!(f, "{}0.{}{}", sign, "0".repeat(-point as usize), digits)
+		} else if digits.len() == 1 {
+			write!(f, "{}{}e{}", sign, digits, point - 1)
+		} else {
+			write!(f, "{}{}.{}e{}", sign, &digits[..1], &digits[1..], point - 1)
+		}
+	}
+}
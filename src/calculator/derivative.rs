@@ -0,0 +1,367 @@
+use super::error::CalcError;
+use super::environment::Environment;
+use super::expression::{Expression, Token};
+use super::operator::{Operator, Function, AngleUnit};
+
+/// One value on the stack while differentiating.
+/// 
+/// Both of these are RPN. `derivative` is `None` when it's zero everywhere, so we don't end up with piles of `0 * stuff`.
+#[derive(Clone)]
+struct Term {
+	value: Vec<Token>,
+	derivative: Option<Vec<Token>>,
+}
+
+fn constant(c: f64) -> Vec<Token> {
+	vec![Token::Constant(c)]
+}
+
+fn op(mut a: Vec<Token>, b: Vec<Token>, o: Operator) -> Vec<Token> {
+	a.extend(b);
+	a.push(Token::Operator(o));
+	a
+}
+
+fn neg(mut a: Vec<Token>) -> Vec<Token> {
+	a.push(Token::Operator(Operator::Unm));
+	a
+}
+
+fn func(args: Vec<Vec<Token>>, f: Function) -> Vec<Token> {
+	let count = args.len();
+	let mut result: Vec<Token> = args.into_iter().flatten().collect();
+	if f.is_variadic() {
+		result.push(Token::ArgCount(count));
+	}
+	result.push(Token::Function(f));
+	result
+}
+
+fn add(a: Option<Vec<Token>>, b: Option<Vec<Token>>) -> Option<Vec<Token>> {
+	match (a, b) {
+		(Some(a), Some(b)) => Some(op(a, b, Operator::Add)),
+		(a, None) => a,
+		(None, b) => b,
+	}
+}
+
+fn sub(a: Option<Vec<Token>>, b: Option<Vec<Token>>) -> Option<Vec<Token>> {
+	match (a, b) {
+		(Some(a), Some(b)) => Some(op(a, b, Operator::Sub)),
+		(a, None) => a,
+		(None, b) => b.map(neg),
+	}
+}
+
+/// `d * factor`, or nothing if `d` is nothing.
+fn times(d: Option<Vec<Token>>, factor: Vec<Token>) -> Option<Vec<Token>> {
+	d.map(|d| op(d, factor, Operator::Mul))
+}
+
+/// (uv)' = u'v + uv'
+fn product(u: &Term, v: &Term) -> Option<Vec<Token>> {
+	add(times(u.derivative.clone(), v.value.clone()), times(v.derivative.clone(), u.value.clone()))
+}
+
+/// (u/v)' = (u'v - uv') / v^2
+fn quotient(u: &Term, v: &Term) -> Option<Vec<Token>> {
+	sub(times(u.derivative.clone(), v.value.clone()), times(v.derivative.clone(), u.value.clone()))
+		.map(|n| op(n, op(v.value.clone(), constant(2.0), Operator::Pow), Operator::Div))
+}
+
+/// (u^v)'. If the exponent doesn't change, this is just the power rule, which is good, since the general version needs ln(u) and falls apart when u is negative.
+fn power(u: &Term, v: &Term) -> Option<Vec<Token>> {
+	if v.derivative.is_none() {
+		// v * u^(v - 1) * u'
+		let factor = op(v.value.clone(), op(u.value.clone(), op(v.value.clone(), constant(1.0), Operator::Sub), Operator::Pow), Operator::Mul);
+		times(u.derivative.clone(), factor)
+	} else {
+		// u^v * (v' ln(u) + v u' / u)
+		let inner = add(
+			times(v.derivative.clone(), func(vec![u.value.clone()], Function::Ln)),
+			times(u.derivative.clone(), op(v.value.clone(), u.value.clone(), Operator::Div)),
+		);
+		times(inner, op(u.value.clone(), v.value.clone(), Operator::Pow))
+	}
+}
+
+/// x^(1/n), which is how both `√` and `root` work.
+fn nth_root(x: &Term, n: &Term) -> Option<Vec<Token>> {
+	let reciprocal = Term {
+		value: op(constant(1.0), n.value.clone(), Operator::Div),
+		derivative: n.derivative.clone().map(|d| neg(op(d, op(n.value.clone(), constant(2.0), Operator::Pow), Operator::Div))),
+	};
+	power(x, &reciprocal)
+}
+
+/// max(a, b) = (a + b + |a - b|) / 2, and min(a, b) = (a + b - |a - b|) / 2, so the derivatives fall out of that.
+fn max_min(a: Term, b: Term, is_max: bool) -> Term {
+	let sign = func(vec![op(a.value.clone(), b.value.clone(), Operator::Sub)], Function::Sgn);
+	let half_sum = times(add(a.derivative.clone(), b.derivative.clone()), constant(0.5));
+	let half_diff = times(times(sub(a.derivative.clone(), b.derivative.clone()), sign), constant(0.5));
+	
+	Term {
+		value: func(vec![a.value, b.value], if is_max { Function::Max } else { Function::Min }),
+		derivative: if is_max { add(half_sum, half_diff) } else { sub(half_sum, half_diff) },
+	}
+}
+
+impl Expression {
+	/// Makes a new `Expression` that's the derivative of this one with respect to `var`.
+	/// 
+	/// Things that aren't smooth get the derivative they have almost everywhere, so `abs(x)` gives `sgn(x)` and `sgn(x)` gives `0`.
+	/// The result is already simplified.
+	/// 
+	/// Angles are in radians here. Use `derivative_in` if they aren't.
+	pub fn derivative(&self, var: &str) -> Result<Expression, CalcError> {
+		self.derivative_in(var, AngleUnit::Radians)
+	}
+	
+	/// `derivative`, but with angles in `angle_unit`. The slope of `sin(x)` is a lot smaller when x is in degrees.
+	pub fn derivative_in(&self, var: &str, angle_unit: AngleUnit) -> Result<Expression, CalcError> {
+		let mut stack: Vec<Term> = Vec::new();
+		let mut arg_count: Option<usize> = None;
+		
+		// Simplifying first gets rid of any `if` that already knows which side it picks, so the other side doesn't need a derivative.
+		// If that goes wrong, calculating it would too, which isn't a problem for here.
+		let mut simplified = self.clone();
+		if simplified.simplify_in(angle_unit).is_err() {
+			simplified = self.clone();
+		}
+		
+		for token in simplified.tokens().iter() {
+			let (arg_length, name) = match token {
+				Token::Constant(_) | Token::Integer(_) => {
+					stack.push(Term { value: vec![token.clone()], derivative: None });
+					continue;
+				},
+				Token::Variable(v) => {
+					let derivative = if v == var { Some(constant(1.0)) } else { None };
+					stack.push(Term { value: vec![token.clone()], derivative });
+					continue;
+				},
+				Token::ArgCount(n) => {
+					arg_count = Some(*n);
+					continue;
+				},
+				Token::Operator(o) => (o.get_parameters(), o.symbol()),
+				Token::Function(f) if f.is_variadic() => match arg_count.take() {
+					Some(n) => (n, f.name()),
+					None => return Err(CalcError::Parse { message: "Variadic function is missing its argument count.", span: None }),
+				},
+				Token::Function(f) => (f.get_parameters(), f.name()),
+				Token::UserFunction(name) => return Err(CalcError::UserFunction { name: name.clone(), message: "Can't differentiate", span: None }),
+				Token::Matrix { .. } | Token::Index(_) => return Err(CalcError::Domain { message: "Matrices can't be differentiated.", span: None }),
+				_ => continue,
+			};
+			
+			if stack.len() < arg_length {
+				return Err(CalcError::Arity { name, expected: arg_length, found: stack.len(), span: None });
+			}
+			let args = stack.split_off(stack.len() - arg_length);
+			
+			let derivative = match token {
+				Token::Operator(o) => match o {
+					Operator::Add => add(args[0].derivative.clone(), args[1].derivative.clone()),
+					Operator::Sub => sub(args[0].derivative.clone(), args[1].derivative.clone()),
+					Operator::Mul => product(&args[0], &args[1]),
+					Operator::Div => quotient(&args[0], &args[1]),
+					Operator::Mod => {
+						// u % v = u - v * trunc(u / v), and trunc(u / v) is flat.
+						let (u, v) = (&args[0], &args[1]);
+						let trunc = op(op(u.value.clone(), op(u.value.clone(), v.value.clone(), Operator::Mod), Operator::Sub), v.value.clone(), Operator::Div);
+						sub(u.derivative.clone(), times(v.derivative.clone(), trunc))
+					},
+					Operator::Pow => power(&args[0], &args[1]),
+					Operator::Rot => nth_root(&args[1], &args[0]),
+					Operator::Unp => args[0].derivative.clone(),
+					Operator::Unm => args[0].derivative.clone().map(neg),
+					Operator::Angle(unit) => times(args[0].derivative.clone(), constant(unit.factor(angle_unit))),
+					Operator::BitAnd | Operator::BitOr | Operator::Xor | Operator::BitNot |
+					Operator::Shl | Operator::Shr => {
+						if args.iter().any(|a| a.derivative.is_some()) {
+							return Err(CalcError::Domain { message: "Bitwise operators can't be differentiated.", span: None });
+						}
+						None
+					},
+					// These only ever give 0 or 1, so they're flat everywhere except where they jump.
+					Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge | Operator::Eq | Operator::Ne |
+					Operator::And | Operator::Or | Operator::Not => None,
+					Operator::To => return Err(CalcError::Domain { message: "Converting units needs units mode.", span: None }),
+				},
+				Token::Function(f) => {
+					let u = || args[0].value.clone();
+					// Everything below is in radians, so the chain rule picks up a constant when angles aren't.
+					let du = || if f.takes_angle() {
+						times(args[0].derivative.clone(), constant(angle_unit.factor(AngleUnit::Radians)))
+					} else {
+						args[0].derivative.clone()
+					};
+					
+					match f {
+						Function::Sin => times(du(), func(vec![u()], Function::Cos)),
+						Function::Cos => times(du(), neg(func(vec![u()], Function::Sin))),
+						Function::Tan => du().map(|d| op(d, op(func(vec![u()], Function::Cos), constant(2.0), Operator::Pow), Operator::Div)),
+						Function::Csc => times(du(), neg(op(func(vec![u()], Function::Csc), func(vec![u()], Function::Cot), Operator::Mul))),
+						Function::Sec => times(du(), op(func(vec![u()], Function::Sec), func(vec![u()], Function::Tan), Operator::Mul)),
+						Function::Cot => times(du(), neg(op(func(vec![u()], Function::Csc), constant(2.0), Operator::Pow))),
+						// 1 / sqrt(1 - u^2), and friends.
+						Function::Asin | Function::Acos => {
+							let d = du().map(|d| op(d, func(vec![op(constant(1.0), op(u(), constant(2.0), Operator::Pow), Operator::Sub)], Function::Sqrt), Operator::Div));
+							if *f == Function::Asin { d } else { d.map(neg) }
+						},
+						Function::Atan | Function::Acot => {
+							let d = du().map(|d| op(d, op(constant(1.0), op(u(), constant(2.0), Operator::Pow), Operator::Add), Operator::Div));
+							if *f == Function::Atan { d } else { d.map(neg) }
+						},
+						Function::Asec | Function::Acsc => {
+							// 1 / (|u| sqrt(u^2 - 1))
+							let bottom = op(func(vec![u()], Function::Abs), func(vec![op(op(u(), constant(2.0), Operator::Pow), constant(1.0), Operator::Sub)], Function::Sqrt), Operator::Mul);
+							let d = du().map(|d| op(d, bottom, Operator::Div));
+							if *f == Function::Asec { d } else { d.map(neg) }
+						},
+						Function::Sinh => times(du(), func(vec![u()], Function::Cosh)),
+						Function::Cosh => times(du(), func(vec![u()], Function::Sinh)),
+						Function::Tanh => du().map(|d| op(d, op(func(vec![u()], Function::Cosh), constant(2.0), Operator::Pow), Operator::Div)),
+						Function::Asinh => du().map(|d| op(d, func(vec![op(op(u(), constant(2.0), Operator::Pow), constant(1.0), Operator::Add)], Function::Sqrt), Operator::Div)),
+						Function::Acosh => du().map(|d| op(d, func(vec![op(op(u(), constant(2.0), Operator::Pow), constant(1.0), Operator::Sub)], Function::Sqrt), Operator::Div)),
+						Function::Atanh => du().map(|d| op(d, op(constant(1.0), op(u(), constant(2.0), Operator::Pow), Operator::Sub), Operator::Div)),
+						Function::Sqrt | Function::Chol => du().map(|d| op(d, op(constant(2.0), func(vec![u()], Function::Sqrt), Operator::Mul), Operator::Div)),
+						Function::Cbrt => du().map(|d| op(d, op(constant(3.0), op(func(vec![u()], Function::Cbrt), constant(2.0), Operator::Pow), Operator::Mul), Operator::Div)),
+						Function::Exp => times(du(), func(vec![u()], Function::Exp)),
+						Function::Log => du().map(|d| op(d, op(u(), constant(core::f64::consts::LN_10), Operator::Mul), Operator::Div)),
+						Function::Ln  => du().map(|d| op(d, u(), Operator::Div)),
+						Function::LogBase => {
+							// log(x, b) = ln(x) / ln(b)
+							let ln = |t: &Term| Term {
+								value: func(vec![t.value.clone()], Function::Ln),
+								derivative: t.derivative.clone().map(|d| op(d, t.value.clone(), Operator::Div)),
+							};
+							quotient(&ln(&args[0]), &ln(&args[1]))
+						},
+						Function::Abs | Function::Norm | Function::QrR => times(du(), func(vec![u()], Function::Sgn)),
+						// These are all just the number itself (or its reciprocal) when it's not a matrix.
+						Function::Det | Function::Transpose => du(),
+						Function::Inv => quotient(&Term { value: constant(1.0), derivative: None }, &args[0]),
+						Function::Dot => product(&args[0], &args[1]),
+						Function::Solve | Function::Lstsq => quotient(&args[1], &args[0]),
+						Function::LuU | Function::Eig => du(),
+						Function::LuL | Function::LuP | Function::QrQ | Function::Rank | Function::Eigvec => None,
+						Function::Cross => return Err(CalcError::Domain { message: "cross needs two 3D vectors, in matrix mode.", span: None }),
+						Function::Sgn | Function::Pi | Function::E => None,
+						Function::Atan2 => {
+							// (x y' - y x') / (x^2 + y^2)
+							let (y, x) = (&args[0], &args[1]);
+							sub(times(y.derivative.clone(), x.value.clone()), times(x.derivative.clone(), y.value.clone()))
+								.map(|n| op(n, op(
+									op(x.value.clone(), constant(2.0), Operator::Pow),
+									op(y.value.clone(), constant(2.0), Operator::Pow),
+									Operator::Add,
+								), Operator::Div))
+						},
+						Function::Root => nth_root(&args[0], &args[1]),
+						Function::Min | Function::Max => {
+							let mut args = args.iter().cloned();
+							let first = args.next().unwrap();
+							args.fold(first, |a, b| max_min(a, b, *f == Function::Max)).derivative
+						},
+						Function::Clamp => {
+							// clamp(x, lo, hi) = min(max(x, lo), hi)
+							let inner = max_min(args[0].clone(), args[1].clone(), true);
+							max_min(inner, args[2].clone(), false).derivative
+						},
+						Function::Sum => args.iter().fold(None, |d, a| add(d, a.derivative.clone())),
+						Function::Mean => args.iter().fold(None, |d, a| add(d, a.derivative.clone()))
+							.map(|d| op(d, constant(args.len() as f64), Operator::Div)),
+						Function::Hypot => {
+							// (a a' + b b' + ...) / hypot(a, b, ...)
+							let hypot = func(args.iter().map(|a| a.value.clone()).collect(), Function::Hypot);
+							args.iter().fold(None, |d, a| add(d, times(a.derivative.clone(), a.value.clone())))
+								.map(|d| op(d, hypot, Operator::Div))
+						},
+						Function::Gcd | Function::Lcm => {
+							if args.iter().any(|a| a.derivative.is_some()) {
+								return Err(CalcError::Domain { message: "gcd and lcm can't be differentiated.", span: None });
+							}
+							None
+						},
+						// Derivatives are only done with real numbers, where re and conj don't change anything, and im and arg are flat.
+						Function::Re | Function::Conj => du(),
+						Function::Im | Function::Arg => None,
+						Function::Cis => return Err(CalcError::Domain { message: "cis needs complex mode.", span: None }),
+						// Each piece gets its own derivative, and the condition picks which one, same as it picks the value.
+						// A missing piece (past the end of `{x < 0: -x}`) stays missing, so the slope has a gap in the same place.
+						Function::If => {
+							let piece = |t: &Term| t.derivative.clone().unwrap_or_else(|| match t.value.as_slice() {
+								[Token::Constant(c)] if c.is_nan() => constant(f64::NAN),
+								_ => constant(0.0),
+							});
+							if args[1].derivative.is_none() && args[2].derivative.is_none() {
+								None
+							} else {
+								Some(func(vec![args[0].value.clone(), piece(&args[1]), piece(&args[2])], Function::If))
+							}
+						},
+						Function::IfThen => Some(func(vec![args[0].value.clone(), args[1].derivative.clone().unwrap_or_else(|| constant(0.0))], Function::IfThen)),
+					}
+				},
+				_ => unreachable!(),
+			};
+			// Same as `du` above, but for functions that give back angles instead of taking them.
+			let derivative = match token {
+				Token::Function(f) if f.gives_angle() => times(derivative, constant(AngleUnit::Radians.factor(angle_unit))),
+				_ => derivative,
+			};
+			
+			let value = match token {
+				Token::Function(f) => func(args.into_iter().map(|a| a.value).collect(), *f),
+				_ => {
+					let mut value: Vec<Token> = args.into_iter().flat_map(|a| a.value).collect();
+					value.push(token.clone());
+					value
+				},
+			};
+			stack.push(Term { value, derivative });
+		}
+		
+		if stack.len() != 1 {
+			return Err(CalcError::Leftover { count: stack.len() });
+		}
+		
+		let mut result = Expression::new(stack.pop().unwrap().derivative.unwrap_or_else(|| constant(0.0)));
+		result.simplify_in(angle_unit)?;
+		Ok(result)
+	}
+	
+	/// Finds where this expression equals zero with Newton's method, starting from `guess`.
+	/// 
+	/// `var` is the variable to solve for. Any other variables come from `variables`.
+	pub fn newton_root(&self, var: &str, guess: f64, variables: Option<&Environment>) -> Result<f64, CalcError> {
+		const MAX_ITERATIONS: usize = 64;
+		
+		let derivative = self.derivative_in(var, variables.map_or(AngleUnit::Radians, Environment::angle_unit))?;
+		let mut variables = variables.cloned().unwrap_or_default();
+		let mut x = guess;
+		
+		for _ in 0..MAX_ITERATIONS {
+			variables.insert(var.to_owned(), x);
+			let y = self.calculate(Some(&variables))?;
+			let slope = derivative.calculate(Some(&variables))?;
+			
+			if y == 0.0 {
+				return Ok(x);
+			}
+			if slope == 0.0 || !slope.is_finite() {
+				break;
+			}
+			
+			let next = x - y / slope;
+			if (next - x).abs() <= 1e-12 * next.abs().max(1.0) {
+				return Ok(next);
+			}
+			x = next;
+		}
+		
+		Err(CalcError::Domain { message: "Couldn't find a root from there.", span: None })
+	}
+}
@@ -0,0 +1,403 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use super::error::{CalcError, Span};
+use super::expression::{Expression, ExpressionVariables, ParenthesisDirection, Token};
+use super::number::{Number, Settings};
+use super::matrix::Matrix;
+use super::quantity::Quantity;
+use super::operator::AngleUnit;
+use super::integer::{IntegerType, Overflow};
+
+/// A function somebody defined, like `f(x) = x^2 - 3x`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserFunction {
+	pub parameters: Vec<String>,
+	pub body: Expression,
+}
+
+/// Everything a session remembers between lines: variables (and matrices, and amounts with units) and functions people have defined, `ans`, the last result, and settings like what unit angles are in.
+/// 
+/// This is what gets passed to `Expression::calculate`. `ans` and `Ans` are both ways to get at the last result.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Environment {
+	variables: ExpressionVariables,
+	/// Variables that hold a matrix instead of a number. A name is only ever in one of these.
+	matrices: HashMap<String, Matrix>,
+	/// Variables that hold an amount with units, like `d = 5 km`. Same deal as `matrices`.
+	quantities: HashMap<String, Quantity>,
+	functions: HashMap<String, UserFunction>,
+	ans: Option<f64>,
+	/// `ans`, when the last result had units. Only one of this and `ans` is ever set.
+	ans_quantity: Option<Quantity>,
+	settings: Settings,
+}
+
+impl Environment {
+	/// How many calls deep functions can go before we give up on them.
+	pub const MAX_RECURSION: usize = 256;
+	
+	pub fn new() -> Environment {
+		Environment::from(ExpressionVariables::new())
+	}
+	
+	/// Looks up a variable, including `ans`.
+	pub fn get(&self, name: &str) -> Option<f64> {
+		match name {
+			"ans" | "Ans" => self.ans,
+			_ => self.variables.get(name).copied(),
+		}
+	}
+	
+	/// Sets a variable, no questions asked. Gives back the old value, if there was one.
+	pub fn insert(&mut self, name: String, value: f64) -> Option<f64> {
+		self.matrices.remove(&name);
+		self.quantities.remove(&name);
+		self.variables.insert(name, value)
+	}
+	
+	/// Forgets a variable. Gives back its value, if it had one.
+	pub fn remove(&mut self, name: &str) -> Option<f64> {
+		self.matrices.remove(name);
+		self.quantities.remove(name);
+		self.variables.remove(name)
+	}
+	
+	/// Looks up a variable that holds a matrix.
+	pub fn matrix(&self, name: &str) -> Option<&Matrix> {
+		self.matrices.get(name)
+	}
+	
+	/// Sets a variable to a matrix. Same as `insert`, it replaces whatever had that name before.
+	pub fn insert_matrix(&mut self, name: String, value: Matrix) -> Option<Matrix> {
+		self.variables.remove(&name);
+		self.quantities.remove(&name);
+		self.matrices.insert(name, value)
+	}
+	
+	/// Looks up a variable that holds an amount with units, including `ans`.
+	pub fn quantity(&self, name: &str) -> Option<&Quantity> {
+		match name {
+			"ans" | "Ans" => self.ans_quantity.as_ref(),
+			_ => self.quantities.get(name),
+		}
+	}
+	
+	/// Sets a variable to an amount with units. Same as `insert`, it replaces whatever had that name before.
+	pub fn insert_quantity(&mut self, name: String, value: Quantity) -> Option<Quantity> {
+		self.variables.remove(&name);
+		self.matrices.remove(&name);
+		self.quantities.insert(name, value)
+	}
+	
+	/// All the variables, not counting `ans`.
+	pub fn variables(&self) -> &ExpressionVariables {
+		&self.variables
+	}
+	
+	pub fn ans(&self) -> Option<f64> {
+		self.ans
+	}
+	
+	pub fn set_ans(&mut self, value: f64) {
+		self.ans = Some(value);
+		self.ans_quantity = None;
+	}
+	
+	pub fn set_ans_quantity(&mut self, value: Quantity) {
+		self.ans = None;
+		self.ans_quantity = Some(value);
+	}
+	
+	/// Everything about how to calculate, all together, the way `Number` wants it.
+	pub fn settings(&self) -> &Settings {
+		&self.settings
+	}
+	
+	/// What trig functions take their angles in (and things like `atan2` give them back in).
+	pub fn angle_unit(&self) -> AngleUnit {
+		self.settings.angle_unit
+	}
+	
+	pub fn set_angle_unit(&mut self, unit: AngleUnit) {
+		self.settings.angle_unit = unit;
+	}
+	
+	/// How many significant digits decimal mode answers get.
+	pub fn precision(&self) -> usize {
+		self.settings.precision
+	}
+	
+	/// Sets how many significant digits decimal mode answers get. (At least 1.)
+	pub fn set_precision(&mut self, digits: usize) {
+		self.settings.precision = digits.max(1);
+	}
+	
+	/// What integer mode calculates with, like `i64` or `u8`.
+	pub fn integer_type(&self) -> IntegerType {
+		self.settings.integer_type
+	}
+	
+	pub fn set_integer_type(&mut self, integer_type: IntegerType) {
+		self.settings.integer_type = integer_type;
+	}
+	
+	/// What integer mode does when something doesn't fit.
+	pub fn overflow(&self) -> Overflow {
+		self.settings.overflow
+	}
+	
+	pub fn set_overflow(&mut self, overflow: Overflow) {
+		self.settings.overflow = overflow;
+	}
+	
+	/// Looks up a function somebody defined.
+	pub fn function(&self, name: &str) -> Option<&UserFunction> {
+		self.functions.get(name)
+	}
+	
+	/// Defines (or redefines) a function.
+	/// 
+	/// A function can call itself, but it can't call itself through some other function, since then redefining this one would quietly change what that one does too.
+	/// `span` is what any error points at.
+	pub fn define(&mut self, name: String, parameters: Vec<String>, body: Expression, span: Option<Span>) -> Result<(), CalcError> {
+		let mut to_visit: Vec<&str> = calls(&body).filter(|&called| called != name).collect();
+		let mut visited: HashSet<&str> = HashSet::new();
+		
+		while let Some(called) = to_visit.pop() {
+			if !visited.insert(called) {
+				continue;
+			}
+			if let Some(function) = self.functions.get(called) {
+				if calls(&function.body).any(|c| c == name) {
+					return Err(CalcError::UserFunction { name: called.to_owned(), message: "This would make a loop through", span });
+				}
+				to_visit.extend(calls(&function.body));
+			}
+		}
+		
+		// Spans in the body point at the line it was defined on, which isn't much use once it's called from somewhere else.
+		let body = Expression::new(body.tokens().to_vec());
+		self.functions.insert(name, UserFunction { parameters, body });
+		Ok(())
+	}
+	
+	/// Calls a function somebody defined. `span` is where it was called from, and `depth` is how many calls deep we already are.
+	/// 
+	/// Only the function's own arguments are visible inside it, not the ones of whatever called it.
+	pub fn call<T: Number>(&self, name: &str, args: &[T], span: Option<Span>, depth: usize) -> Result<T, CalcError> {
+		let function = match self.functions.get(name) {
+			Some(f) => f,
+			None => return Err(CalcError::UserFunction { name: name.to_owned(), message: "Unknown function", span }),
+		};
+		if function.parameters.len() != args.len() {
+			return Err(CalcError::UserFunction { name: name.to_owned(), message: "Wrong number of arguments for", span });
+		}
+		if depth >= Environment::MAX_RECURSION {
+			return Err(CalcError::UserFunction { name: name.to_owned(), message: "Too much recursion in", span });
+		}
+		
+		function.body.evaluate_with(Some(self), &function.parameters, args, depth + 1)
+			.map_err(|e| match span { Some(s) => e.with_span(s), None => e })
+	}
+	
+	/// Turns names followed by a `(` into function calls, if there's a function by that name. `defining` counts as a function too, so it can call itself.
+	pub fn mark_calls(&self, tokens: Vec<(Token, Span)>, defining: Option<&str>) -> Vec<(Token, Span)> {
+		let mut result: Vec<(Token, Span)> = Vec::with_capacity(tokens.len());
+		
+		for (i, (token, span)) in tokens.iter().enumerate() {
+			let token = match token {
+				Token::Variable(name) if (self.functions.contains_key(name) || defining == Some(name.as_str()))
+					&& matches!(tokens.get(i + 1), Some((Token::Parenthesis(ParenthesisDirection::Left), _))) => Token::UserFunction(name.clone()),
+				_ => token.clone(),
+			};
+			result.push((token, *span));
+		}
+		
+		result
+	}
+	
+	/// Runs a statement. Assignments and plain old expressions give back their result and remember it as `ans`, and definitions give back nothing.
+	pub fn run(&mut self, statement: &Statement) -> Result<Option<f64>, CalcError> {
+		self.run_as::<f64>(statement)
+	}
+	
+	/// Same as `run`, but calculates with some other kind of number, like `Complex`.
+	/// 
+	/// Variables (and `ans`) are still plain `f64`s, so assigning something like `2i` is an error, and results like that don't become `ans`.
+	/// Matrices are the exception, and get stored as matrices. So are amounts with units, which keep their units, and can be `ans` too.
+	pub fn run_as<T: Number>(&mut self, statement: &Statement) -> Result<Option<T>, CalcError> {
+		let result: T = match statement {
+			Statement::Assign { name, expression } => {
+				let result = expression.evaluate::<T>(Some(self))?;
+				match (result.to_f64(), result.to_matrix(), result.to_quantity()) {
+					(Some(value), _, _) => { self.insert(name.clone(), value); },
+					(None, Some(matrix), _) => { self.insert_matrix(name.clone(), matrix); },
+					(None, None, Some(quantity)) => { self.insert_quantity(name.clone(), quantity); },
+					(None, None, None) => return Err(CalcError::Domain { message: "Only real numbers can be stored in variables.", span: None }),
+				};
+				result
+			},
+			Statement::Define { name, parameters, body, span } => {
+				self.define(name.clone(), parameters.clone(), body.clone(), *span)?;
+				return Ok(None);
+			},
+			Statement::Evaluate(expression) => expression.evaluate::<T>(Some(self))?,
+		};
+		
+		if let Some(value) = result.to_f64() {
+			self.set_ans(value);
+		} else if let Some(quantity) = result.to_quantity() {
+			self.set_ans_quantity(quantity);
+		}
+		Ok(Some(result))
+	}
+}
+
+/// Every function somebody defined that `expression` calls.
+fn calls(expression: &Expression) -> impl Iterator<Item = &str> {
+	expression.tokens().iter().filter_map(|t| match t {
+		Token::UserFunction(name) => Some(name.as_str()),
+		_ => None,
+	})
+}
+
+impl From<ExpressionVariables> for Environment {
+	fn from(variables: ExpressionVariables) -> Environment {
+		Environment { variables, matrices: HashMap::new(), quantities: HashMap::new(), functions: HashMap::new(), ans: None, ans_quantity: None, settings: Settings::default() }
+	}
+}
+
+impl fmt::Display for Environment {
+	/// Lists every variable and function, one per line, in alphabetical order. This is what the `vars` command shows.
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.variables.is_empty() && self.matrices.is_empty() && self.quantities.is_empty() && self.functions.is_empty() && self.ans.is_none() && self.ans_quantity.is_none() {
+			return write!(f, "No variables yet.");
+		}
+		
+		let mut names: Vec<&String> = self.variables.keys().chain(self.matrices.keys()).chain(self.quantities.keys()).collect();
+		names.sort();
+		let mut function_names: Vec<&String> = self.functions.keys().collect();
+		function_names.sort();
+		
+		let mut lines: Vec<String> = names.into_iter().map(|name| match (self.variables.get(name), self.matrices.get(name)) {
+			(Some(value), _) => format!("{} = {}", name, value),
+			(None, Some(matrix)) => format!("{} = {}", name, matrix),
+			(None, None) => format!("{} = {}", name, self.quantities[name]),
+		}).collect();
+		for name in function_names {
+			let function = &self.functions[name];
+			lines.push(format!("{}({}) = {}", name, function.parameters.join(", "), function.body));
+		}
+		if let Some(ans) = self.ans {
+			lines.push(format!("ans = {}", ans));
+		}
+		if let Some(ans) = &self.ans_quantity {
+			lines.push(format!("ans = {}", ans));
+		}
+		write!(f, "{}", lines.join("\n"))
+	}
+}
+
+/// One line of input: something to calculate, something to calculate and then store, like `a = 3.5`, or a function definition, like `f(x) = x^2`.
+#[derive(Debug, Clone)]
+pub enum Statement {
+	Assign { name: String, expression: Expression },
+	/// `span` is the function's name, for pointing at if defining it goes wrong.
+	Define { name: String, parameters: Vec<String>, body: Expression, span: Option<Span> },
+	Evaluate(Expression),
+}
+
+impl Statement {
+	/// Finds the `=` that makes `input` an assignment or a definition, if there is one.
+	/// `==`, `<=`, `>=`, and `!=` are comparisons, so they don't count.
+	pub fn find_equals(input: &str) -> Option<usize> {
+		let bytes = input.as_bytes();
+		(0..bytes.len()).find(|&i| {
+			bytes[i] == b'=' && bytes.get(i + 1) != Some(&b'=') && !(i > 0 && matches!(bytes[i - 1], b'<' | b'>' | b'!' | b'='))
+		})
+	}
+	
+	/// Reads a statement from an infix string. Anything with an `=` in it is an assignment or a definition. (`==`, `<=`, `>=`, and `!=` don't count.)
+	/// 
+	/// Functions in `environment` can be called, so pass it in if there are any.
+	/// Spans in any errors (and in the expression) point into the whole of `input`, not just the part after the `=`.
+	pub fn from_infix_str(input: &str, environment: Option<&Environment>) -> Result<Statement, CalcError> {
+		let empty = Environment::new();
+		let environment = environment.unwrap_or(&empty);
+		
+		let equals = match Statement::find_equals(input) {
+			Some(i) => i,
+			None => {
+				let tokens = environment.mark_calls(Expression::infix_spanned_tokens_from_str(input)?, None);
+				return Ok(Statement::Evaluate(Expression::new_from_infix_spanned(tokens)?));
+			},
+		};
+		
+		let (left, right) = (&input[..equals], &input[equals + 1..]);
+		let equals_span = Some(Span::new(equals, equals + 1));
+		
+		let left_tokens = Expression::infix_spanned_tokens_from_str(left)?;
+		let (name, name_span, parameters) = match left_tokens.as_slice() {
+			[(Token::Variable(name), span)] => (name.clone(), *span, None),
+			[(Token::Variable(name), span), (Token::Parenthesis(ParenthesisDirection::Left), _), rest @ ..] => {
+				(name.clone(), *span, Some(Statement::parameters(rest)?))
+			},
+			[] => return Err(CalcError::Parse { message: "Nothing to assign to.", span: equals_span }),
+			[(Token::Function(_), span), ..] => return Err(CalcError::Parse { message: "That's already a built-in function.", span: Some(*span) }),
+			[(_, span), ..] => return Err(CalcError::Parse { message: "Only variables and functions can be assigned to.", span: Some(*span) }),
+		};
+		if name == "ans" || name == "Ans" {
+			return Err(CalcError::Parse { message: "ans is the last result, so it can't be assigned to.", span: Some(name_span) });
+		}
+		
+		let offset = equals + 1;
+		let tokens = Expression::infix_spanned_tokens_from_str(right)
+			.map_err(|e| e.shifted(offset))?
+			.into_iter()
+			.map(|(t, s)| (t, s.shifted(offset)))
+			.collect();
+		
+		match parameters {
+			Some(parameters) => {
+				let tokens = environment.mark_calls(tokens, Some(&name));
+				let body = Expression::new_from_infix_spanned(tokens)?;
+				Ok(Statement::Define { name, parameters, body, span: Some(name_span) })
+			},
+			None => {
+				let tokens = environment.mark_calls(tokens, None);
+				let expression = Expression::new_from_infix_spanned(tokens)?;
+				Ok(Statement::Assign { name, expression })
+			},
+		}
+	}
+	
+	/// Reads the `x, y)` part of `f(x, y) =`.
+	fn parameters(tokens: &[(Token, Span)]) -> Result<Vec<String>, CalcError> {
+		let mut parameters: Vec<String> = Vec::new();
+		let mut rest = tokens;
+		
+		loop {
+			match rest {
+				[(Token::Parenthesis(ParenthesisDirection::Right), _)] if parameters.is_empty() => break,
+				[(Token::Variable(p), span), next, after @ ..] => {
+					if parameters.contains(p) || p == "ans" || p == "Ans" {
+						return Err(CalcError::Parse { message: "Parameters need different names.", span: Some(*span) });
+					}
+					parameters.push(p.clone());
+					
+					match (next, after) {
+						((Token::Parenthesis(ParenthesisDirection::Right), _), []) => break,
+						((Token::Comma, _), _) => rest = after,
+						((_, span), _) => return Err(CalcError::Parse { message: "Expected a comma or a right parenthesis.", span: Some(*span) }),
+					}
+				},
+				[(Token::Variable(_), span)] => return Err(CalcError::Parse { message: "Missing right parentheses.", span: Some(*span) }),
+				[(_, span), ..] => return Err(CalcError::Parse { message: "Parameters have to be plain variable names.", span: Some(*span) }),
+				[] => return Err(CalcError::Parse { message: "Missing right parentheses.", span: None }),
+			}
+		}
+		
+		Ok(parameters)
+	}
+}
@@ -0,0 +1,142 @@
+use std::fmt;
+
+/// A range of bytes in the original input string. Used to point at the thing that went wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+	pub start: usize,
+	pub end: usize,
+}
+
+impl Span {
+	pub fn new(start: usize, end: usize) -> Span {
+		Span { start, end }
+	}
+	
+	/// Moves the span `offset` bytes to the right. For when a piece of a string got parsed on its own.
+	pub fn shifted(self, offset: usize) -> Span {
+		Span::new(self.start + offset, self.end + offset)
+	}
+}
+
+/// Every way the calculator can fail, from reading a string all the way to calculating a result.
+/// 
+/// Most of these carry an optional `Span`. Tokens that were made by hand (or made up by the calculator, like implicit multiplication) don't have one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CalcError {
+	/// Some piece of the input couldn't be turned into a token.
+	Lex { message: &'static str, span: Option<Span> },
+	/// The tokens are there, but they don't make a valid expression. (Mismatched parentheses, etc.)
+	Parse { message: &'static str, span: Option<Span> },
+	/// An operator or function didn't get as many arguments as it needed.
+	Arity { name: &'static str, expected: usize, found: usize, span: Option<Span> },
+	/// A variable was used, but nobody gave it a value.
+	UndefinedVariable { name: String, span: Option<Span> },
+	/// The arguments were outside of what an operator or function can handle.
+	Domain { message: &'static str, span: Option<Span> },
+	/// Two things needed the same units, like both sides of a `+`, and didn't have them. `left` and `right` are the units they had.
+	Dimension { left: String, right: String, span: Option<Span> },
+	/// Two matrices needed to be the same size (or the right sizes to multiply), and weren't. Sizes are rows, then columns.
+	Shape { left: (usize, usize), right: (usize, usize), span: Option<Span> },
+	/// Something went wrong with a function somebody defined, like `f(x) = x^2`. The message goes right before the function's name.
+	UserFunction { name: String, message: &'static str, span: Option<Span> },
+	/// The calculation didn't end with exactly one value on the stack.
+	Leftover { count: usize },
+}
+
+impl CalcError {
+	/// Gets the span this error points at, if it has one.
+	pub fn span(&self) -> Option<Span> {
+		match self {
+			CalcError::Lex { span, .. } |
+			CalcError::Parse { span, .. } |
+			CalcError::Arity { span, .. } |
+			CalcError::UndefinedVariable { span, .. } |
+			CalcError::UserFunction { span, .. } |
+			CalcError::Dimension { span, .. } |
+			CalcError::Shape { span, .. } |
+			CalcError::Domain { span, .. } => *span,
+			CalcError::Leftover { .. } => None,
+		}
+	}
+	
+	/// Gives the error a span, unless it already has one.
+	/// 
+	/// Handy for things like `Operator::try_from`, which only see a `&str` and have no idea where it came from.
+	pub fn with_span(mut self, new_span: Span) -> CalcError {
+		match &mut self {
+			CalcError::Lex { span, .. } |
+			CalcError::Parse { span, .. } |
+			CalcError::Arity { span, .. } |
+			CalcError::UndefinedVariable { span, .. } |
+			CalcError::UserFunction { span, .. } |
+			CalcError::Dimension { span, .. } |
+			CalcError::Shape { span, .. } |
+			CalcError::Domain { span, .. } => if span.is_none() { *span = Some(new_span); },
+			CalcError::Leftover { .. } => {},
+		}
+		self
+	}
+	
+	/// Moves this error's span `offset` bytes to the right, if it has one.
+	pub fn shifted(mut self, offset: usize) -> CalcError {
+		match &mut self {
+			CalcError::Lex { span, .. } |
+			CalcError::Parse { span, .. } |
+			CalcError::Arity { span, .. } |
+			CalcError::UndefinedVariable { span, .. } |
+			CalcError::UserFunction { span, .. } |
+			CalcError::Dimension { span, .. } |
+			CalcError::Shape { span, .. } |
+			CalcError::Domain { span, .. } => *span = span.map(|s| s.shifted(offset)),
+			CalcError::Leftover { .. } => {},
+		}
+		self
+	}
+	
+	/// Renders the error under the line of `input` it points at, with carets under the problem.
+	/// 
+	/// ```text
+	/// 2+2^5)/8
+	///      ^ Missing left parenthesis.
+	/// ```
+	/// If there's no span, this is just the error message.
+	pub fn diagnostic(&self, input: &str) -> String {
+		let span = match self.span() {
+			Some(s) if s.start <= input.len() && s.end <= input.len() => s,
+			_ => return self.to_string(),
+		};
+		
+		// Only show the line the span starts on.
+		let line_start = input[..span.start].rfind('\n').map_or(0, |i| i + 1);
+		let line_end = input[span.start..].find('\n').map_or(input.len(), |i| span.start + i);
+		let line = input[line_start..line_end].trim_end_matches('\r');
+		
+		// Keep tabs as tabs so the carets still line up.
+		let padding: String = input[line_start..span.start].chars()
+			.map(|c| if c == '\t' { '\t' } else { ' ' })
+			.collect();
+		let width = input[span.start..span.end.min(line_end).max(span.start)].chars().count().max(1);
+		
+		format!("{}\n{}{} {}", line, padding, "^".repeat(width), self)
+	}
+}
+
+impl fmt::Display for CalcError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			CalcError::Lex { message, .. } |
+			CalcError::Parse { message, .. } |
+			CalcError::Domain { message, .. } => write!(f, "{}", message),
+			CalcError::Arity { name, expected, found, .. } =>
+				write!(f, "Wrong number of arguments for {}. (Wanted {}, got {}.)", name, expected, found),
+			CalcError::UndefinedVariable { name, .. } => write!(f, "Undefined variable '{}'.", name),
+			CalcError::UserFunction { name, message, .. } => write!(f, "{} {}.", message, name),
+			CalcError::Dimension { left, right, .. } => write!(f, "Units don't match. ({} and {})", left, right),
+			CalcError::Shape { left, right, .. } => write!(f, "Sizes don't match. ({}x{} and {}x{})", left.0, left.1, right.0, right.1),
+			CalcError::Leftover { count: 0 } => write!(f, "No calculation result."),
+			CalcError::Leftover { count } => write!(f, "Too many leftover results. ({} left on the stack.)", count),
+		}
+	}
+}
+
+impl std::error::Error for CalcError {}
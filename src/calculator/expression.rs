@@ -1,17 +1,41 @@
 use std::collections::HashMap;
 use core::convert::TryFrom;
 
+use num_bigint::BigInt;
+use num_traits::{Signed, ToPrimitive};
+
 use super::operator;
+use super::error::{CalcError, Span};
+use super::environment::Environment;
+use super::number::{Number, Settings, operate_in, call_in};
 
 /// The Token enum holds a variety of types.
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Token {
 	Constant(f64),
-	Variable(char),
+	/// A whole number too big for a `Constant` to hold exactly, like `9007199254740993`. Modes that can keep it exact do, and the rest only take it if an `f64` happens to be close enough anyway.
+	Integer(BigInt),
+	Variable(String),
 	Operator(operator::Operator),
 	Function(operator::Function),
 	Parenthesis(ParenthesisDirection),
 	Comma,
+	/// The `{` and `}` around a piecewise expression, like `{x < 0: -x, x}`. Only shows up in infix, and turns into `if` before it gets anywhere else.
+	Brace(ParenthesisDirection),
+	/// The `:` between a piece's condition and its value.
+	Colon,
+	/// The `[` and `]` around a matrix like `[1, 2; 3, 4]`, or around an index like `v[2]`. Only shows up in infix.
+	Bracket(ParenthesisDirection),
+	/// The `;` between the rows of a matrix.
+	Semicolon,
+	/// Builds a matrix out of the `rows * cols` values before it, going row by row. Only shows up in RPN, where it's written `[2x2]`.
+	Matrix { rows: usize, cols: usize },
+	/// Indexes into a matrix. This is how many values it takes off the stack: the matrix, and then each index. Written `[2] index` in RPN.
+	Index(usize),
+	/// How many arguments the variadic function right after this got. Only shows up in RPN, never in infix.
+	ArgCount(usize),
+	/// A call to a function somebody defined, like `f` in `f(x) = x^2`. These always get an `ArgCount` too, since they can be redefined with a different number of parameters.
+	UserFunction(String),
 }
 
 /// Why not
@@ -20,20 +44,20 @@ pub enum ParenthesisDirection {
 	Left, Right,
 }
 impl TryFrom<&str> for ParenthesisDirection {
-	type Error = &'static str;
+	type Error = CalcError;
 	
 	fn try_from(s: &str) -> Result<Self, Self::Error> {
 		match s {
 			"(" => Ok(ParenthesisDirection::Left),
 			")" => Ok(ParenthesisDirection::Right),
-			_ => Err("Scary error - could not parse parenthesis."),
+			_ => Err(CalcError::Lex { message: "Scary error - could not parse parenthesis.", span: None }),
 		}
 	}
 }
 
 impl Token {
 	pub fn is_value(&self) -> bool {
-		matches!(self, Token::Constant(_) | Token::Variable(_))
+		matches!(self, Token::Constant(_) | Token::Integer(_) | Token::Variable(_))
 	}
 	
 	pub fn is_operator(&self) -> bool {
@@ -41,22 +65,53 @@ impl Token {
 	}
 	
 	pub fn is_function(&self) -> bool {
-		matches!(self, Token::Function(_))
+		matches!(self, Token::Function(_) | Token::UserFunction(_))
+	}
+	
+	/// Whether a value could have just ended with this token. (So a constant, variable, right parenthesis, or something like `Pi`.)
+	/// 
+	/// If it couldn't, a `+` or `-` after this token has to be unary.
+	pub fn ends_value(&self) -> bool {
+		// TODO: maybe just split Pi and E off into a "constants" token type.
+		match self {
+			Token::Constant(_) | Token::Integer(_) | Token::Variable(_) => true,
+			Token::Parenthesis(ParenthesisDirection::Right) | Token::Bracket(ParenthesisDirection::Right) => true,
+			Token::Function(f) => f.get_parameters() < 1,
+			Token::Operator(o) => o.is_postfix(),
+			_ => false,
+		}
 	}
 }
 
-pub type ExpressionVariables = HashMap<char, f64>;
+pub type ExpressionVariables = HashMap<String, f64>;
+
+/// Where an `if` goes after one of its arguments, so it only calculates the side it picks. See `Expression::branches`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Branch {
+	/// This token finished an `if`'s condition. `otherwise` is where the side for false starts, and `end` is the `if` itself. They're the same if there's no side for false.
+	Condition { otherwise: usize, end: usize },
+	/// This token finished the side for true, so the side for false gets skipped, all the way past the `if` at `end`.
+	Then { end: usize },
+}
 
 /// The Expression struct holds a collection of tokens, and provides a variety of utility functions.
-#[derive(Clone)]
+#[derive(Clone, PartialEq)]
 pub struct Expression {
 	tokens: Vec<Token>,
+	/// Where each token came from in the input string, if it came from one. Always the same length as `tokens`.
+	spans: Vec<Option<Span>>,
 }
 
 impl Expression {
 	/// Makes an `Expression` from a vector of `Token`s.
 	pub fn new(tokens: Vec<Token>) -> Expression {
-		Expression { tokens }
+		let spans = vec![None; tokens.len()];
+		Expression { tokens, spans }
+	}
+	
+	/// Makes an `Expression` straight from an infix string. Same as `infix_spanned_tokens_from_str` followed by `new_from_infix_spanned`.
+	pub fn from_infix_str(input: &str) -> Result<Expression, CalcError> {
+		Expression::new_from_infix_spanned(Expression::infix_spanned_tokens_from_str(input)?)
 	}
 	
 	/// Makes an `Expression` from a `vec!` of `Tokens` organized using infix notation.
@@ -82,24 +137,60 @@ impl Expression {
 	/// 	Token::Operator(operator::Operator::Add),
 	/// ]);
 	/// ```
-	pub fn new_from_infix(tokens: Vec<Token>) -> Result<Expression, &'static str> {
-		let mut op_stack: Vec<Token> = Vec::new();
-		let mut result: Vec<Token> = Vec::new();
+	pub fn new_from_infix(tokens: Vec<Token>) -> Result<Expression, CalcError> {
+		Expression::shunting_yard(tokens.into_iter().map(|t| (t, None)).collect())
+	}
+	
+	/// Same as `new_from_infix`, but every token knows where it came from, so errors can point at the input.
+	pub fn new_from_infix_spanned(tokens: Vec<(Token, Span)>) -> Result<Expression, CalcError> {
+		Expression::shunting_yard(tokens.into_iter().map(|(t, s)| (t, Some(s))).collect())
+	}
+	
+	/// The actual infix -> RPN conversion. Only used internally.
+	fn shunting_yard(tokens: Vec<(Token, Option<Span>)>) -> Result<Expression, CalcError> {
+		let mut op_stack: Vec<(Token, Option<Span>)> = Vec::new();
+		let mut result: Vec<(Token, Option<Span>)> = Vec::new();
 		
+		let tokens = Expression::expand_piecewise(&tokens)?;
 		let tokens = Expression::process_implicit_tokens(&tokens);
 		
-		for token in tokens.iter().cloned() {
+		// One entry for every left parenthesis we're inside of: how many arguments it's seen so far, and whether it belongs to a function.
+		let mut arg_counts: Vec<(usize, bool)> = Vec::new();
+		// Same for left brackets: how many values are in the row so far, how long the rows before it were, and whether it's an index instead of a matrix.
+		let mut brackets: Vec<(usize, Vec<usize>, bool)> = Vec::new();
+		let mut prev_token: Option<Token> = None;
+		
+		for (i, (token, span)) in tokens.iter().cloned().enumerate() {
+			// Variadic functions can't guess how many arguments they get without parentheses.
+			if let Token::Function(f) = &token {
+				if f.is_variadic() && !matches!(tokens.get(i + 1), Some((Token::Parenthesis(ParenthesisDirection::Left), _))) {
+					return Err(CalcError::Parse { message: "This function needs its arguments in parentheses.", span });
+				}
+			}
+			
+			let this_token = token.clone();
+			
 			// Shunting-yard algorithm match statement
 			match token {
-				Token::Constant(_) => result.push(token),
-				Token::Variable(_) => result.push(token),
+				Token::Constant(_) | Token::Integer(_) => result.push((token, span)),
+				Token::Variable(_) => result.push((token, span)),
+				// Postfix operators already have what they work on, so they go straight to the result.
+				// The only thing that could still be waiting is a function that just finished, like `sin(x)°` or `Pi rad`, and that goes first.
+				Token::Operator(o) if o.is_postfix() => {
+					let function_done = matches!(prev_token, Some(Token::Parenthesis(ParenthesisDirection::Right)) | Some(Token::Function(_)));
+					if function_done && matches!(op_stack.last(), Some((t, _)) if t.is_function()) {
+						result.push(op_stack.pop().unwrap());
+					}
+					result.push((token, span));
+				},
 				Token::Operator(o) => {
-					if !op_stack.is_empty() {
-						let mut next_token = op_stack[op_stack.len() - 1];
+					// Unary operators don't have anything on their left to fight over, so they go straight on the stack.
+					if o.get_parameters() > 1 && !op_stack.is_empty() {
+						let mut next_token = &op_stack[op_stack.len() - 1].0;
 						
 						// Very pretty code.
 						while match next_token {
-							Token::Function(_) => true,
+							Token::Function(_) | Token::UserFunction(_) => true,
 							Token::Operator(o2) => 
 								o2.get_precedence() > o.get_precedence() ||
 								o2.get_precedence() == o.get_precedence() && o2.get_associativity() == operator::OperatorAssociativity::Left,
@@ -107,99 +198,339 @@ impl Expression {
 						} {
 							result.push(op_stack.pop().unwrap());
 							if !op_stack.is_empty() {
-								next_token = op_stack[op_stack.len() - 1];
+								next_token = &op_stack[op_stack.len() - 1].0;
 							} else {
 								break;
 							}
 						}
 					}
 					
-					op_stack.push(token);
+					op_stack.push((token, span));
 				},
-				Token::Function(_) => op_stack.push(token),
+				Token::Function(_) | Token::UserFunction(_) => op_stack.push((token, span)),
 				Token::Parenthesis(d) => match d {
-					ParenthesisDirection::Left => op_stack.push(token),
+					ParenthesisDirection::Left => {
+						arg_counts.push((1, matches!(prev_token, Some(t) if t.is_function())));
+						op_stack.push((token, span));
+					},
 					ParenthesisDirection::Right => {
 						// Search through the stack for a left parenthesis.
 						loop {
-							let op = op_stack.pop().unwrap();
+							let op = match op_stack.pop() {
+								Some(op) => op,
+								None => return Err(CalcError::Parse { message: "Missing left parentheses.", span }),
+							};
 							
-							if op == Token::Parenthesis(ParenthesisDirection::Left) {
-								break;
-							} else {
-								result.push(op);
-								if op_stack.is_empty() {
-									return Err("Missing left parentheses.");
+							match op.0 {
+								Token::Parenthesis(ParenthesisDirection::Left) => break,
+								Token::Bracket(ParenthesisDirection::Left) => return Err(CalcError::Parse { message: "Missing right bracket.", span: op.1 }),
+								_ => result.push(op),
+							}
+						}
+						
+						let (mut count, is_call) = arg_counts.pop().unwrap_or((1, false));
+						// `()` has nothing in it at all.
+						if prev_token == Some(Token::Parenthesis(ParenthesisDirection::Left)) {
+							count = 0;
+						}
+						
+						if is_call {
+							// The function is still sitting right under its parenthesis. Now we know how many arguments it got.
+							if let Some((Token::UserFunction(_), _)) = op_stack.last() {
+								// We don't know how many it wants until it's called, so just keep track of how many it got.
+								result.push((Token::ArgCount(count), None));
+							}
+							if let Some((Token::Function(f), f_span)) = op_stack.last_mut() {
+								let overload = f.with_arguments(count);
+								if overload.is_variadic() {
+									if count < overload.get_parameters() {
+										return Err(CalcError::Arity { name: overload.name(), expected: overload.get_parameters(), found: count, span: *f_span });
+									}
+									
+									// Nothing else can make it onto the result before this function gets popped, so the count ends up right in front of it.
+									result.push((Token::ArgCount(count), None));
+								} else if overload.get_parameters() != count {
+									return Err(CalcError::Arity { name: overload.name(), expected: overload.get_parameters(), found: count, span: *f_span });
 								}
+								*f = overload;
 							}
+						} else if count > 1 {
+							return Err(CalcError::Parse { message: "Commas can only separate function arguments.", span });
 						}
 					},
 				},
-				_ => {},
+				Token::Bracket(ParenthesisDirection::Left) => {
+					// Right after a value, this is an index, like `v[2]` or `f(x)[1]`. A function that just finished goes first, same as for postfix operators.
+					let is_index = matches!(prev_token, Some(ref t) if t.ends_value());
+					if is_index && matches!(prev_token, Some(Token::Parenthesis(ParenthesisDirection::Right)) | Some(Token::Function(_)))
+					&& matches!(op_stack.last(), Some((t, _)) if t.is_function()) {
+						result.push(op_stack.pop().unwrap());
+					}
+					brackets.push((1, Vec::new(), is_index));
+					op_stack.push((token, span));
+				},
+				Token::Bracket(ParenthesisDirection::Right) => {
+					loop {
+						let op = match op_stack.pop() {
+							Some(op) => op,
+							None => return Err(CalcError::Parse { message: "Missing left bracket.", span }),
+						};
+						match op.0 {
+							Token::Bracket(ParenthesisDirection::Left) => break,
+							Token::Parenthesis(ParenthesisDirection::Left) => return Err(CalcError::Parse { message: "Missing right parentheses.", span: op.1 }),
+							_ => result.push(op),
+						}
+					}
+					
+					let (count, mut rows, is_index) = brackets.pop().unwrap_or((1, Vec::new(), false));
+					if prev_token == Some(Token::Bracket(ParenthesisDirection::Left)) {
+						return Err(CalcError::Parse { message: if is_index { "Missing an index." } else { "Matrices can't be empty." }, span });
+					}
+					
+					if is_index {
+						result.push((Token::Index(count + 1), span));
+					} else {
+						rows.push(count);
+						let cols = rows[0];
+						if rows.iter().any(|r| *r != cols) {
+							return Err(CalcError::Parse { message: "Every row of a matrix needs to be the same length.", span });
+						}
+						result.push((Token::Matrix { rows: rows.len(), cols }, span));
+					}
+				},
+				Token::Comma | Token::Semicolon => {
+					// Finish off the argument (or element) before the comma, but leave its parenthesis (or bracket) alone.
+					let opener = loop {
+						match op_stack.last() {
+							Some((Token::Parenthesis(ParenthesisDirection::Left), _)) => break Token::Parenthesis(ParenthesisDirection::Left),
+							Some((Token::Bracket(ParenthesisDirection::Left), _)) => break Token::Bracket(ParenthesisDirection::Left),
+							Some(_) => result.push(op_stack.pop().unwrap()),
+							None if token == Token::Comma => return Err(CalcError::Parse { message: "Commas can only be used inside parentheses.", span }),
+							None => return Err(CalcError::Parse { message: "Semicolons only go in matrices, like [1, 2; 3, 4].", span }),
+						}
+					};
+					
+					match (&token, opener) {
+						(Token::Comma, Token::Parenthesis(_)) => if let Some((count, _)) = arg_counts.last_mut() {
+							*count += 1;
+						},
+						(Token::Comma, _) => if let Some((count, _, _)) = brackets.last_mut() {
+							*count += 1;
+						},
+						(_, Token::Bracket(_)) if !matches!(brackets.last(), Some((_, _, true))) => {
+							let (count, rows, _) = brackets.last_mut().unwrap();
+							rows.push(*count);
+							*count = 1;
+						},
+						_ => return Err(CalcError::Parse { message: "Semicolons only go in matrices, like [1, 2; 3, 4].", span }),
+					}
+				},
+				Token::ArgCount(_) => return Err(CalcError::Parse { message: "Argument counts can't be used in infix expressions.", span }),
+				Token::Matrix { .. } | Token::Index(_) => return Err(CalcError::Parse { message: "Matrix sizes and indexes can't be used in infix expressions.", span }),
+				Token::Brace(_) | Token::Colon => unreachable!("piecewise expressions are expanded before this"),
 			}
+			
+			prev_token = Some(this_token);
 		}
 		
 		// Dump rest of op_stack onto the result.
-		while !op_stack.is_empty() {
-			let op = op_stack.pop().unwrap();
-			
+		while let Some(op) = op_stack.pop() {
 			// If there was a parenthesis, somebody screwed up.
 			// Those should've been consumed a long time ago.
-			if op == Token::Parenthesis(ParenthesisDirection::Left) {
-				return Err("Missing right parentheses.");
-			} else {
-				result.push(op);
+			match op.0 {
+				Token::Parenthesis(ParenthesisDirection::Left) => return Err(CalcError::Parse { message: "Missing right parentheses.", span: op.1 }),
+				Token::Bracket(ParenthesisDirection::Left) => return Err(CalcError::Parse { message: "Missing right bracket.", span: op.1 }),
+				_ => result.push(op),
+			}
+		}
+		
+		let (tokens, spans) = result.into_iter().unzip();
+		Ok(Expression { tokens, spans })
+	}
+	
+	/// Turns piecewise expressions into calls to `if`. Only used internally.
+	/// 
+	/// `{a: x, b: y, z}` is `if(a, x, if(b, y, z))`. If the last piece has a condition too, there's nothing left for when none of them are true, so that's `if(b, y)`.
+	fn expand_piecewise(tokens: &[(Token, Option<Span>)]) -> Result<Vec<(Token, Option<Span>)>, CalcError> {
+		let mut result: Vec<(Token, Option<Span>)> = Vec::new();
+		let mut i = 0;
+		
+		while i < tokens.len() {
+			match &tokens[i] {
+				(Token::Brace(ParenthesisDirection::Left), span) => {
+					let mut depth = 0;
+					let end = tokens[i..].iter().position(|(t, _)| {
+						match t {
+							Token::Brace(ParenthesisDirection::Left) => depth += 1,
+							Token::Brace(ParenthesisDirection::Right) => depth -= 1,
+							_ => {},
+						}
+						depth == 0
+					});
+					let end = match end {
+						Some(end) => i + end,
+						None => return Err(CalcError::Parse { message: "Missing right brace.", span: *span }),
+					};
+					
+					result.extend(Expression::expand_pieces(&tokens[i + 1..end], *span, tokens[end].1)?);
+					i = end + 1;
+				},
+				(Token::Brace(ParenthesisDirection::Right), span) => return Err(CalcError::Parse { message: "Missing left brace.", span: *span }),
+				(Token::Colon, span) => return Err(CalcError::Parse { message: "Colons only go in piecewise expressions, like {x < 0: -x, x}.", span: *span }),
+				(token, span) => {
+					result.push((token.clone(), *span));
+					i += 1;
+				},
+			}
+		}
+		
+		Ok(result)
+	}
+	
+	/// The inside of one piecewise expression, for `expand_piecewise`. `left` and `right` are where its braces were.
+	fn expand_pieces(tokens: &[(Token, Option<Span>)], left: Option<Span>, right: Option<Span>) -> Result<Vec<(Token, Option<Span>)>, CalcError> {
+		// Split it up at the commas and colons that aren't inside something else. Each piece is its condition, its value, and where to point if it's broken.
+		type Tokens<'a> = &'a [(Token, Option<Span>)];
+		let mut pieces: Vec<(Option<Tokens>, Tokens, Option<Span>)> = Vec::new();
+		let (mut start, mut colon, mut depth) = (0, None, 0i32);
+		for (i, (token, span)) in tokens.iter().enumerate() {
+			match token {
+				Token::Parenthesis(ParenthesisDirection::Left) | Token::Brace(ParenthesisDirection::Left) | Token::Bracket(ParenthesisDirection::Left) => depth += 1,
+				Token::Parenthesis(ParenthesisDirection::Right) | Token::Brace(ParenthesisDirection::Right) | Token::Bracket(ParenthesisDirection::Right) => depth -= 1,
+				Token::Colon if depth == 0 => {
+					if colon.is_some() {
+						return Err(CalcError::Parse { message: "Each piece only gets one condition.", span: *span });
+					}
+					colon = Some((i, *span));
+				},
+				_ => {},
+			}
+			
+			if depth == 0 && (*token == Token::Comma || i == tokens.len() - 1) {
+				let end = if *token == Token::Comma { i } else { i + 1 };
+				let piece = match colon.take() {
+					Some((c, span)) => (Some(&tokens[start..c]), &tokens[c + 1..end], span),
+					None => (None, &tokens[start..end], *span),
+				};
+				pieces.push(piece);
+				start = i + 1;
+			}
+		}
+		
+		if pieces.is_empty() || tokens.last().map(|t| &t.0) == Some(&Token::Comma) {
+			return Err(CalcError::Parse { message: "Missing a piece.", span: right });
+		}
+		
+		let mut result: Vec<(Token, Option<Span>)> = vec![(Token::Parenthesis(ParenthesisDirection::Left), left)];
+		let mut ifs = 0;
+		let last = pieces.len() - 1;
+		for (i, (condition, value, span)) in pieces.into_iter().enumerate() {
+			if value.is_empty() || condition.is_some_and(<[_]>::is_empty) {
+				return Err(CalcError::Parse { message: "Missing a piece.", span });
+			}
+			
+			match condition {
+				Some(condition) => {
+					result.push((Token::Function(operator::Function::If), left));
+					result.push((Token::Parenthesis(ParenthesisDirection::Left), left));
+					result.extend(Expression::expand_piecewise(condition)?);
+					result.push((Token::Comma, span));
+					result.extend(Expression::expand_piecewise(value)?);
+					// The last piece doesn't have anything left for when it's false, so it's `if(a, x)`, which has nothing there.
+					if i != last {
+						result.push((Token::Comma, right));
+					}
+					ifs += 1;
+				},
+				None if i == last => result.extend(Expression::expand_piecewise(value)?),
+				None => return Err(CalcError::Parse { message: "Only the last piece can leave out its condition.", span: value[0].1 }),
 			}
 		}
+		result.extend((0..=ifs).map(|_| (Token::Parenthesis(ParenthesisDirection::Right), right)));
 		
-		Ok(Expression { tokens: result })
+		Ok(result)
 	}
 	
-	/// Adds implicit multiplication stuff. Only used internally.
-	fn process_implicit_tokens(input: &Vec<Token>) -> Vec<Token> {
-		let mut result: Vec<Token> = Vec::new();
+	/// Adds implicit multiplication stuff, and turns `+` and `-` into unary operators where they need to be. Only used internally.
+	/// 
+	/// A `+` or `-` is unary when there's nothing for it to take on its left: at the start of the input, or right after an operator, `(`, `,`, or a function that still wants its argument.
+	fn process_implicit_tokens(input: &[(Token, Option<Span>)]) -> Vec<(Token, Option<Span>)> {
+		let mut result: Vec<(Token, Option<Span>)> = Vec::new();
 		
 		for i in 0..input.len() {
-			let token = input[i];
+			let (mut token, span) = input[i].clone();
 			let prev_token = if i > 0 {
-				Some(input[i - 1])
+				Some(&input[i - 1].0)
 			} else {
 				None
 			};
 			
+			if !matches!(prev_token, Some(t) if t.ends_value()) {
+				token = match token {
+					Token::Operator(operator::Operator::Add) => Token::Operator(operator::Operator::Unp),
+					Token::Operator(operator::Operator::Sub) => Token::Operator(operator::Operator::Unm),
+					_ => token,
+				};
+			}
+			
 			if let Some(prev_token) = prev_token {
-				// TODO: make this look less awkward. or maybe just split Pi and E off into a "constants" token type.
-				if (prev_token.is_value() || prev_token == Token::Parenthesis(ParenthesisDirection::Right) ||
-					(prev_token.is_function() && match prev_token { Token::Function(f) => f.get_parameters() < 1, _ => false, })
-				)
+				if prev_token.ends_value()
 				&& (token.is_value() || token.is_function() || token == Token::Parenthesis(ParenthesisDirection::Left)) {
-					result.push(Token::Operator(operator::Operator::Mul));
+					result.push((Token::Operator(operator::Operator::Mul), None));
+				}
+				// Numbers can't be indexed, so `2[1, 2]` is scaling a vector.
+				if matches!(prev_token, Token::Constant(_) | Token::Integer(_)) && token == Token::Bracket(ParenthesisDirection::Left) {
+					result.push((Token::Operator(operator::Operator::Mul), None));
 				}
 			}
 			
-			result.push(token);
+			result.push((token, span));
 		}
 		
 		result
 	}
 	
 	/// Makes a vector of infix tokens from a string. Useful for user-facing things.
-	pub fn infix_tokens_from_str(input: &str) -> Result<Vec<Token>, &'static str> {
+	/// 
+	/// Numbers can be written like `12`, `1.5`, `.5`, `1_000_000`, `6.02E23`, or `1e-9`, and `inf` and `nan` work too.
+	/// Whole numbers can also be hex, binary, or octal, like `0xFF`, `0b1010_0101`, or `0o755`.
+	/// Angles can have a unit after them, like `30°`, `30deg`, `1.2rad`, or `50grad`. `deg`, `rad`, and `grad` are only units right after a value, so they still work as variable names.
+	/// `to` is the same way: it's only a conversion (like `60 mph to m/s`) when it comes after a value.
+	/// A number followed right away by `e` or `E` and some digits is always read as an exponent, so if you want `2 * E * 5`, write `2E*5` or `2 E 5`. If there aren't any digits, like in `2E` or `3e+x`, the `E` is the constant (and the `e` is a variable).
+	/// 
+	/// Names are a letter (any alphabet, so `θ` works) followed by any letters, digits, or underscores, like `theta`, `x_1`, or `v0`.
+	/// Everything written together is one name, so `xy` is a variable called `xy`, and `sinx` isn't `sin x`. Put a space or a `*` between them if you want them multiplied.
+	/// A name is only a function (or `inf`/`nan`) if the whole thing matches, so `asinh` is never `a*sinh` or `asin*h`, and `sinc` and `maxSpeed` are just variables.
+	/// `and`, `or`, `not`, and `xor` are operators, so they can't be variables.
+	/// 
+	/// Piecewise expressions go in braces, like `{x < 0: -x, x}`. Each piece is `condition: value`, except the last one can be just a value, for when nothing else was true.
+	/// 
+	/// Matrices go in brackets, with commas between the values and semicolons between the rows, like `[1, 2; 3, 4]`. A vector is just one row, like `[1, 2, 3]`.
+	/// Brackets right after something else are an index instead, counting from 1, so `v[2]` is the second value of `v`, `A[2]` is the second row of `A`, and `A[2, 1]` is the first value of that row.
+	pub fn infix_tokens_from_str(input: &str) -> Result<Vec<Token>, CalcError> {
+		Ok(Expression::infix_spanned_tokens_from_str(input)?.into_iter().map(|(t, _)| t).collect())
+	}
+	
+	/// Makes a vector of infix tokens from a string, along with the span of the string each one came from.
+	pub fn infix_spanned_tokens_from_str(input: &str) -> Result<Vec<(Token, Span)>, CalcError> {
 		//TODO: don't initialize Regex stuff every time.
-		let mut result: Vec<Token> = Vec::new();
+		let mut result: Vec<(Token, Span)> = Vec::new();
 		
 		let big_regex = regex::Regex::new(r"(?x) # Order of these lines determines the priority.
 			 (\(|\))                             # Matches any parenthesis.
-			|((?:\d*\.\d+)|(?:\d+\.\d*)|(?:\d+)) # Matches any constants.
-			|(\+|\-|\*|/|%|\^)                   # Matches any operators.
-			|(sin|cos|tan|csc|sec|cot|log|ln|abs|sgn|Pi|E) # Matches any functions.
-			|(\S)                                # Matches any variables.
+			|(0[xX][\da-fA-F_]+|0[bB][01_]+|0[oO][0-7_]+|(?:\d[\d_]*(?:\.[\d_]*)?|\.\d[\d_]*)(?:[eE][+\-]?\d[\d_]*)?) # Matches any constants.
+			|(<<|>>|<=|>=|==|!=|<|>|\+|\-|\*|/|%|\^|√|&|\||~|°) # Matches any operators.
+			|(\p{Alphabetic}\w*)                  # Matches any names. (Functions, keywords, or variables.)
+			|(,)                                 # Matches commas.
+			|(\{|\}|:)                           # Matches the braces and colons of piecewise expressions.
+			|(\[|\]|;)                           # Matches the brackets and semicolons of matrices.
+			|(\S)                                # Matches anything else, which is probably a variable.
 		").unwrap();
 		
 		// TODO: this sucks. I didn't want to think about anything while doing this, and it shows.
 		#[derive(Clone, Copy, PartialEq, Eq)]
 		enum InfixStringRegexMatchesType {
-			Parenthesis, Constant, Operator, Function, Variable,
+			Parenthesis, Constant, Operator, Name, Comma, Piecewise, Matrix, Variable,
 		}
 		struct InfixStringRegexMatches {
 			start: usize,
@@ -218,7 +549,9 @@ impl Expression {
 							// Ewwww
 							1 => InfixStringRegexMatchesType::Parenthesis,
 							2 => InfixStringRegexMatchesType::Constant, 3 => InfixStringRegexMatchesType::Operator,
-							4 => InfixStringRegexMatchesType::Function, 5 => InfixStringRegexMatchesType::Variable,
+							4 => InfixStringRegexMatchesType::Name, 5 => InfixStringRegexMatchesType::Comma,
+							6 => InfixStringRegexMatchesType::Piecewise, 7 => InfixStringRegexMatchesType::Matrix,
+							8 => InfixStringRegexMatchesType::Variable,
 							_ => panic!("uhhhhhh what"),
 						},
 					});
@@ -229,49 +562,495 @@ impl Expression {
 		
 		for cap in matches.iter() {
 			let tmp = &input[cap.start..cap.end];
-			match cap.token_type {
-				InfixStringRegexMatchesType::Variable => {
-					match tmp.chars().next() {
-						Some(v) => result.push(Token::Variable(v)),
-						None => return Err("Scary error - could not parse variable."),
+			let span = Span::new(cap.start, cap.end);
+			let token = match cap.token_type {
+				InfixStringRegexMatchesType::Variable => Token::Variable(tmp.to_owned()),
+				InfixStringRegexMatchesType::Constant => Expression::parse_number(tmp).map_err(|e| e.with_span(span))?,
+				InfixStringRegexMatchesType::Operator => Token::Operator(operator::Operator::try_from(tmp).map_err(|e| e.with_span(span))?),
+				InfixStringRegexMatchesType::Name => {
+					// The whole name has to match, so `asinh` doesn't turn into `a sinh` or `asin h`.
+					match tmp {
+						"inf" => Token::Constant(f64::INFINITY),
+						"nan" => Token::Constant(f64::NAN),
+						"xor" | "and" | "or" | "not" => Token::Operator(operator::Operator::try_from(tmp).map_err(|e| e.with_span(span))?),
+						// Angle units only count right after something, so `rad` can still be a variable on its own.
+						"deg" | "rad" | "grad" if matches!(result.last(), Some((t, _)) if t.ends_value()) => Token::Operator(operator::Operator::try_from(tmp).map_err(|e| e.with_span(span))?),
+						// Same for `to`, which needs something on its left to convert.
+						"to" if matches!(result.last(), Some((t, _)) if t.ends_value()) => Token::Operator(operator::Operator::To),
+						// Some functions need their parentheses, so `re` or `rank` can still be variables on their own.
+						_ => match operator::Function::try_from(tmp) {
+							Ok(f) if !f.needs_parentheses() || input[cap.end..].trim_start().starts_with('(') => Token::Function(f),
+							_ => Token::Variable(tmp.to_owned()),
+						},
 					}
 				},
-				InfixStringRegexMatchesType::Constant => {
-					match tmp.parse::<f64>() {
-						Ok(c) => result.push(Token::Constant(c)),
-						Err(_) => return Err("Could not parse f64."),
-					}
+				InfixStringRegexMatchesType::Parenthesis => Token::Parenthesis(ParenthesisDirection::try_from(tmp).map_err(|e| e.with_span(span))?),
+				InfixStringRegexMatchesType::Comma => Token::Comma,
+				InfixStringRegexMatchesType::Piecewise => match tmp {
+					"{" => Token::Brace(ParenthesisDirection::Left),
+					"}" => Token::Brace(ParenthesisDirection::Right),
+					_ => Token::Colon,
 				},
-				InfixStringRegexMatchesType::Operator => result.push(Token::Operator(operator::Operator::try_from(tmp)?)),
-				InfixStringRegexMatchesType::Function => result.push(Token::Function(operator::Function::try_from(tmp)?)),
-				InfixStringRegexMatchesType::Parenthesis => result.push(Token::Parenthesis(ParenthesisDirection::try_from(tmp)?)),
-			}
+				InfixStringRegexMatchesType::Matrix => match tmp {
+					"[" => Token::Bracket(ParenthesisDirection::Left),
+					"]" => Token::Bracket(ParenthesisDirection::Right),
+					_ => Token::Semicolon,
+				},
+			};
+			result.push((token, span));
 		}
 		
 		Ok(result)
 	}
 	
-	// /// Simplifies an expression, looking for known values it can compute once.
-	// pub fn simplify(&mut self) -> Result<(), &'static str> {
-	// 	unimplemented!();
-	// }
+	/// Makes an `Expression` from a string of whitespace-separated tokens in reverse polish notation, like `2 4 * x sin +`.
+	/// 
+	/// Numbers, names, and operators work the same as in infix, except:
+	/// * Negative numbers can be written directly, like `-2.5`. A lone `-` is always subtraction, so unary minus and plus are `neg` and `pos`.
+	/// * Variadic functions need to know how many arguments to take, so put `[n]` right before them: `a b c [3] max`.
+	///   This works for other functions too, so `x 2 [2] log` is log base 2.
+	/// * A name with `[n]` before it is a call to a function somebody defined, so `f(x, y)` is `x y [2] f`.
+	/// * Matrices are their values, row by row, and then their size, so `[1, 2; 3, 4]` is `1 2 3 4 [2x2]`.
+	///   Indexing is `index` with a count that includes the matrix, so `A[2, 1]` is `A 2 1 [3] index`.
+	/// * `°`, `deg`, `rad`, and `grad` are always angle units, like `30 deg sin`, and `to` is always a conversion, like `60 mph m s / to`, so they can't be variables.
+	/// 
+	/// The stack is checked as it's read, so anything that'd run out of arguments (or leave extra results) is an error here instead of when calculating.
+	pub fn from_rpn_str(input: &str) -> Result<Expression, CalcError> {
+		let mut tokens: Vec<Token> = Vec::new();
+		let mut spans: Vec<Option<Span>> = Vec::new();
+		let mut depth: usize = 0;
+		let mut arg_count: Option<(usize, Span)> = None;
+		
+		let words = input.split_whitespace().map(|word| {
+			// split_whitespace always gives back pieces of the original string, so this finds where they were.
+			let start = word.as_ptr() as usize - input.as_ptr() as usize;
+			(word, Span::new(start, start + word.len()))
+		});
+		
+		for (word, span) in words {
+			if let Some((rows, cols)) = Expression::parse_rpn_matrix_size(word) {
+				if let Some((_, count_span)) = arg_count {
+					return Err(CalcError::Parse { message: "Argument counts have to come right before a function.", span: Some(count_span) });
+				}
+				let n = rows * cols;
+				if depth < n {
+					return Err(CalcError::Arity { name: "matrix", expected: n, found: depth, span: Some(span) });
+				}
+				depth = depth - n + 1;
+				tokens.push(Token::Matrix { rows, cols });
+				spans.push(Some(span));
+				continue;
+			}
+			if let Some(n) = Expression::parse_rpn_arg_count(word).map_err(|e| e.with_span(span))? {
+				arg_count = Some((n, span));
+				continue;
+			}
+			let token = match Expression::token_from_rpn_word(word) {
+				Some(t) => t,
+				None => return Err(CalcError::Lex { message: "Unknown RPN token.", span: Some(span) }),
+			};
+			
+			let (token, arg_length) = match token {
+				Token::Operator(o) => (token, o.get_parameters()),
+				Token::Function(f) => match arg_count.take() {
+					Some((n, _)) => {
+						let f = f.with_arguments(n);
+						if (f.is_variadic() && n < f.get_parameters()) || (!f.is_variadic() && n != f.get_parameters()) {
+							return Err(CalcError::Arity { name: f.name(), expected: f.get_parameters(), found: n, span: Some(span) });
+						}
+						if f.is_variadic() {
+							tokens.push(Token::ArgCount(n));
+							spans.push(None);
+						}
+						(Token::Function(f), n)
+					},
+					None if f.is_variadic() => return Err(CalcError::Parse { message: "This function needs an argument count before it, like [3].", span: Some(span) }),
+					None => (token, f.get_parameters()),
+				},
+				Token::Variable(name) if name == "index" => match arg_count.take() {
+					Some((n, _)) if n >= 2 => (Token::Index(n), n),
+					Some((n, _)) => return Err(CalcError::Arity { name: "index", expected: 2, found: n, span: Some(span) }),
+					None => return Err(CalcError::Parse { message: "index needs a count before it, like [2].", span: Some(span) }),
+				},
+				// A name with an argument count is a call to a function somebody defined.
+				Token::Variable(name) if arg_count.is_some() => {
+					let (n, _) = arg_count.take().unwrap();
+					tokens.push(Token::ArgCount(n));
+					spans.push(None);
+					(Token::UserFunction(name), n)
+				},
+				_ => (token, 0),
+			};
+			
+			if let Some((_, count_span)) = arg_count {
+				return Err(CalcError::Parse { message: "Argument counts have to come right before a function.", span: Some(count_span) });
+			}
+			
+			if depth < arg_length {
+				return Err(match token {
+					Token::Operator(o) => CalcError::Arity { name: o.symbol(), expected: arg_length, found: depth, span: Some(span) },
+					Token::Function(f) => CalcError::Arity { name: f.name(), expected: arg_length, found: depth, span: Some(span) },
+					Token::UserFunction(name) => CalcError::UserFunction { name, message: "Not enough arguments for", span: Some(span) },
+					Token::Index(_) => CalcError::Arity { name: "index", expected: arg_length, found: depth, span: Some(span) },
+					_ => unreachable!(),
+				});
+			}
+			depth = depth - arg_length + 1;
+			
+			tokens.push(token);
+			spans.push(Some(span));
+		}
+		
+		if let Some((_, count_span)) = arg_count {
+			return Err(CalcError::Parse { message: "Argument counts have to come right before a function.", span: Some(count_span) });
+		}
+		if depth != 1 {
+			return Err(CalcError::Leftover { count: depth });
+		}
+		
+		Ok(Expression { tokens, spans })
+	}
+	
+	/// Reads one word of RPN into a token, if it's a number, operator, function, or variable name.
+	/// 
+	/// Argument counts like `[3]` aren't tokens on their own, so `parse_rpn_arg_count` deals with those.
+	pub fn token_from_rpn_word(word: &str) -> Option<Token> {
+		match word {
+			"neg" => Some(Token::Operator(operator::Operator::Unm)),
+			"pos" => Some(Token::Operator(operator::Operator::Unp)),
+			_ => {
+				if let Ok(o) = operator::Operator::try_from(word) {
+					Some(Token::Operator(o))
+				} else if let Ok(f) = operator::Function::try_from(word) {
+					Some(Token::Function(f))
+				} else if let Some(token) = Expression::parse_rpn_number(word) {
+					Some(token)
+				} else if word.starts_with(char::is_alphabetic) && word.chars().all(|c| c.is_alphanumeric() || c == '_') {
+					Some(Token::Variable(word.to_owned()))
+				} else {
+					None
+				}
+			},
+		}
+	}
+	
+	/// Reads an argument count like `[3]`. Gives `Ok(None)` if the word isn't one at all, and an error if it tried to be one.
+	pub fn parse_rpn_arg_count(word: &str) -> Result<Option<usize>, CalcError> {
+		if !(word.starts_with('[') && word.ends_with(']')) {
+			return Ok(None);
+		}
+		match word[1..word.len() - 1].parse::<usize>() {
+			Ok(n) => Ok(Some(n)),
+			Err(_) => Err(CalcError::Lex { message: "Argument counts look like [3].", span: None }),
+		}
+	}
+	
+	/// Reads a matrix size like `[2x3]` (rows, then columns). Gives `None` if the word isn't one.
+	fn parse_rpn_matrix_size(word: &str) -> Option<(usize, usize)> {
+		let inside = word.strip_prefix('[')?.strip_suffix(']')?;
+		let (rows, cols) = inside.split_once('x')?;
+		match (rows.parse::<usize>(), cols.parse::<usize>()) {
+			(Ok(rows), Ok(cols)) if rows > 0 && cols > 0 => Some((rows, cols)),
+			_ => None,
+		}
+	}
+	
+	/// Reads a number for `from_rpn_str`. Same as the infix numbers, but it can start with a sign.
+	fn parse_rpn_number(word: &str) -> Option<Token> {
+		let (negative, rest) = match word.strip_prefix('-') {
+			Some(rest) => (true, rest),
+			None => (false, word.strip_prefix('+').unwrap_or(word)),
+		};
+		
+		let token = match rest {
+			"inf" => Token::Constant(f64::INFINITY),
+			"nan" => return Some(Token::Constant(f64::NAN)),
+			// Rust is a bit more lenient than the infix lexer (it'd take "infinity"), so check the start.
+			_ if rest.starts_with(|c: char| c.is_ascii_digit() || c == '.') => Expression::parse_number(rest).ok()?,
+			_ => return None,
+		};
+		match token {
+			Token::Constant(c) if negative => Some(Token::Constant(-c)),
+			Token::Integer(n) if negative => Some(Token::Integer(-n)),
+			_ => Some(token),
+		}
+	}
+	
+	/// Reads a number without a sign, like `1.5e3`, `1_000`, `0xFF`, `0b1010`, or `0o17`. Hex, binary, and octal are whole numbers only.
+	/// 
+	/// Whole numbers past 2^53 can't all be `f64`s, so those become `Token::Integer`, and whether that's close enough is up to the mode. Everything else is a `Token::Constant`.
+	fn parse_number(text: &str) -> Result<Token, CalcError> {
+		// Digit separators are just for looking at.
+		let text = text.replace('_', "");
+		let (digits, radix) = match text.get(..2) {
+			Some("0x") | Some("0X") => (&text[2..], 16),
+			Some("0b") | Some("0B") => (&text[2..], 2),
+			Some("0o") | Some("0O") => (&text[2..], 8),
+			_ if text.bytes().all(|b| b.is_ascii_digit()) => (&text[..], 10),
+			_ => return text.parse::<f64>().map(Token::Constant).map_err(|_| CalcError::Lex { message: "Could not parse f64.", span: None }),
+		};
+		
+		match BigInt::parse_bytes(digits.as_bytes(), radix) {
+			Some(n) if digits.chars().all(|c| c.is_digit(radix)) => Ok(Expression::integer_token(n)),
+			_ => Err(CalcError::Lex { message: "Could not parse number.", span: None }),
+		}
+	}
+	
+	/// A whole number as a token. Anything with 53 bits or less is exactly an `f64`, so those are just constants.
+	fn integer_token(n: BigInt) -> Token {
+		match n.to_f64() {
+			Some(c) if n.bits() <= f64::MANTISSA_DIGITS as u64 => Token::Constant(c),
+			_ => Token::Integer(n),
+		}
+	}
+	
+	/// Writes this expression out in reverse polish notation, in a way `from_rpn_str` can read back in.
+	pub fn to_rpn_string(&self) -> String {
+		let mut words: Vec<String> = Vec::new();
+		
+		for token in self.tokens.iter() {
+			let word = match token {
+				Token::Constant(c) if c.is_nan() => "nan".to_owned(),
+				Token::Constant(c) => c.to_string(),
+				Token::Integer(n) => n.to_string(),
+				Token::Variable(v) => v.clone(),
+				Token::Operator(operator::Operator::Unm) => "neg".to_owned(),
+				Token::Operator(operator::Operator::Unp) => "pos".to_owned(),
+				Token::Operator(o) => o.symbol().to_owned(),
+				Token::Function(f) => {
+					// Things like log(x, base) share a name with something else, so they need their count to tell them apart.
+					if !f.is_variadic() && operator::Function::try_from(f.name()).ok() != Some(*f) {
+						words.push(format!("[{}]", f.get_parameters()));
+					}
+					f.name().to_owned()
+				},
+				Token::ArgCount(n) => format!("[{}]", n),
+				Token::UserFunction(name) => name.clone(),
+				Token::Parenthesis(ParenthesisDirection::Left) => "(".to_owned(),
+				Token::Parenthesis(ParenthesisDirection::Right) => ")".to_owned(),
+				Token::Comma => ",".to_owned(),
+				Token::Brace(ParenthesisDirection::Left) => "{".to_owned(),
+				Token::Brace(ParenthesisDirection::Right) => "}".to_owned(),
+				Token::Colon => ":".to_owned(),
+				Token::Bracket(ParenthesisDirection::Left) => "[".to_owned(),
+				Token::Bracket(ParenthesisDirection::Right) => "]".to_owned(),
+				Token::Semicolon => ";".to_owned(),
+				Token::Matrix { rows, cols } => format!("[{}x{}]", rows, cols),
+				Token::Index(n) => format!("[{}] index", n),
+			};
+			words.push(word);
+		}
+		
+		words.join(" ")
+	}
+	
+	/// Gets the tokens of this expression, in reverse polish notation.
+	pub fn tokens(&self) -> &[Token] {
+		&self.tokens
+	}
+	
+	/// Gets where each token came from in the input string. Lines up with `tokens`.
+	pub fn spans(&self) -> &[Option<Span>] {
+		&self.spans
+	}
+	
+	/// Simplifies an expression, looking for known values it can compute once. Returns how many tokens it got rid of.
+	/// 
+	/// Anything that only uses constants (including things like `Pi`) gets folded into a single constant, and identities like `x*1`, `x+0`, and `x^1` turn into just `x`.
+	/// Things like `x*0` are left alone, since `x` could be NaN or infinity.
+	/// 
+	/// Folding things like `sin(30)` depends on what unit angles are in, and this assumes radians. Use `simplify_in` if they aren't.
+	pub fn simplify(&mut self) -> Result<usize, CalcError> {
+		self.simplify_in(operator::AngleUnit::Radians)
+	}
+	
+	/// `simplify`, but with angles in `angle_unit`. The result only means the same thing as before in that unit.
+	pub fn simplify_in(&mut self, angle_unit: operator::AngleUnit) -> Result<usize, CalcError> {
+		let old_length = self.tokens.len();
+		// Folding is all floats, so the angle unit's the only setting that matters.
+		let settings = Settings { angle_unit, ..Settings::default() };
+		
+		let mut result: Vec<(Token, Option<Span>)> = Vec::new();
+		// Mirrors the stack `calculate` would have. Each entry is where that value's tokens start in `result`, and its value if we already know it.
+		let mut stack: Vec<(usize, Option<f64>)> = Vec::new();
+		let mut arg_count: Option<(Token, Option<Span>)> = None;
+		// Which tokens are in one side of an `if`, and so might never get calculated.
+		let mut conditional = vec![false; self.tokens.len()];
+		for (i, branch) in self.branches().into_iter().enumerate() {
+			if let Some(Branch::Condition { end, .. }) = branch {
+				conditional[i + 1..end].iter_mut().for_each(|c| *c = true);
+			}
+		}
+		
+		for (i, (token, span)) in self.tokens.iter().cloned().zip(self.spans.iter().cloned()).enumerate() {
+			let arg_length = match &token {
+				Token::Constant(c) => {
+					stack.push((result.len(), Some(*c)));
+					result.push((token, span));
+					continue;
+				},
+				// Folding big integers would turn them into floats, so they're left alone like variables.
+				Token::Integer(_) | Token::Variable(_) => {
+					stack.push((result.len(), None));
+					result.push((token, span));
+					continue;
+				},
+				Token::ArgCount(_) => {
+					// Hold onto this until we know if the function's sticking around.
+					arg_count = Some((token, span));
+					continue;
+				},
+				Token::Operator(o) => o.get_parameters(),
+				Token::Function(f) if f.is_variadic() => match &arg_count {
+					Some((Token::ArgCount(n), _)) => *n,
+					_ => return Err(CalcError::Parse { message: "Variadic function is missing its argument count.", span }),
+				},
+				Token::Function(f) => f.get_parameters(),
+				Token::UserFunction(_) => match &arg_count {
+					Some((Token::ArgCount(n), _)) => *n,
+					_ => return Err(CalcError::Parse { message: "Function call is missing its argument count.", span }),
+				},
+				Token::Matrix { rows, cols } => rows * cols,
+				Token::Index(n) => *n,
+				_ => {
+					result.push((token, span));
+					continue;
+				},
+			};
+			
+			if stack.len() < arg_length {
+				let name = match &token { Token::Operator(o) => o.symbol(), Token::Function(f) => f.name(), Token::Index(_) => "index", Token::Matrix { .. } => "matrix", _ => "?" };
+				return Err(CalcError::Arity { name, expected: arg_length, found: stack.len(), span });
+			}
+			
+			let args = stack.split_off(stack.len() - arg_length);
+			let start = args.first().map_or(result.len(), |a| a.0);
+			
+			// Everything's known, so just do it now. (Except for functions somebody defined, since those can change later, and matrices, which aren't numbers.)
+			if args.iter().all(|a| a.1.is_some()) && !matches!(token, Token::UserFunction(_) | Token::Matrix { .. } | Token::Index(_)) {
+				let values: Vec<f64> = args.iter().map(|a| a.1.unwrap()).collect();
+				let value = match &token {
+					Token::Operator(o) => operate_in(*o, values, &settings),
+					Token::Function(f) => call_in(*f, values, &settings),
+					_ => unreachable!(),
+				};
+				match value {
+					Ok(value) => {
+						result.truncate(start);
+						result.push((Token::Constant(value), None));
+						stack.push((start, Some(value)));
+						arg_count = None;
+						continue;
+					},
+					// This might never get calculated, so it's only a problem once it does.
+					Err(_) if conditional[i] => {},
+					Err(e) => return Err(match span { Some(s) => e.with_span(s), None => e }),
+				}
+			}
+			
+			// An `if` that already knows its condition is just the side it picks.
+			if let (Token::Function(operator::Function::If | operator::Function::IfThen), [condition, then, rest @ ..]) = (&token, &args[..]) {
+				match (condition.1, rest.first()) {
+					(Some(c), _) if c != 0.0 && !c.is_nan() => {
+						result.truncate(rest.first().map_or(result.len(), |o| o.0));
+						result.drain(condition.0..then.0);
+						stack.push((start, then.1));
+						continue;
+					},
+					(Some(0.0), Some(otherwise)) => {
+						result.drain(condition.0..otherwise.0);
+						stack.push((start, otherwise.1));
+						continue;
+					},
+					(Some(_), _) => {
+						result.truncate(start);
+						result.push((Token::Constant(f64::NAN), span));
+						stack.push((start, Some(f64::NAN)));
+						continue;
+					},
+					(None, _) => {},
+				}
+			}
+			
+			// Identities. Either keep the left side (and drop the right), or keep the right side (and drop the left).
+			if let (Token::Operator(o), [left, right]) = (&token, &args[..]) {
+				use operator::Operator;
+				
+				let keep_left = match o {
+					Operator::Add | Operator::Sub => right.1 == Some(0.0),
+					Operator::Mul | Operator::Div | Operator::Pow => right.1 == Some(1.0),
+					_ => false,
+				};
+				let keep_right = match o {
+					Operator::Add => left.1 == Some(0.0),
+					Operator::Mul | Operator::Rot => left.1 == Some(1.0),
+					_ => false,
+				};
+				
+				if keep_left {
+					result.truncate(right.0);
+					stack.push(*left);
+					continue;
+				} else if keep_right {
+					result.drain(left.0..right.0);
+					stack.push((left.0, right.1));
+					continue;
+				}
+			}
+			if let (Token::Operator(operator::Operator::Unp), [only]) = (&token, &args[..]) {
+				stack.push(*only);
+				continue;
+			}
+			
+			if let Some(count) = arg_count.take() {
+				result.push(count);
+			}
+			result.push((token, span));
+			stack.push((start, None));
+		}
+		
+		let (tokens, spans) = result.into_iter().unzip();
+		self.tokens = tokens;
+		self.spans = spans;
+		
+		Ok(old_length - self.tokens.len())
+	}
 	
 	/// Calculates an expression, returning a `f64`. If you want to use variables, you can also pass Some variables.
-	pub fn calculate(&self, variables: Option<&ExpressionVariables>) -> Result<f64, &'static str> {
-		let mut stack: Vec<f64> = Vec::new();
+	pub fn calculate(&self, variables: Option<&Environment>) -> Result<f64, CalcError> {
+		self.evaluate::<f64>(variables)
+	}
+	
+	/// Calculates an expression with any kind of `Number`, like `evaluate::<Complex>`. `calculate` is just `evaluate::<f64>`.
+	pub fn evaluate<T: Number>(&self, variables: Option<&Environment>) -> Result<T, CalcError> {
+		self.evaluate_with(variables, &[], &[], 0)
+	}
+	
+	/// `evaluate`, but inside a function somebody defined. `parameters` get their values from `args`, and `depth` is how many function calls deep we are, so `Environment::call` can stop runaway recursion.
+	pub(crate) fn evaluate_with<T: Number>(&self, variables: Option<&Environment>, parameters: &[String], args: &[T], depth: usize) -> Result<T, CalcError> {
+		let mut stack: Vec<T> = Vec::new();
+		let mut arg_count: Option<usize> = None;
+		let settings = variables.map_or_else(Settings::default, |vars| *vars.settings());
+		let branches = self.branches();
+		// Which `if`s already picked a side, so there's nothing left for them to do once they're reached.
+		let mut picked = vec![false; self.tokens.len()];
 		
-		for i in 0..self.tokens.len() {
-			let token = self.tokens[i];
+		let mut i = 0;
+		while i < self.tokens.len() {
+			let token = &self.tokens[i];
+			let span = self.spans[i];
 			
 			match token {
-				Token::Constant(c) => stack.push(c),
+				Token::Constant(c) => stack.push(T::try_from_f64(*c, &settings).map_err(|e| match span { Some(s) => e.with_span(s), None => e })?),
+				Token::Integer(n) => stack.push(T::from_integer(n, &settings).map_err(|e| match span { Some(s) => e.with_span(s), None => e })?),
+				Token::Function(_) if picked[i] => {},
 				Token::Operator(o) => {
 					// TODO: move most of this back into operator impl.
 					let arg_length = o.get_parameters(); // oops
 					let mut args = Vec::new();
 					
 					if stack.len() < arg_length {
-						return Err("Not enough arguments.");
+						return Err(CalcError::Arity { name: o.symbol(), expected: arg_length, found: stack.len(), span });
 					}
 					
 					// Get arguments from stack.
@@ -281,16 +1060,23 @@ impl Expression {
 					// It has to be reversed because reasons.
 					args.reverse();
 					
-					let result = o.calculate(args)?;
+					let result = operate_in(*o, args, &settings).map_err(|e| match span { Some(s) => e.with_span(s), None => e })?;
 					stack.push(result);
 				},
 				Token::Function(f) => {
 					// TODO: ewwwwww do not like just copy-pasting this
-					let arg_length = f.get_parameters(); // oops
+					let arg_length = if f.is_variadic() {
+						match arg_count.take() {
+							Some(n) => n,
+							None => return Err(CalcError::Parse { message: "Variadic function is missing its argument count.", span }),
+						}
+					} else {
+						f.get_parameters()
+					};
 					let mut args = Vec::new();
 					
 					if stack.len() < arg_length {
-						return Err("Not enough arguments.");
+						return Err(CalcError::Arity { name: f.name(), expected: arg_length, found: stack.len(), span });
 					}
 					
 					// Get arguments from stack.
@@ -300,34 +1086,130 @@ impl Expression {
 					// It has to be reversed because reasons.
 					args.reverse();
 					
-					let result = f.calculate(args)?;
+					let result = call_in(*f, args, &settings).map_err(|e| match span { Some(s) => e.with_span(s), None => e })?;
 					stack.push(result);
 				},
 				Token::Variable(v) => {
-					if let Some(variables) = variables {
-						match variables.get(&v) {
-							Some(val) => stack.push(*val),
-							None => return Err("Undefined variable."),
-						}
+					// A function's parameters hide any variables with the same name.
+					if let Some(p) = parameters.iter().position(|p| p == v) {
+						stack.push(args[p].clone());
 					} else {
-						return Err("Encountered variable without Some ExpressionVariables.");
+						match variables.and_then(|vars| vars.get(v)) {
+							Some(val) => stack.push(T::try_from_f64(val, &settings).map_err(|e| match span { Some(s) => e.with_span(s), None => e })?),
+							None => {
+								let stored = variables.and_then(|vars| vars.matrix(v).and_then(T::from_matrix).or_else(|| vars.quantity(v).and_then(T::from_quantity)));
+								// Variables win over names like units, so somebody's `m = 5` still works.
+								match stored.or_else(|| T::named(v)) {
+									Some(val) => stack.push(val),
+									None => return Err(CalcError::UndefinedVariable { name: v.clone(), span }),
+								}
+							},
+						}
+					}
+				},
+				Token::UserFunction(name) => {
+					let arg_length = match arg_count.take() {
+						Some(n) if n <= stack.len() => n,
+						Some(_) => return Err(CalcError::UserFunction { name: name.clone(), message: "Not enough arguments for", span }),
+						None => return Err(CalcError::Parse { message: "Function call is missing its argument count.", span }),
+					};
+					let args = stack.split_off(stack.len() - arg_length);
+					
+					let result = match variables {
+						Some(vars) => vars.call(name, &args, span, depth)?,
+						None => return Err(CalcError::UserFunction { name: name.clone(), message: "Unknown function", span }),
+					};
+					stack.push(result);
+				},
+				Token::Matrix { rows, cols } => {
+					let n = rows * cols;
+					if stack.len() < n {
+						return Err(CalcError::Arity { name: "matrix", expected: n, found: stack.len(), span });
 					}
+					let values = stack.split_off(stack.len() - n);
+					stack.push(T::matrix(*rows, *cols, values).map_err(|e| match span { Some(s) => e.with_span(s), None => e })?);
 				},
+				Token::Index(n) => {
+					if stack.len() < *n {
+						return Err(CalcError::Arity { name: "index", expected: *n, found: stack.len(), span });
+					}
+					let args = stack.split_off(stack.len() - n);
+					stack.push(args[0].index(&args[1..]).map_err(|e| match span { Some(s) => e.with_span(s), None => e })?);
+				},
+				Token::ArgCount(n) => arg_count = Some(*n),
 				_ => {},
 			}
+			
+			// `if` only calculates the side it picks. Unless the condition's a matrix, which picks value by value, so it needs both.
+			i = match branches[i] {
+				Some(Branch::Condition { otherwise, end }) => match stack.last().and_then(T::to_f64) {
+					Some(condition) => {
+						stack.pop();
+						picked[end] = true;
+						if condition != 0.0 && !condition.is_nan() {
+							i + 1
+						} else if condition == 0.0 && otherwise != end {
+							otherwise
+						} else {
+							stack.push(T::missing().map_err(|e| match self.spans[end] { Some(s) => e.with_span(s), None => e })?);
+							end + 1
+						}
+					},
+					None => i + 1,
+				},
+				Some(Branch::Then { end }) if picked[end] => end + 1,
+				_ => i + 1,
+			};
 		}
 		
-		if !stack.is_empty() {
-			if stack.len() > 1 { // this is unnecessary but I think it helps sometimes
-				Err("Too many leftover results.")
-			} else {
-				Ok(stack[0])
-			}
+		if stack.len() == 1 {
+			Ok(stack.pop().unwrap())
 		} else {
-			Err("No calculation result.")
+			Err(CalcError::Leftover { count: stack.len() })
 		}
 	}
 	
+	/// Finds every `if` in the expression, so calculating can skip the side it doesn't pick. Lines up with `tokens`.
+	/// 
+	/// The arguments are all still there in RPN, in order, so this just marks where each one ends.
+	/// If the expression's broken, nothing gets marked, and calculating finds out it's broken the normal way.
+	pub(crate) fn branches(&self) -> Vec<Option<Branch>> {
+		let mut branches = vec![None; self.tokens.len()];
+		// Mirrors the stack `calculate` would have. Each entry is where that value's last token is.
+		let mut stack: Vec<usize> = Vec::new();
+		let mut arg_count: Option<usize> = None;
+		
+		for (i, token) in self.tokens.iter().enumerate() {
+			let arg_length = match token {
+				Token::Constant(_) | Token::Integer(_) | Token::Variable(_) => 0,
+				Token::Operator(o) => o.get_parameters(),
+				Token::Function(f) if f.is_variadic() => arg_count.take().unwrap_or(0),
+				Token::Function(f) => f.get_parameters(),
+				Token::UserFunction(_) => arg_count.take().unwrap_or(0),
+				Token::Matrix { rows, cols } => rows * cols,
+				Token::Index(n) => *n,
+				Token::ArgCount(n) => {
+					arg_count = Some(*n);
+					continue;
+				},
+				_ => continue,
+			};
+			
+			if stack.len() < arg_length {
+				return vec![None; self.tokens.len()];
+			}
+			let args = stack.split_off(stack.len() - arg_length);
+			// Without a side for false, `otherwise` is the `if` itself.
+			if matches!(token, Token::Function(operator::Function::If | operator::Function::IfThen)) {
+				branches[args[0]] = Some(Branch::Condition { otherwise: args[1] + 1, end: i });
+				branches[args[1]] = Some(Branch::Then { end: i });
+			}
+			stack.push(i);
+		}
+		
+		branches
+	}
+	
 	/// Debug garbage
 	pub fn print(&self) {
 		println!("{:?}", self.tokens);
@@ -339,3 +1221,107 @@ impl std::fmt::Debug for Expression {
         write!(f, "Expr {:?}", self.tokens)
     }
 }
+
+/// Turns the expression back into infix, with only the parentheses it needs. Reading the result back in gives an equivalent expression.
+impl std::fmt::Display for Expression {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		// Things like constants and functions never need parentheses around them.
+		const ATOM: usize = usize::MAX;
+		
+		// Each entry is some text, and the precedence of the operator on the outside of it.
+		let mut stack: Vec<(String, usize)> = Vec::new();
+		let mut arg_count: Option<usize> = None;
+		
+		// If the expression's broken, there might not be enough to pop. Better to show something than nothing.
+		fn pop(stack: &mut Vec<(String, usize)>) -> (String, usize) {
+			stack.pop().unwrap_or_else(|| ("?".to_owned(), ATOM))
+		}
+		
+		for token in self.tokens.iter() {
+			match token {
+				Token::Constant(c) => {
+					if c.is_nan() {
+						stack.push(("nan".to_owned(), ATOM));
+					} else if c.is_sign_negative() {
+						// This'll read back in as a unary minus, so it needs the same care.
+						stack.push((c.to_string(), operator::Operator::Unm.get_precedence()));
+					} else {
+						stack.push((c.to_string(), ATOM));
+					}
+				},
+				Token::Integer(n) if n.is_negative() => stack.push((n.to_string(), operator::Operator::Unm.get_precedence())),
+				Token::Integer(n) => stack.push((n.to_string(), ATOM)),
+				Token::Variable(v) => stack.push((v.clone(), ATOM)),
+				Token::Operator(o) => {
+					let precedence = o.get_precedence();
+					
+					if o.is_postfix() {
+						let (a, a_precedence) = pop(&mut stack);
+						let a = if a_precedence < precedence { format!("({})", a) } else { a };
+						
+						// Word units need a space, or `x rad` would read back in as a variable called `xrad`.
+						let space = if o.symbol().starts_with(char::is_alphabetic) { " " } else { "" };
+						stack.push((format!("{}{}{}", a, space, o.symbol()), precedence));
+					} else if o.get_parameters() == 1 {
+						let (a, a_precedence) = pop(&mut stack);
+						let a = if a_precedence < precedence { format!("({})", a) } else { a };
+						
+						// Same for `not`, or `not x` would be a variable called `notx`.
+						let space = if o.symbol().starts_with(char::is_alphabetic) { " " } else { "" };
+						stack.push((format!("{}{}{}", o.symbol(), space, a), precedence));
+					} else {
+						let (b, b_precedence) = pop(&mut stack);
+						let (a, a_precedence) = pop(&mut stack);
+						let left = o.get_associativity() == operator::OperatorAssociativity::Left;
+						
+						let a = if a_precedence < precedence || (a_precedence == precedence && !left) { format!("({})", a) } else { a };
+						let b = if b_precedence < precedence || (b_precedence == precedence && left) { format!("({})", b) } else { b };
+						
+						// Spaces around + and - make the rest easier to read.
+						let space = if precedence <= operator::Operator::Add.get_precedence() { " " } else { "" };
+						stack.push((format!("{}{}{}{}{}", a, space, o.symbol(), space, b), precedence));
+					}
+				},
+				Token::Function(func) => {
+					let arg_length = if func.is_variadic() { arg_count.take().unwrap_or(0) } else { func.get_parameters() };
+					
+					if !func.is_variadic() && arg_length == 0 {
+						stack.push((func.name().to_owned(), ATOM));
+					} else {
+						let mut args: Vec<String> = (0..arg_length).map(|_| pop(&mut stack).0).collect();
+						args.reverse();
+						
+						stack.push((format!("{}({})", func.name(), args.join(", ")), ATOM));
+					}
+				},
+				Token::UserFunction(name) => {
+					let mut args: Vec<String> = (0..arg_count.take().unwrap_or(0)).map(|_| pop(&mut stack).0).collect();
+					args.reverse();
+					
+					stack.push((format!("{}({})", name, args.join(", ")), ATOM));
+				},
+				Token::Matrix { rows, cols } => {
+					let mut values: Vec<String> = (0..rows * cols).map(|_| pop(&mut stack).0).collect();
+					values.reverse();
+					
+					let rows: Vec<String> = values.chunks(*cols).map(|row| row.join(", ")).collect();
+					stack.push((format!("[{}]", rows.join("; ")), ATOM));
+				},
+				Token::Index(n) => {
+					let mut indices: Vec<String> = (1..*n).map(|_| pop(&mut stack).0).collect();
+					indices.reverse();
+					let (a, a_precedence) = pop(&mut stack);
+					// A plain number in front of brackets reads back in as multiplying, so those get parentheses too.
+					let a = if a_precedence < ATOM || a.starts_with(|c: char| c.is_ascii_digit() || c == '.') { format!("({})", a) } else { a };
+					
+					stack.push((format!("{}[{}]", a, indices.join(", ")), ATOM));
+				},
+				Token::ArgCount(n) => arg_count = Some(*n),
+				_ => {},
+			}
+		}
+		
+		let results: Vec<String> = stack.into_iter().map(|s| s.0).collect();
+		write!(f, "{}", results.join(", "))
+	}
+}
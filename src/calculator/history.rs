@@ -0,0 +1,74 @@
+use std::collections::VecDeque;
+
+/// Undo and redo for anything that can be cloned.
+/// 
+/// Before changing something, hand the old version to `record`. Then `undo` and `redo` trade the current version for an older or newer one.
+/// Only the last `depth` changes are kept; older ones fall off the end.
+#[derive(Debug, Clone, PartialEq)]
+pub struct History<T> {
+	undos: VecDeque<T>,
+	redos: Vec<T>,
+	depth: usize,
+}
+
+impl<T: Clone> History<T> {
+	pub const DEFAULT_DEPTH: usize = 64;
+	
+	pub fn new(depth: usize) -> History<T> {
+		History { undos: VecDeque::new(), redos: Vec::new(), depth }
+	}
+	
+	/// How many changes this remembers.
+	pub fn depth(&self) -> usize {
+		self.depth
+	}
+	
+	/// Changes how many changes this remembers, forgetting the oldest ones if there's too many now.
+	pub fn set_depth(&mut self, depth: usize) {
+		self.depth = depth;
+		while self.undos.len() > depth {
+			self.undos.pop_front();
+		}
+		self.redos.truncate(depth);
+	}
+	
+	/// Remembers `old` as the state before a change. Anything that could've been redone is gone now.
+	pub fn record(&mut self, old: T) {
+		self.redos.clear();
+		if self.depth == 0 {
+			return;
+		}
+		if self.undos.len() == self.depth {
+			self.undos.pop_front();
+		}
+		self.undos.push_back(old);
+	}
+	
+	/// Goes back one change. Gives back the state to switch to, or `None` if there's nothing to undo.
+	pub fn undo(&mut self, current: T) -> Option<T> {
+		let old = self.undos.pop_back()?;
+		self.redos.push(current);
+		Some(old)
+	}
+	
+	/// Goes forward one change, after an `undo`. Gives back the state to switch to, or `None` if there's nothing to redo.
+	pub fn redo(&mut self, current: T) -> Option<T> {
+		let new = self.redos.pop()?;
+		self.undos.push_back(current);
+		Some(new)
+	}
+	
+	pub fn can_undo(&self) -> bool {
+		!self.undos.is_empty()
+	}
+	
+	pub fn can_redo(&self) -> bool {
+		!self.redos.is_empty()
+	}
+}
+
+impl<T: Clone> Default for History<T> {
+	fn default() -> History<T> {
+		History::new(History::<T>::DEFAULT_DEPTH)
+	}
+}
@@ -0,0 +1,277 @@
+use core::convert::TryFrom;
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_integer::Integer as _;
+use num_traits::{FromPrimitive, One, Pow, Signed, ToPrimitive, Zero};
+
+use super::error::CalcError;
+use super::number::{Number, Settings, logic, choose};
+use super::operator::{Operator, Function};
+
+/// How many bits an `Integer` has, and whether it has a sign. Written the same as Rust's, like `i32` or `u8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntegerType {
+	pub bits: u32,
+	pub signed: bool,
+}
+
+impl IntegerType {
+	pub const DEFAULT: IntegerType = IntegerType { bits: 64, signed: true };
+	
+	pub fn min(&self) -> BigInt {
+		if self.signed { -(BigInt::one() << (self.bits - 1)) } else { BigInt::zero() }
+	}
+	
+	pub fn max(&self) -> BigInt {
+		if self.signed { (BigInt::one() << (self.bits - 1)) - 1 } else { (BigInt::one() << self.bits) - 1 }
+	}
+	
+	/// The bits of `value` as an unsigned number, so -1 as an `i8` is 255. This is what hex, octal, and binary show.
+	pub fn bit_pattern(&self, value: &BigInt) -> BigInt {
+		value.mod_floor(&(BigInt::one() << self.bits))
+	}
+	
+	/// Wraps `value` around to fit, the same way the hardware would.
+	fn wrap(&self, value: &BigInt) -> BigInt {
+		let value = self.bit_pattern(value);
+		if self.signed && value > self.max() { value - (BigInt::one() << self.bits) } else { value }
+	}
+	
+	fn overflow_message(&self) -> &'static str {
+		match (self.bits, self.signed) {
+			(8, true) => "Overflow! That doesn't fit in an i8.",
+			(16, true) => "Overflow! That doesn't fit in an i16.",
+			(32, true) => "Overflow! That doesn't fit in an i32.",
+			(64, true) => "Overflow! That doesn't fit in an i64.",
+			(128, true) => "Overflow! That doesn't fit in an i128.",
+			(8, false) => "Overflow! That doesn't fit in a u8.",
+			(16, false) => "Overflow! That doesn't fit in a u16.",
+			(32, false) => "Overflow! That doesn't fit in a u32.",
+			(64, false) => "Overflow! That doesn't fit in a u64.",
+			(128, false) => "Overflow! That doesn't fit in a u128.",
+			_ => "Overflow! That doesn't fit.",
+		}
+	}
+}
+
+impl TryFrom<&str> for IntegerType {
+	type Error = CalcError;
+	
+	fn try_from(s: &str) -> Result<Self, Self::Error> {
+		let signed = match s.chars().next() {
+			Some('i') => true,
+			Some('u') => false,
+			_ => return Err(CalcError::Parse { message: "Integer types look like i32 or u8.", span: None }),
+		};
+		match s[1..].parse::<u32>() {
+			Ok(bits @ (8 | 16 | 32 | 64 | 128)) => Ok(IntegerType { bits, signed }),
+			_ => Err(CalcError::Parse { message: "Integers can have 8, 16, 32, 64, or 128 bits.", span: None }),
+		}
+	}
+}
+
+impl fmt::Display for IntegerType {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}{}", if self.signed { "i" } else { "u" }, self.bits)
+	}
+}
+
+/// What happens when a result doesn't fit in an `Integer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+	/// Wrap around, like `wrapping_add`.
+	Wrap,
+	/// Give an error, like `checked_add`.
+	Check,
+}
+
+/// A whole number with a fixed number of bits, for "programmer mode": `Expression::evaluate::<Integer>`.
+/// 
+/// Which type (like `i32` or `u8`) and what to do about overflow are in `Settings`, like `Decimal`'s precision.
+/// Division rounds toward zero, `>>` keeps the sign of signed numbers, and everything else works like it does in Rust.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Integer(BigInt);
+
+impl Integer {
+	/// Makes an integer of `settings.integer_type`. If `value` doesn't fit, it wraps around or gives an error, depending on `settings.overflow`.
+	pub fn new(value: BigInt, settings: &Settings) -> Result<Integer, CalcError> {
+		let integer_type = settings.integer_type;
+		if value >= integer_type.min() && value <= integer_type.max() {
+			return Ok(Integer(value));
+		}
+		match settings.overflow {
+			Overflow::Wrap => Ok(Integer(integer_type.wrap(&value))),
+			Overflow::Check => Err(CalcError::Domain { message: integer_type.overflow_message(), span: None }),
+		}
+	}
+	
+	pub fn value(&self) -> &BigInt {
+		&self.0
+	}
+	
+	/// Writes this in decimal, hex, octal, and binary, side by side. Negative numbers show their two's complement bits (as `integer_type`) in everything but decimal.
+	pub fn to_bases_string(&self, integer_type: IntegerType) -> String {
+		let bits = integer_type.bit_pattern(&self.0);
+		format!("{}   0x{}   0o{:o}   0b{}", self.0, group_digits(&format!("{:X}", bits)), bits, group_digits(&format!("{:b}", bits)))
+	}
+	
+	/// `x` shifted `n` bits. Anything past the width just shifts everything out, so shifting by a billion doesn't need a billion bits.
+	fn shift(operator: Operator, x: &BigInt, n: &BigInt, settings: &Settings) -> Result<Integer, CalcError> {
+		if n.is_negative() {
+			return Err(CalcError::Domain { message: "Can't shift by a negative amount.", span: None });
+		}
+		let n = n.to_u32().unwrap_or(u32::MAX).min(settings.integer_type.bits);
+		if operator == Operator::Shl { Integer::new(x << n, settings) } else { Integer::new(x >> n, settings) }
+	}
+	
+	fn root(x: &BigInt, n: &BigInt, settings: &Settings) -> Result<Integer, CalcError> {
+		let n = match n.to_u32() {
+			Some(n) if n > 0 => n,
+			_ => return Err(CalcError::Domain { message: "Roots have to be positive whole numbers.", span: None }),
+		};
+		if x.is_negative() && n & 1 == 0 {
+			return Err(CalcError::Domain { message: "Negative numbers don't have even roots.", span: None });
+		}
+		// Rounds toward zero, same as dividing does.
+		Integer::new(x.nth_root(n), settings)
+	}
+	
+	fn pow(x: &BigInt, n: &BigInt, settings: &Settings) -> Result<Integer, CalcError> {
+		if n.is_negative() {
+			return Err(CalcError::Domain { message: "Integer mode can't do negative powers.", span: None });
+		}
+		if settings.overflow == Overflow::Wrap {
+			let integer_type = settings.integer_type;
+			let modulus = BigInt::one() << integer_type.bits;
+			return Ok(Integer(integer_type.wrap(&integer_type.bit_pattern(x).modpow(n, &modulus))));
+		}
+		
+		// Anything past 1 grows at least a bit per power, so big powers won't fit no matter what.
+		match n.to_u32() {
+			Some(n) if n <= 128 || x.abs() <= BigInt::one() => Integer::new(Pow::pow(x, n), settings),
+			_ if x.is_zero() => Ok(Integer(BigInt::zero())),
+			_ if x.is_one() => Ok(Integer(BigInt::one())),
+			_ if x == &BigInt::from(-1) => Ok(Integer(if n.is_even() { BigInt::one() } else { x.clone() })),
+			_ => Err(CalcError::Domain { message: settings.integer_type.overflow_message(), span: None }),
+		}
+	}
+}
+
+/// Puts an underscore between every 4 digits (from the right), like `1010_0101`. Numbers written like that can be read right back in.
+fn group_digits(digits: &str) -> String {
+	let mut grouped = String::new();
+	for (i, c) in digits.chars().enumerate() {
+		if i > 0 && (digits.len() - i) & 3 == 0 {
+			grouped.push('_');
+		}
+		grouped.push(c);
+	}
+	grouped
+}
+
+/// Bitwise operators on integers with as many bits as they need, for modes that don't have a fixed width. Negative numbers act like two's complement with the sign bit going on forever.
+pub(crate) fn bitwise(operator: Operator, args: &[BigInt]) -> Result<BigInt, CalcError> {
+	/// Shifting further than this would make a number that's way too big to be useful.
+	const MAX_SHIFT: u32 = 1 << 16;
+	
+	match operator {
+		Operator::BitAnd => Ok(&args[0] & &args[1]),
+		Operator::BitOr => Ok(&args[0] | &args[1]),
+		Operator::Xor => Ok(&args[0] ^ &args[1]),
+		Operator::BitNot => Ok(!&args[0]),
+		Operator::Shl | Operator::Shr => {
+			let n = match args[1].to_u32() {
+				Some(n) if n <= MAX_SHIFT => n,
+				_ if args[1].is_negative() => return Err(CalcError::Domain { message: "Can't shift by a negative amount.", span: None }),
+				_ => return Err(CalcError::Domain { message: "That's too far to shift.", span: None }),
+			};
+			Ok(if operator == Operator::Shl { &args[0] << n } else { &args[0] >> n })
+		},
+		_ => unreachable!("{:?} isn't a bitwise operator", operator),
+	}
+}
+
+impl Number for Integer {
+	/// Truncates, so this never fails. It doesn't know the type, so it's only for little things like the 1 from a comparison. Constants and variables use `try_from_f64` instead, which is pickier.
+	fn from_f64(value: f64) -> Integer {
+		Integer(BigInt::from_f64(value.trunc()).unwrap_or_default())
+	}
+	
+	fn try_from_f64(value: f64, settings: &Settings) -> Result<Integer, CalcError> {
+		match BigInt::from_f64(value) {
+			Some(n) if value.fract() == 0.0 => Integer::new(n, settings),
+			_ => Err(CalcError::Domain { message: "Integer mode only has whole numbers.", span: None }),
+		}
+	}
+	
+	fn from_integer(value: &BigInt, settings: &Settings) -> Result<Integer, CalcError> {
+		Integer::new(value.clone(), settings)
+	}
+	
+	fn to_f64(&self) -> Option<f64> {
+		self.0.to_f64()
+	}
+	
+	fn missing() -> Result<Integer, CalcError> {
+		Err(CalcError::Domain { message: "None of the pieces are true, and integer mode doesn't have nan.", span: None })
+	}
+	
+	fn operate(operator: Operator, args: &[Integer], settings: &Settings) -> Result<Integer, CalcError> {
+		let values: Vec<&BigInt> = args.iter().map(|a| &a.0).collect();
+		match operator {
+			Operator::Add => Integer::new(values[0] + values[1], settings),
+			Operator::Sub => Integer::new(values[0] - values[1], settings),
+			Operator::Mul => Integer::new(values[0] * values[1], settings),
+			Operator::Div | Operator::Mod if values[1].is_zero() => Err(CalcError::Domain { message: "Can't divide by zero.", span: None }),
+			Operator::Div => Integer::new(values[0] / values[1], settings),
+			Operator::Mod => Integer::new(values[0] % values[1], settings),
+			Operator::Pow => Integer::pow(values[0], values[1], settings),
+			Operator::Rot => Integer::root(values[1], values[0], settings),
+			Operator::Unp => Ok(args[0].clone()),
+			Operator::Unm => Integer::new(-values[0], settings),
+			Operator::BitAnd | Operator::BitOr | Operator::Xor => Integer::new(bitwise(operator, &[values[0].clone(), values[1].clone()])?, settings),
+			// Unsigned numbers don't have a sign bit to flip, so ~ is counting down from the top instead.
+			Operator::BitNot if !settings.integer_type.signed => Integer::new(settings.integer_type.max() - values[0], settings),
+			Operator::BitNot => Integer::new(!values[0], settings),
+			Operator::Shl | Operator::Shr => Integer::shift(operator, values[0], values[1], settings),
+			Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge | Operator::Eq | Operator::Ne |
+			Operator::And | Operator::Or | Operator::Not => Ok(logic(operator, args)),
+			Operator::To => Err(CalcError::Domain { message: "Converting units needs units mode.", span: None }),
+			// `number::operate_in` turns angles into the right unit itself, and never passes them along.
+			Operator::Angle(_) => unreachable!("angles are handled by operate_in"),
+		}
+	}
+	
+	fn call(function: Function, args: &[Integer], settings: &Settings) -> Result<Integer, CalcError> {
+		let values: Vec<&BigInt> = args.iter().map(|a| &a.0).collect();
+		match function {
+			Function::Abs => Integer::new(values[0].abs(), settings),
+			Function::Sgn => Ok(Integer(values[0].signum())),
+			Function::Min => Ok(Integer(values.into_iter().min().unwrap().clone())),
+			Function::Max => Ok(Integer(values.into_iter().max().unwrap().clone())),
+			Function::Clamp => Ok(Integer(values[0].max(values[1]).min(values[2]).clone())),
+			Function::Sum => Integer::new(values.into_iter().sum(), settings),
+			Function::Mean => Integer::new(values.iter().copied().sum::<BigInt>() / BigInt::from(values.len()), settings),
+			Function::Hypot => Integer::new(values.iter().map(|v| *v * *v).sum::<BigInt>().sqrt(), settings),
+			Function::Gcd => Ok(Integer(values.iter().fold(BigInt::zero(), |a, b| a.gcd(b)))),
+			Function::Lcm => Integer::new(values.iter().fold(BigInt::one(), |a, b| a.lcm(b)), settings),
+			Function::Root => Integer::root(values[0], values[1], settings),
+			Function::Sqrt => Integer::root(values[0], &BigInt::from(2), settings),
+			Function::Cbrt => Integer::root(values[0], &BigInt::from(3), settings),
+			Function::Re | Function::Conj | Function::Det | Function::Transpose => Ok(args[0].clone()),
+			Function::Norm => Integer::new(values[0].abs(), settings),
+			Function::Dot => Integer::new(values[0] * values[1], settings),
+			Function::Im => Ok(Integer(BigInt::zero())),
+			Function::Arg if !values[0].is_negative() => Ok(Integer(BigInt::zero())),
+			Function::If | Function::IfThen => choose(args),
+			_ => Err(CalcError::Domain { message: "That function doesn't work in integer mode.", span: None }),
+		}
+	}
+}
+
+impl fmt::Display for Integer {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{}", self.0)
+	}
+}
@@ -0,0 +1,827 @@
+use core::cmp::Ordering;
+use std::fmt;
+
+use super::error::CalcError;
+use super::number::{Number, Settings, choose};
+use super::operator::{Operator, Function};
+
+/// For when `solve` can't find just one answer.
+pub const SINGULAR: &str = "That matrix is singular, so there isn't just one solution.";
+/// For when `lstsq` can't find just one best fit.
+pub const RANK_DEFICIENT: &str = "Those columns aren't independent, so there isn't just one best fit.";
+pub const NOT_POSITIVE_DEFINITE: &str = "chol needs a positive definite matrix.";
+const ILL_CONDITIONED: &str = "That matrix is too close to singular for an accurate answer.";
+
+/// Past this condition number, only about 4 of an answer's 16 digits mean anything, so it's better to say so than to give back noise.
+const MAX_CONDITION: f64 = 1e12;
+
+/// A grid of numbers, for matrix mode. Plain numbers are just 1x1 matrices, so everything in matrix mode is one of these.
+/// 
+/// Vectors are matrices with one row (or one column). `[1, 2, 3]` is a row, and `[1; 2; 3]` is a column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Matrix {
+	rows: usize,
+	cols: usize,
+	/// Row by row, so `[1, 2; 3, 4]` is `[1, 2, 3, 4]`.
+	values: Vec<f64>,
+}
+
+impl Matrix {
+	/// # Panics
+	/// If there aren't exactly `rows * cols` values.
+	pub fn new(rows: usize, cols: usize, values: Vec<f64>) -> Matrix {
+		assert_eq!(values.len(), rows * cols, "a {}x{} matrix needs {} values", rows, cols, rows * cols);
+		Matrix { rows, cols, values }
+	}
+	
+	pub fn scalar(value: f64) -> Matrix {
+		Matrix::new(1, 1, vec![value])
+	}
+	
+	pub fn identity(n: usize) -> Matrix {
+		let mut values = vec![0.0; n * n];
+		for i in 0..n {
+			values[i * n + i] = 1.0;
+		}
+		Matrix::new(n, n, values)
+	}
+	
+	pub fn rows(&self) -> usize {
+		self.rows
+	}
+	
+	pub fn cols(&self) -> usize {
+		self.cols
+	}
+	
+	/// Every value, row by row.
+	pub fn values(&self) -> &[f64] {
+		&self.values
+	}
+	
+	/// The value in `row` and `col`, counting from 0.
+	pub fn get(&self, row: usize, col: usize) -> f64 {
+		self.values[row * self.cols + col]
+	}
+	
+	/// Rows, then columns.
+	pub fn size(&self) -> (usize, usize) {
+		(self.rows, self.cols)
+	}
+	
+	pub fn is_scalar(&self) -> bool {
+		self.size() == (1, 1)
+	}
+	
+	pub fn is_vector(&self) -> bool {
+		self.rows == 1 || self.cols == 1
+	}
+	
+	fn shape_error(&self, other: &Matrix) -> CalcError {
+		CalcError::Shape { left: self.size(), right: other.size(), span: None }
+	}
+	
+	/// How many rows (and columns) this has, as long as it's square.
+	fn square(&self) -> Result<usize, CalcError> {
+		if self.rows == self.cols {
+			Ok(self.rows)
+		} else {
+			Err(CalcError::Domain { message: "That only works on square matrices.", span: None })
+		}
+	}
+	
+	/// Does `f` to two matrices value by value. A plain number goes with every value of the other side, so `2 * [1, 2]` is `[2, 4]`.
+	fn zip_with(&self, other: &Matrix, f: impl Fn(f64, f64) -> Result<f64, CalcError>) -> Result<Matrix, CalcError> {
+		let (rows, cols) = match (self.is_scalar(), other.is_scalar()) {
+			(true, _) => other.size(),
+			(_, true) => self.size(),
+			_ if self.size() == other.size() => self.size(),
+			_ => return Err(self.shape_error(other)),
+		};
+		
+		let values = (0..rows * cols)
+			.map(|i| f(self.values[if self.is_scalar() { 0 } else { i }], other.values[if other.is_scalar() { 0 } else { i }]))
+			.collect::<Result<Vec<f64>, CalcError>>()?;
+		Ok(Matrix::new(rows, cols, values))
+	}
+	
+	/// Same as `zip_with`, but for one or more matrices at once. The plain numbers go with every value, and everything else has to be the same size.
+	fn zip_all(args: &[Matrix], f: impl Fn(&[f64]) -> Result<f64, CalcError>) -> Result<Matrix, CalcError> {
+		let mut size = (1, 1);
+		for a in args.iter().filter(|a| !a.is_scalar()) {
+			if size == (1, 1) {
+				size = a.size();
+			} else if a.size() != size {
+				return Err(CalcError::Shape { left: size, right: a.size(), span: None });
+			}
+		}
+		
+		let mut values = Vec::with_capacity(size.0 * size.1);
+		let mut scratch = vec![0.0; args.len()];
+		for i in 0..size.0 * size.1 {
+			for (s, a) in scratch.iter_mut().zip(args.iter()) {
+				*s = a.values[if a.is_scalar() { 0 } else { i }];
+			}
+			values.push(f(&scratch)?);
+		}
+		Ok(Matrix::new(size.0, size.1, values))
+	}
+	
+	pub fn transpose(&self) -> Matrix {
+		let mut values = Vec::with_capacity(self.values.len());
+		for col in 0..self.cols {
+			for row in 0..self.rows {
+				values.push(self.get(row, col));
+			}
+		}
+		Matrix::new(self.cols, self.rows, values)
+	}
+	
+	/// The matrix product, `self * other`. `self` needs as many columns as `other` has rows.
+	pub fn multiply(&self, other: &Matrix) -> Result<Matrix, CalcError> {
+		if self.cols != other.rows {
+			return Err(self.shape_error(other));
+		}
+		
+		let mut values = vec![0.0; self.rows * other.cols];
+		for row in 0..self.rows {
+			for col in 0..other.cols {
+				values[row * other.cols + col] = (0..self.cols).map(|k| self.get(row, k) * other.get(k, col)).sum();
+			}
+		}
+		Ok(Matrix::new(self.rows, other.cols, values))
+	}
+	
+	/// Swaps two rows of `values`, which has `cols` columns.
+	fn swap_rows(values: &mut [f64], cols: usize, a: usize, b: usize) {
+		for col in 0..cols {
+			values.swap(a * cols + col, b * cols + col);
+		}
+	}
+	
+	/// Which row from `start` down has the biggest value in `col`. Picking that one to eliminate with keeps the rounding errors small.
+	fn pivot(values: &[f64], cols: usize, col: usize, start: usize, rows: usize) -> usize {
+		(start..rows).max_by(|&a, &b| {
+			values[a * cols + col].abs().partial_cmp(&values[b * cols + col].abs()).unwrap_or(Ordering::Equal)
+		}).unwrap_or(start)
+	}
+	
+	/// The biggest value that still counts as 0 next to everything else in this matrix. Anything smaller is just rounding errors.
+	fn tolerance(&self) -> f64 {
+		let biggest = self.values.iter().fold(0.0, |m: f64, v| m.max(v.abs()));
+		biggest * self.rows.max(self.cols) as f64 * f64::EPSILON
+	}
+	
+	/// Whether `x` is small enough to count as 0, using `tolerance`. NaN counts, so it never gets divided by.
+	fn negligible(x: f64, tolerance: f64) -> bool {
+		x.abs().partial_cmp(&tolerance) != Some(Ordering::Greater)
+	}
+	
+	pub fn is_symmetric(&self) -> bool {
+		let tolerance = self.tolerance();
+		self.rows == self.cols && (0..self.rows).all(|i| (0..i).all(|j| Matrix::negligible(self.get(i, j) - self.get(j, i), tolerance)))
+	}
+	
+	/// The biggest sum of absolute values down any column. Cheap to work out, and good enough for condition numbers.
+	fn column_norm(&self) -> f64 {
+		(0..self.cols).map(|col| (0..self.rows).map(|row| self.get(row, col).abs()).sum()).fold(0.0, f64::max)
+	}
+	
+	/// `P A = L U`, from Gaussian elimination with partial pivoting.
+	fn lu(&self) -> Result<Lu, CalcError> {
+		let n = self.square()?;
+		let tolerance = self.tolerance();
+		let mut lu = Lu { n, values: self.values.clone(), permutation: (0..n).collect(), sign: 1.0, singular: false };
+		let a = &mut lu.values;
+		
+		for k in 0..n {
+			let p = Matrix::pivot(a, n, k, k, n);
+			if p != k {
+				Matrix::swap_rows(a, n, p, k);
+				lu.permutation.swap(p, k);
+				lu.sign = -lu.sign;
+			}
+			
+			let pivot = a[k * n + k];
+			if Matrix::negligible(pivot, tolerance) {
+				lu.singular = true;
+			}
+			// Everything below this pivot is already 0 (it's the biggest in its column), so there's nothing to eliminate.
+			if pivot == 0.0 {
+				continue;
+			}
+			for i in k + 1..n {
+				let factor = a[i * n + k] / pivot;
+				a[i * n + k] = factor;
+				for j in k + 1..n {
+					a[i * n + j] -= factor * a[k * n + j];
+				}
+			}
+		}
+		
+		Ok(lu)
+	}
+	
+	/// `lu`, but it has to be good for solving things. Gives back the inverse too, since that's needed to check.
+	/// 
+	/// Singular matrices get `message`. Matrices that are only nearly singular get an error too, since the answer would be mostly rounding errors.
+	fn checked_lu(&self, message: &'static str) -> Result<(Lu, Matrix), CalcError> {
+		let lu = self.lu()?;
+		if lu.singular {
+			return Err(CalcError::Domain { message, span: None });
+		}
+		
+		let inverse = lu.solve(&Matrix::identity(lu.n));
+		let condition = self.column_norm() * inverse.column_norm();
+		if condition.partial_cmp(&MAX_CONDITION) != Some(Ordering::Less) {
+			return Err(CalcError::Domain { message: ILL_CONDITIONED, span: None });
+		}
+		Ok((lu, inverse))
+	}
+	
+	pub fn determinant(&self) -> Result<f64, CalcError> {
+		Ok(self.lu()?.determinant())
+	}
+	
+	pub fn inverse(&self) -> Result<Matrix, CalcError> {
+		Ok(self.checked_lu("That matrix can't be inverted.")?.1)
+	}
+	
+	/// The L, U, and P in `P A = L U`. L has 1s down its diagonal, and P is the rows of the identity, shuffled.
+	pub fn lu_parts(&self) -> Result<(Matrix, Matrix, Matrix), CalcError> {
+		let lu = self.lu()?;
+		Ok((lu.lower(), lu.upper(), lu.permutation()))
+	}
+	
+	/// `b` as columns to solve for, along with whether it got turned around to get that way. A row works like a column, so `solve(A, [1, 2])` does what it looks like.
+	fn right_side(&self, b: &Matrix) -> Result<(Matrix, bool), CalcError> {
+		if b.rows == self.rows {
+			Ok((b.clone(), false))
+		} else if b.rows == 1 && b.cols == self.rows {
+			Ok((b.transpose(), true))
+		} else {
+			Err(self.shape_error(b))
+		}
+	}
+	
+	/// `x` in `self * x = b`. `b` can have more than one column, to solve for them all at once.
+	pub fn solve(&self, b: &Matrix) -> Result<Matrix, CalcError> {
+		self.square()?;
+		let (b, turned) = self.right_side(b)?;
+		let (lu, _) = self.checked_lu(SINGULAR)?;
+		
+		let x = lu.solve(&b);
+		Ok(if turned { x.transpose() } else { x })
+	}
+	
+	/// `A = Q R` with Householder reflections. Q is square and orthogonal, R is the same size as `self`, and R's diagonal is never negative.
+	pub fn qr(&self) -> (Matrix, Matrix) {
+		let (m, n) = self.size();
+		let mut r = self.values.clone();
+		let mut q = Matrix::identity(m).values;
+		
+		for k in 0..n.min(m.saturating_sub(1)) {
+			// Reflect the column from the diagonal down onto its first value, which zeroes out the rest.
+			let mut v: Vec<f64> = (k..m).map(|i| r[i * n + k]).collect();
+			let length = v.iter().fold(0.0, |a: f64, b| a.hypot(*b));
+			if length == 0.0 {
+				continue;
+			}
+			v[0] += if v[0] > 0.0 { length } else { -length };
+			let v_length = v.iter().fold(0.0, |a: f64, b| a.hypot(*b));
+			for x in v.iter_mut() {
+				*x /= v_length;
+			}
+			
+			// R = H R and Q = Q H, where H = I - 2 v v^T. H only touches rows (or columns) from k on.
+			for j in 0..n {
+				let dot: f64 = (k..m).map(|i| v[i - k] * r[i * n + j]).sum();
+				for i in k..m {
+					r[i * n + j] -= 2.0 * v[i - k] * dot;
+				}
+			}
+			for i in 0..m {
+				let dot: f64 = (k..m).map(|j| q[i * m + j] * v[j - k]).sum();
+				for j in k..m {
+					q[i * m + j] -= 2.0 * dot * v[j - k];
+				}
+			}
+			for i in k + 1..m {
+				r[i * n + k] = 0.0;
+			}
+		}
+		
+		// Flipping a row of R and the matching column of Q doesn't change Q R, and this way there's only one answer.
+		for i in 0..m.min(n) {
+			if r[i * n + i] < 0.0 {
+				for j in 0..n {
+					r[i * n + j] = -r[i * n + j];
+				}
+				for row in 0..m {
+					q[row * m + i] = -q[row * m + i];
+				}
+			}
+		}
+		
+		(Matrix::new(m, m, q), Matrix::new(m, n, r))
+	}
+	
+	/// The L in `A = L L^T`, for symmetric positive definite matrices. It's about twice as quick as LU, and it's the usual way to tell if a matrix is positive definite.
+	pub fn cholesky(&self) -> Result<Matrix, CalcError> {
+		let n = self.square()?;
+		if !self.is_symmetric() {
+			return Err(CalcError::Domain { message: "chol needs a symmetric matrix.", span: None });
+		}
+		
+		let tolerance = self.tolerance();
+		let mut l = vec![0.0; n * n];
+		for j in 0..n {
+			let diagonal = self.get(j, j) - (0..j).map(|k| l[j * n + k] * l[j * n + k]).sum::<f64>();
+			if Matrix::negligible(diagonal, tolerance) || diagonal < 0.0 {
+				return Err(CalcError::Domain { message: NOT_POSITIVE_DEFINITE, span: None });
+			}
+			let diagonal = diagonal.sqrt();
+			l[j * n + j] = diagonal;
+			
+			for i in j + 1..n {
+				l[i * n + j] = (self.get(i, j) - (0..j).map(|k| l[i * n + k] * l[j * n + k]).sum::<f64>()) / diagonal;
+			}
+		}
+		
+		Ok(Matrix::new(n, n, l))
+	}
+	
+	/// How many of the rows (or columns) are independent. Gaussian elimination, with anything within `tolerance` of 0 counting as 0.
+	pub fn rank(&self) -> usize {
+		let (m, n) = self.size();
+		let tolerance = self.tolerance();
+		let mut a = self.values.clone();
+		let mut rank = 0;
+		
+		for k in 0..n {
+			if rank == m {
+				break;
+			}
+			let p = Matrix::pivot(&a, n, k, rank, m);
+			if Matrix::negligible(a[p * n + k], tolerance) {
+				continue;
+			}
+			Matrix::swap_rows(&mut a, n, p, rank);
+			for i in rank + 1..m {
+				let factor = a[i * n + k] / a[rank * n + k];
+				for j in k..n {
+					a[i * n + j] -= factor * a[rank * n + j];
+				}
+			}
+			rank += 1;
+		}
+		
+		rank
+	}
+	
+	/// The `x` that makes `self * x` as close to `b` as it can get, for when there are more equations than unknowns. Done with QR, which is more accurate than solving `A^T A x = A^T b`.
+	pub fn least_squares(&self, b: &Matrix) -> Result<Matrix, CalcError> {
+		let (m, n) = self.size();
+		if m < n {
+			return Err(CalcError::Domain { message: "lstsq needs at least as many rows as columns.", span: None });
+		}
+		let (b, turned) = self.right_side(b)?;
+		let (q, r) = self.qr();
+		
+		// R's diagonal says how independent the columns are.
+		let diagonal: Vec<f64> = (0..n).map(|i| r.get(i, i)).collect();
+		let smallest = diagonal.iter().cloned().fold(f64::INFINITY, f64::min);
+		let biggest = diagonal.iter().cloned().fold(0.0, f64::max);
+		if Matrix::negligible(smallest, self.tolerance()) {
+			return Err(CalcError::Domain { message: RANK_DEFICIENT, span: None });
+		}
+		if biggest / smallest >= MAX_CONDITION {
+			return Err(CalcError::Domain { message: ILL_CONDITIONED, span: None });
+		}
+		
+		// Only the top n rows of Q^T b can be matched. The rest is how far off the best fit is.
+		let y = q.transpose().multiply(&b)?;
+		let k = b.cols;
+		let mut x = vec![0.0; n * k];
+		for col in 0..k {
+			for i in (0..n).rev() {
+				let known: f64 = (i + 1..n).map(|j| r.get(i, j) * x[j * k + col]).sum();
+				x[i * k + col] = (y.get(i, col) - known) / r.get(i, i);
+			}
+		}
+		
+		let x = Matrix::new(n, k, x);
+		Ok(if turned { x.transpose() } else { x })
+	}
+	
+	/// Eigenvalues (smallest first) and their eigenvectors (as columns, in the same order). Each eigenvector is 1 long, with its biggest value positive.
+	/// 
+	/// Symmetric matrices use the Jacobi method, which always works and gives eigenvectors at right angles to each other.
+	/// Anything else uses the QR algorithm and then inverse iteration, and has to have real eigenvalues, since matrices are only real numbers.
+	/// If an eigenvalue of one of those shows up more than once, its eigenvector does too.
+	pub fn eigen(&self) -> Result<(Vec<f64>, Matrix), CalcError> {
+		let n = self.square()?;
+		if self.values.iter().any(|v| !v.is_finite()) {
+			return Err(CalcError::Domain { message: "Eigenvalues need every value to be finite.", span: None });
+		}
+		
+		let (values, vectors) = if self.is_symmetric() {
+			self.jacobi()
+		} else {
+			let values = self.qr_eigenvalues()?;
+			let vectors = values.iter().map(|v| self.eigenvector(*v)).collect::<Result<Vec<Vec<f64>>, CalcError>>()?;
+			(values, vectors)
+		};
+		
+		let mut order: Vec<usize> = (0..n).collect();
+		order.sort_by(|a, b| values[*a].partial_cmp(&values[*b]).unwrap_or(Ordering::Equal));
+		
+		let mut columns = vec![0.0; n * n];
+		for (col, i) in order.iter().enumerate() {
+			let vector = normalized(&vectors[*i]);
+			for row in 0..n {
+				columns[row * n + col] = vector[row];
+			}
+		}
+		Ok((order.iter().map(|i| values[*i]).collect(), Matrix::new(n, n, columns)))
+	}
+	
+	/// Spins pairs of rows and columns until everything off the diagonal is 0, keeping track of the spins. The diagonal ends up as the eigenvalues, and the spins as the eigenvectors.
+	fn jacobi(&self) -> (Vec<f64>, Vec<Vec<f64>>) {
+		let n = self.rows;
+		let mut a = self.values.clone();
+		let mut v = Matrix::identity(n).values;
+		let done = f64::EPSILON * self.norm();
+		
+		// This converges really quickly, so 50 sweeps is way more than it'll ever need.
+		for _ in 0..50 {
+			let off_diagonal = (0..n).map(|i| (0..n).filter(|j| *j != i).map(|j| a[i * n + j] * a[i * n + j]).sum::<f64>()).sum::<f64>().sqrt();
+			if off_diagonal <= done {
+				break;
+			}
+			
+			for p in 0..n {
+				for q in p + 1..n {
+					let apq = a[p * n + q];
+					if apq == 0.0 {
+						continue;
+					}
+					// The angle that zeroes out a[p][q]. t is its tangent, picked so the angle's as small as it can be.
+					let theta = (a[q * n + q] - a[p * n + p]) / (2.0 * apq);
+					let t = theta.signum() / (theta.abs() + theta.hypot(1.0));
+					let c = 1.0 / t.hypot(1.0);
+					let s = t * c;
+					
+					for k in 0..n {
+						let (kp, kq) = (a[k * n + p], a[k * n + q]);
+						a[k * n + p] = c * kp - s * kq;
+						a[k * n + q] = s * kp + c * kq;
+						let (kp, kq) = (v[k * n + p], v[k * n + q]);
+						v[k * n + p] = c * kp - s * kq;
+						v[k * n + q] = s * kp + c * kq;
+					}
+					for k in 0..n {
+						let (pk, qk) = (a[p * n + k], a[q * n + k]);
+						a[p * n + k] = c * pk - s * qk;
+						a[q * n + k] = s * pk + c * qk;
+					}
+				}
+			}
+		}
+		
+		let values = (0..n).map(|i| a[i * n + i]).collect();
+		let vectors = (0..n).map(|col| (0..n).map(|row| v[row * n + col]).collect()).collect();
+		(values, vectors)
+	}
+	
+	/// The QR algorithm with Wilkinson shifts. Every step is `A = R Q + shift`, which has the same eigenvalues, and eventually the bottom row is all 0 but its eigenvalue.
+	/// That one gets taken off, and it keeps going with what's left.
+	fn qr_eigenvalues(&self) -> Result<Vec<f64>, CalcError> {
+		let mut a = self.clone();
+		let mut values = Vec::with_capacity(self.rows);
+		let mut iterations = 0;
+		let small = |x: f64, y: f64, z: f64| x.abs() <= f64::EPSILON * (y.abs() + z.abs());
+		
+		while a.rows > 0 {
+			let n = a.rows;
+			if n == 1 {
+				values.push(a.get(0, 0));
+				break;
+			}
+			
+			let (p, q) = (n - 2, n - 1);
+			if small(a.get(q, p), a.get(p, p), a.get(q, q)) {
+				values.push(a.get(q, q));
+				a = a.top_left(q);
+				iterations = 0;
+				continue;
+			}
+			
+			// The bottom 2x2 is only attached to the rest by zeroes, so its eigenvalues can be worked out directly.
+			let (w, x, y, z) = (a.get(p, p), a.get(p, q), a.get(q, p), a.get(q, q));
+			let half_trace = (w + z) / 2.0;
+			let discriminant = half_trace * half_trace - (w * z - x * y);
+			if n == 2 || small(a.get(p, p - 1), a.get(p - 1, p - 1), w) {
+				// Rounding can push a repeated eigenvalue's discriminant a little under 0.
+				if discriminant < -f64::EPSILON * half_trace * half_trace.max(1.0) {
+					return Err(CalcError::Domain { message: "That matrix has complex eigenvalues, and matrices only hold real numbers.", span: None });
+				}
+				let root = discriminant.max(0.0).sqrt();
+				values.push(half_trace - root);
+				values.push(half_trace + root);
+				a = a.top_left(p);
+				iterations = 0;
+				continue;
+			}
+			
+			iterations += 1;
+			if iterations > 30 {
+				return Err(CalcError::Domain { message: "The eigenvalues didn't settle down.", span: None });
+			}
+			// Whichever eigenvalue of the bottom 2x2 is closer to the corner. Every now and then, something a bit off, so it can't get stuck going in circles.
+			let shift = if iterations == 10 || iterations == 20 {
+				z + a.get(q, p).abs() + a.get(p, p - 1).abs()
+			} else if discriminant < 0.0 {
+				z
+			} else {
+				let root = discriminant.sqrt();
+				if (half_trace + root - z).abs() < (half_trace - root - z).abs() { half_trace + root } else { half_trace - root }
+			};
+			
+			let shifted = a.zip_with(&Matrix::identity(n), |a, i| Ok(a - shift * i))?;
+			let (q, r) = shifted.qr();
+			a = r.multiply(&q)?.zip_with(&Matrix::identity(n), |a, i| Ok(a + shift * i))?;
+		}
+		
+		Ok(values)
+	}
+	
+	/// Inverse iteration: solving `(A - value I) x = x` over and over blows up the part of `x` that's the eigenvector, and shrinks the rest.
+	fn eigenvector(&self, value: f64) -> Result<Vec<f64>, CalcError> {
+		let n = self.rows;
+		let shifted = self.zip_with(&Matrix::identity(n), |a, i| Ok(a - value * i))?;
+		let mut lu = shifted.lu()?;
+		// That's singular on purpose, so the zeroes in U get nudged just enough to divide by.
+		let nudge = self.norm().max(1.0) * f64::EPSILON;
+		for i in 0..n {
+			let u = &mut lu.values[i * n + i];
+			if u.abs() < nudge {
+				*u = if *u < 0.0 { -nudge } else { nudge };
+			}
+		}
+		
+		// Anything that isn't at right angles to the eigenvector works to start with. All the same values usually isn't.
+		let mut x = Matrix::new(n, 1, (0..n).map(|i| 1.0 + i as f64 / n as f64).collect());
+		for _ in 0..3 {
+			x = lu.solve(&x);
+			x = Matrix::new(n, 1, normalized(&x.values));
+		}
+		Ok(x.values)
+	}
+	
+	/// The first `n` rows and columns.
+	fn top_left(&self, n: usize) -> Matrix {
+		Matrix::new(n, n, (0..n).flat_map(|row| self.values[row * self.cols..row * self.cols + n].iter().cloned()).collect())
+	}
+	
+	/// `self^power`, for square matrices and whole powers. Negative powers are powers of the inverse.
+	pub fn power(&self, power: f64) -> Result<Matrix, CalcError> {
+		let n = self.square()?;
+		if power.fract() != 0.0 || !power.is_finite() {
+			return Err(CalcError::Domain { message: "Matrices can only be raised to whole powers.", span: None });
+		}
+		
+		let mut base = if power < 0.0 { self.inverse()? } else { self.clone() };
+		let mut result = Matrix::identity(n);
+		let mut power = power.abs() as u64;
+		// Squaring over and over, so A^1000 is only about 20 multiplications.
+		while power > 0 {
+			if power & 1 == 1 {
+				result = result.multiply(&base)?;
+			}
+			power >>= 1;
+			if power > 0 {
+				base = base.multiply(&base)?;
+			}
+		}
+		Ok(result)
+	}
+	
+	/// The dot product of two vectors with the same number of values. Rows and columns can be mixed.
+	pub fn dot(&self, other: &Matrix) -> Result<f64, CalcError> {
+		if !self.is_vector() || !other.is_vector() || self.values.len() != other.values.len() {
+			return Err(self.shape_error(other));
+		}
+		Ok(self.values.iter().zip(other.values.iter()).map(|(a, b)| a * b).sum())
+	}
+	
+	/// The cross product of two 3D vectors. The answer's a row or a column, same as `self`.
+	pub fn cross(&self, other: &Matrix) -> Result<Matrix, CalcError> {
+		if !self.is_vector() || !other.is_vector() || self.values.len() != 3 || other.values.len() != 3 {
+			return Err(CalcError::Domain { message: "cross needs two 3D vectors.", span: None });
+		}
+		let (a, b) = (&self.values, &other.values);
+		Ok(Matrix::new(self.rows, self.cols, vec![
+			a[1] * b[2] - a[2] * b[1],
+			a[2] * b[0] - a[0] * b[2],
+			a[0] * b[1] - a[1] * b[0],
+		]))
+	}
+	
+	/// The length of a vector. For a matrix, this is the same thing with every value (the Frobenius norm).
+	pub fn norm(&self) -> f64 {
+		self.values.iter().fold(0.0, |a, b| a.hypot(*b))
+	}
+	
+	/// Turns a 1-based index into a 0-based one, as long as it's in `0..length`.
+	fn position(index: &Matrix, length: usize) -> Result<usize, CalcError> {
+		match index.to_f64() {
+			Some(i) if i.fract() == 0.0 && i >= 1.0 && i <= length as f64 => Ok(i as usize - 1),
+			Some(i) if i.fract() == 0.0 && i >= 1.0 => Err(CalcError::Domain { message: "That index is past the end.", span: None }),
+			_ => Err(CalcError::Domain { message: "Indexes have to be whole numbers, starting at 1.", span: None }),
+		}
+	}
+}
+
+/// `P A = L U`. L and U share one grid, since L's diagonal is all 1s anyway.
+struct Lu {
+	n: usize,
+	values: Vec<f64>,
+	/// Row `i` of `P A` is row `permutation[i]` of `A`.
+	permutation: Vec<usize>,
+	/// -1 if an odd number of rows got swapped, for the determinant.
+	sign: f64,
+	/// Whether some pivot was close enough to 0 to not be trusted.
+	singular: bool,
+}
+
+impl Lu {
+	fn lower(&self) -> Matrix {
+		let n = self.n;
+		Matrix::new(n, n, (0..n * n).map(|i| match (i / n, i - i / n * n) {
+			(row, col) if row == col => 1.0,
+			(row, col) if row > col => self.values[i],
+			_ => 0.0,
+		}).collect())
+	}
+	
+	fn upper(&self) -> Matrix {
+		let n = self.n;
+		Matrix::new(n, n, (0..n * n).map(|i| if i / n <= i - i / n * n { self.values[i] } else { 0.0 }).collect())
+	}
+	
+	fn permutation(&self) -> Matrix {
+		let n = self.n;
+		let mut values = vec![0.0; n * n];
+		for (row, col) in self.permutation.iter().enumerate() {
+			values[row * n + col] = 1.0;
+		}
+		Matrix::new(n, n, values)
+	}
+	
+	fn determinant(&self) -> f64 {
+		(0..self.n).fold(self.sign, |d, i| d * self.values[i * self.n + i])
+	}
+	
+	/// Solves `L U x = P b` for every column of `b`, by substituting forwards and then backwards. Doesn't check if it's singular, so that's up to whoever's calling.
+	fn solve(&self, b: &Matrix) -> Matrix {
+		let (n, k) = (self.n, b.cols);
+		let mut x = vec![0.0; n * k];
+		for col in 0..k {
+			for i in 0..n {
+				let known: f64 = (0..i).map(|j| self.values[i * n + j] * x[j * k + col]).sum();
+				x[i * k + col] = b.get(self.permutation[i], col) - known;
+			}
+			for i in (0..n).rev() {
+				let known: f64 = (i + 1..n).map(|j| self.values[i * n + j] * x[j * k + col]).sum();
+				x[i * k + col] = (x[i * k + col] - known) / self.values[i * n + i];
+			}
+		}
+		Matrix::new(n, k, x)
+	}
+}
+
+/// `vector`, scaled to be 1 long, and flipped so its biggest value is positive. Eigenvectors can point either way, and this picks one.
+fn normalized(vector: &[f64]) -> Vec<f64> {
+	let length = vector.iter().fold(0.0, |a: f64, b| a.hypot(*b));
+	let biggest = vector.iter().fold(0.0, |m: f64, v| if v.abs() > m.abs() { *v } else { m });
+	let scale = if biggest < 0.0 { -length } else { length };
+	vector.iter().map(|v| v / scale).collect()
+}
+
+impl Number for Matrix {
+	fn from_f64(value: f64) -> Matrix {
+		Matrix::scalar(value)
+	}
+	
+	fn to_f64(&self) -> Option<f64> {
+		if self.is_scalar() { Some(self.values[0]) } else { None }
+	}
+	
+	/// Only plain numbers can go in a matrix. (So no putting matrices inside matrices.)
+	fn matrix(rows: usize, cols: usize, values: Vec<Matrix>) -> Result<Matrix, CalcError> {
+		match values.iter().map(Matrix::to_f64).collect::<Option<Vec<f64>>>() {
+			Some(values) => Ok(Matrix::new(rows, cols, values)),
+			None => Err(CalcError::Domain { message: "Matrices can only be made out of numbers.", span: None }),
+		}
+	}
+	
+	/// Vectors take one index. Matrices take a row and a column, or just a row, which gives the whole row back.
+	fn index(&self, indices: &[Matrix]) -> Result<Matrix, CalcError> {
+		match indices {
+			[i] if self.is_vector() => Ok(Matrix::scalar(self.values[Matrix::position(i, self.values.len())?])),
+			[row] => {
+				let row = Matrix::position(row, self.rows)?;
+				Ok(Matrix::new(1, self.cols, self.values[row * self.cols..(row + 1) * self.cols].to_vec()))
+			},
+			[row, col] => Ok(Matrix::scalar(self.get(Matrix::position(row, self.rows)?, Matrix::position(col, self.cols)?))),
+			_ => Err(CalcError::Domain { message: "Matrices only take a row and a column.", span: None }),
+		}
+	}
+	
+	fn to_matrix(&self) -> Option<Matrix> {
+		Some(self.clone())
+	}
+	
+	fn from_matrix(matrix: &Matrix) -> Option<Matrix> {
+		Some(matrix.clone())
+	}
+	
+	/// `*`, `/`, and `^` are the matrix versions. Everything else goes value by value.
+	fn operate(operator: Operator, args: &[Matrix], _settings: &Settings) -> Result<Matrix, CalcError> {
+		if args.iter().all(Matrix::is_scalar) {
+			let values: Vec<f64> = args.iter().map(|a| a.values[0]).collect();
+			return Ok(Matrix::scalar(operator.calculate(&values)?));
+		}
+		
+		match operator {
+			Operator::Mul if !args[0].is_scalar() && !args[1].is_scalar() => args[0].multiply(&args[1]),
+			// A / B is A * inv(B). A plain number on the left just scales inv(B).
+			Operator::Div if !args[1].is_scalar() => {
+				let inverse = args[1].inverse()?;
+				if args[0].is_scalar() {
+					inverse.zip_with(&args[0], |a, b| Ok(a * b))
+				} else {
+					args[0].multiply(&inverse)
+				}
+			},
+			Operator::Pow => match args[1].to_f64() {
+				Some(power) => args[0].power(power),
+				None => Err(CalcError::Domain { message: "Matrices can only be raised to whole powers.", span: None }),
+			},
+			Operator::To => Err(CalcError::Domain { message: "Converting units needs units mode.", span: None }),
+			_ if operator.get_parameters() == 1 => Matrix::zip_all(args, |a| operator.calculate(a)),
+			_ => args[0].zip_with(&args[1], |a, b| operator.calculate(&[a, b])),
+		}
+	}
+	
+	/// Most functions go value by value, like `sin([0, Pi])`. Variadic ones like `sum` with just a matrix add up what's in it.
+	fn call(function: Function, args: &[Matrix], _settings: &Settings) -> Result<Matrix, CalcError> {
+		if args.iter().all(Matrix::is_scalar) {
+			let values: Vec<f64> = args.iter().map(|a| a.values[0]).collect();
+			return Ok(Matrix::scalar(function.calculate(&values)?));
+		}
+		
+		match function {
+			Function::Det => Ok(Matrix::scalar(args[0].determinant()?)),
+			Function::Inv => args[0].inverse(),
+			Function::Transpose => Ok(args[0].transpose()),
+			Function::Dot => Ok(Matrix::scalar(args[0].dot(&args[1])?)),
+			Function::Cross => args[0].cross(&args[1]),
+			Function::Norm => Ok(Matrix::scalar(args[0].norm())),
+			Function::Solve => args[0].solve(&args[1]),
+			Function::Lstsq => args[0].least_squares(&args[1]),
+			Function::LuL => Ok(args[0].lu_parts()?.0),
+			Function::LuU => Ok(args[0].lu_parts()?.1),
+			Function::LuP => Ok(args[0].lu_parts()?.2),
+			Function::QrQ => Ok(args[0].qr().0),
+			Function::QrR => Ok(args[0].qr().1),
+			Function::Chol => args[0].cholesky(),
+			Function::Rank => Ok(Matrix::scalar(args[0].rank() as f64)),
+			Function::Eig => {
+				let values = args[0].eigen()?.0;
+				Ok(Matrix::new(values.len(), 1, values))
+			},
+			Function::Eigvec => Ok(args[0].eigen()?.1),
+			// A plain number condition picks a whole side. A matrix of them picks value by value.
+			Function::If | Function::IfThen if args[0].is_scalar() => choose(args),
+			_ if function.is_variadic() && args.len() == 1 => Ok(Matrix::scalar(function.calculate(&args[0].values)?)),
+			_ => Matrix::zip_all(args, |a| function.calculate(a)),
+		}
+	}
+}
+
+impl fmt::Display for Matrix {
+	/// Writes this the same way it'd be typed in, like `[1, 2; 3, 4]`. Plain numbers are written just like an `f64` would be.
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.is_scalar() {
+			return write!(f, "{}", self.values[0]);
+		}
+		
+		let rows: Vec<String> = self.values.chunks(self.cols)
+			// Adding 0 turns -0 into 0, which shows up a lot after eliminating things.
+			.map(|row| row.iter().map(|v| (v + 0.0).to_string()).collect::<Vec<String>>().join(", "))
+			.collect();
+		write!(f, "[{}]", rows.join("; "))
+	}
+}
@@ -1,2 +1,15 @@
+pub mod error;
 pub mod operator;
 pub mod expression;
+pub mod derivative;
+pub mod compiled;
+pub mod number;
+pub mod complex;
+pub mod rational;
+pub mod decimal;
+pub mod integer;
+pub mod quantity;
+pub mod matrix;
+pub mod environment;
+pub mod history;
+pub mod stack;
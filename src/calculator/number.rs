@@ -0,0 +1,234 @@
+use core::cmp::Ordering;
+
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, ToPrimitive};
+
+use super::error::CalcError;
+use super::operator::{Operator, Function, AngleUnit};
+use super::matrix::Matrix;
+use super::quantity::Quantity;
+use super::decimal::Decimal;
+use super::integer::{IntegerType, Overflow};
+
+/// Everything about how to calculate that isn't the numbers themselves. `Environment` keeps one of these, and it gets handed to every `Number` operation.
+/// 
+/// Most kinds of numbers only care about some of it, or none of it. Floats don't have a precision, for example.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+	/// What trig functions take their angles in.
+	pub angle_unit: AngleUnit,
+	/// How many significant digits `Decimal` results get. (At least 1.)
+	pub precision: usize,
+	/// How many bits an `Integer` has, and whether it has a sign.
+	pub integer_type: IntegerType,
+	/// What happens when an `Integer` doesn't fit.
+	pub overflow: Overflow,
+}
+
+impl Default for Settings {
+	fn default() -> Settings {
+		Settings { angle_unit: AngleUnit::Radians, precision: Decimal::DEFAULT_PRECISION, integer_type: IntegerType::DEFAULT, overflow: Overflow::Check }
+	}
+}
+
+/// Anything an `Expression` can be calculated with. `f64` is the normal one, but there's others, like `Complex`.
+/// 
+/// Parsing doesn't care about any of this, so every kind of number shares the same parser, and `Expression::evaluate` picks which one to use.
+pub trait Number: Clone + Sized {
+	/// Turns a constant (or a variable's value) into this kind of number.
+	fn from_f64(value: f64) -> Self;
+	
+	/// `from_f64`, but for numbers that can't be just anything. `Integer` doesn't have fractions, for example, and has to fit in `settings.integer_type`.
+	fn try_from_f64(value: f64, _settings: &Settings) -> Result<Self, CalcError> {
+		Ok(Self::from_f64(value))
+	}
+	
+	/// A whole number that's too big to be a constant, like `9007199254740993`. (See `Token::Integer`.)
+	/// 
+	/// Modes that can keep it exact should. Otherwise it's only fine if an `f64` has it exactly, same as how `0.1` is close enough but this isn't.
+	fn from_integer(value: &BigInt, settings: &Settings) -> Result<Self, CalcError> {
+		match value.to_f64() {
+			Some(f) if BigInt::from_f64(f).as_ref() == Some(value) => Self::try_from_f64(f, settings),
+			_ => Err(CalcError::Domain { message: "That number's too big to be exact. Integer, decimal, and rational modes can take it.", span: None }),
+		}
+	}
+	
+	/// Turns this back into an `f64`, for storing in variables. `None` if that'd lose something important, like an imaginary part.
+	fn to_f64(&self) -> Option<f64>;
+	
+	/// What a piecewise expression is when none of its pieces are true, like `{x < 0: -x}` when x is 2.
+	/// Usually that's NaN, so a graph has a gap there, but not every kind of number has one.
+	fn missing() -> Result<Self, CalcError> {
+		Ok(Self::from_f64(f64::NAN))
+	}
+	
+	/// A value for a name that isn't a variable, like `km` for `Quantity`. Most kinds of numbers don't have any.
+	fn named(_name: &str) -> Option<Self> {
+		None
+	}
+	
+	/// Builds a `rows` by `cols` matrix out of `values`, which go row by row. Only `Matrix` has matrices.
+	fn matrix(_rows: usize, _cols: usize, _values: Vec<Self>) -> Result<Self, CalcError> {
+		Err(CalcError::Domain { message: "Matrices need matrix mode.", span: None })
+	}
+	
+	/// `self[indices]`, like `v[2]` or `A[1, 2]`.
+	fn index(&self, _indices: &[Self]) -> Result<Self, CalcError> {
+		Err(CalcError::Domain { message: "Only matrices can be indexed.", span: None })
+	}
+	
+	/// This as a `Matrix`, so it can be kept in a variable when `to_f64` can't manage it. `None` for anything that isn't a matrix.
+	fn to_matrix(&self) -> Option<Matrix> {
+		None
+	}
+	
+	/// Gets a matrix back out of a variable. `None` if this kind of number doesn't do matrices.
+	fn from_matrix(_matrix: &Matrix) -> Option<Self> {
+		None
+	}
+	
+	/// This as a `Quantity`, so it can be kept in a variable with its units when `to_f64` can't manage it. `None` for anything that doesn't have units.
+	fn to_quantity(&self) -> Option<Quantity> {
+		None
+	}
+	
+	/// Gets a quantity back out of a variable. `None` if this kind of number doesn't do units.
+	fn from_quantity(_quantity: &Quantity) -> Option<Self> {
+		None
+	}
+	
+	/// Same as `Operator::calculate`, but with this kind of number.
+	fn operate(operator: Operator, args: &[Self], settings: &Settings) -> Result<Self, CalcError>;
+	
+	/// Same as `Function::calculate`, but with this kind of number.
+	fn call(function: Function, args: &[Self], settings: &Settings) -> Result<Self, CalcError>;
+	
+	/// Turns an angle from one unit into another, as precisely as this kind of number can manage. (Radians need pi, so that's usually the hard part.)
+	/// 
+	/// This can be a little more precise than usual, so trig functions get every digit they can. `Unp` rounds it back off.
+	fn convert_angle(self, from: AngleUnit, to: AngleUnit, settings: &Settings) -> Result<Self, CalcError> {
+		// Zero's zero in any unit, and this way modes without pi can still have an angle of zero.
+		if from == to || self.to_f64() == Some(0.0) {
+			return Ok(self);
+		}
+		
+		let full_turn = |unit: AngleUnit| match unit {
+			AngleUnit::Radians => Self::operate(Operator::Mul, &[Self::from_f64(2.0), Self::call(Function::Pi, &[], settings)?], settings),
+			_ => Ok(Self::from_f64(unit.full_turn())),
+		};
+		Self::operate(Operator::Div, &[Self::operate(Operator::Mul, &[self, full_turn(to)?], settings)?, full_turn(from)?], settings)
+	}
+}
+
+/// `Number::operate`, but with angles in `settings.angle_unit`, so `30°` comes out right.
+pub fn operate_in<T: Number>(operator: Operator, mut args: Vec<T>, settings: &Settings) -> Result<T, CalcError> {
+	match operator {
+		Operator::Angle(unit) => T::operate(Operator::Unp, &[args.pop().unwrap().convert_angle(unit, settings.angle_unit, settings)?], settings),
+		_ => T::operate(operator, &args, settings),
+	}
+}
+
+/// `Number::call`, but with angles in `settings.angle_unit`. Trig functions always work in radians, so the units get sorted out on the way in (or out).
+pub fn call_in<T: Number>(function: Function, mut args: Vec<T>, settings: &Settings) -> Result<T, CalcError> {
+	let angle_unit = settings.angle_unit;
+	if let Some(exact) = args.first().and_then(T::to_f64).and_then(|a| exact_trig(function, a, angle_unit)) {
+		return T::try_from_f64(exact, settings);
+	}
+	
+	if function.takes_angle() {
+		args[0] = args[0].clone().convert_angle(angle_unit, AngleUnit::Radians, settings)?;
+	}
+	let result = T::call(function, &args, settings)?;
+	if function.gives_angle() {
+		T::operate(Operator::Unp, &[result.convert_angle(AngleUnit::Radians, angle_unit, settings)?], settings)
+	} else {
+		Ok(result)
+	}
+}
+
+/// Comparisons and logic, for any kind of number that can be put in order. True is 1 and false is 0.
+/// 
+/// Anything that can't be ordered (so NaN) makes the answer NaN, instead of quietly being false. That way a graph of something like `x < nan` has a gap instead of a line at 0.
+pub fn logic<T: Number + PartialOrd>(operator: Operator, args: &[T]) -> T {
+	let zero = T::from_f64(0.0);
+	let truth = |x: &T| x.partial_cmp(&zero).map(|o| o != Ordering::Equal);
+	let both = |f: fn(bool, bool) -> bool| match (truth(&args[0]), truth(&args[1])) {
+		(Some(a), Some(b)) => Some(f(a, b)),
+		_ => None,
+	};
+	
+	let result = match operator {
+		Operator::Lt => args[0].partial_cmp(&args[1]).map(|o| o == Ordering::Less),
+		Operator::Le => args[0].partial_cmp(&args[1]).map(|o| o != Ordering::Greater),
+		Operator::Gt => args[0].partial_cmp(&args[1]).map(|o| o == Ordering::Greater),
+		Operator::Ge => args[0].partial_cmp(&args[1]).map(|o| o != Ordering::Less),
+		Operator::Eq => args[0].partial_cmp(&args[1]).map(|o| o == Ordering::Equal),
+		Operator::Ne => args[0].partial_cmp(&args[1]).map(|o| o != Ordering::Equal),
+		Operator::And => both(|a, b| a && b),
+		Operator::Or => both(|a, b| a || b),
+		Operator::Not => truth(&args[0]).map(|a| !a),
+		_ => unreachable!("{:?} isn't a comparison", operator),
+	};
+	T::from_f64(match result {
+		Some(true) => 1.0,
+		Some(false) => 0.0,
+		None => f64::NAN,
+	})
+}
+
+/// `if(condition, a, b)` or `if(condition, a)`, for any kind of number. Anything but 0 is true.
+/// 
+/// A NaN condition isn't true or false, so that gives `Number::missing`, and so does a false one with no `b`.
+/// `Expression::evaluate` doesn't need this most of the time, since it skips the side it doesn't pick, but a matrix condition picks value by value.
+pub fn choose<T: Number>(args: &[T]) -> Result<T, CalcError> {
+	match args[0].to_f64() {
+		Some(c) if c.is_nan() => T::missing(),
+		Some(c) if c != 0.0 => Ok(args[1].clone()),
+		Some(_) => args.get(2).cloned().map_or_else(T::missing, Ok),
+		None => Err(CalcError::Domain { message: "Conditions have to be real numbers.", span: None }),
+	}
+}
+
+/// Trig functions of whole quarter turns, exactly. Radians can't land on those, but `sin(180°)` should really be 0, not 1.2e-16.
+/// 
+/// `angle` is in `unit`. Anything that isn't a quarter turn (or isn't a trig function) gives `None`.
+pub(crate) fn exact_trig(function: Function, angle: f64, unit: AngleUnit) -> Option<f64> {
+	let quarter = unit.full_turn() / 4.0;
+	if unit == AngleUnit::Radians || angle % quarter != 0.0 {
+		return None;
+	}
+	
+	let (sin, cos) = match (angle / quarter).rem_euclid(4.0) as u8 {
+		0 => (0.0, 1.0),
+		1 => (1.0, 0.0),
+		2 => (0.0, -1.0),
+		_ => (-1.0, 0.0),
+	};
+	match function {
+		Function::Sin => Some(sin),
+		Function::Cos => Some(cos),
+		Function::Tan => Some(sin / cos),
+		Function::Csc => Some(1.0 / sin),
+		Function::Sec => Some(1.0 / cos),
+		Function::Cot => Some(cos / sin),
+		_ => None,
+	}
+}
+
+impl Number for f64 {
+	fn from_f64(value: f64) -> f64 {
+		value
+	}
+	
+	fn to_f64(&self) -> Option<f64> {
+		Some(*self)
+	}
+	
+	fn operate(operator: Operator, args: &[f64], _settings: &Settings) -> Result<f64, CalcError> {
+		operator.calculate(args)
+	}
+	
+	fn call(function: Function, args: &[f64], _settings: &Settings) -> Result<f64, CalcError> {
+		function.calculate(args)
+	}
+}
@@ -1,5 +1,7 @@
 use core::convert::TryFrom;
 
+use super::error::CalcError;
+
 /// This enum contains all the operators that can be used in the RPN calc.
 /// 
 /// When I say "Operator", I mean things like + and -. Functions like sin() go in function.rs.
@@ -10,9 +12,19 @@ pub enum Operator {
 	Mod,
 	Pow, Rot,
 	Unp, Unm,
+	BitAnd, BitOr, Xor, BitNot,
+	Shl, Shr,
+	/// Comparisons. These give 1 for true and 0 for false, so they can be multiplied with and such.
+	Lt, Le, Gt, Ge, Eq, Ne,
+	/// Logic. Anything that isn't 0 counts as true.
+	And, Or, Not,
+	/// `60 mph to m/s`. Only does anything with `Quantity`, since nothing else has units.
+	To,
+	/// A unit after an angle, like `30°` or `1.2rad`. Turns the angle into whatever unit the context uses.
+	Angle(AngleUnit),
 }
 impl TryFrom<&str> for Operator {
-	type Error = &'static str;
+	type Error = CalcError;
 	
 	fn try_from(s: &str) -> Result<Self, Self::Error> {
 		match s {
@@ -20,7 +32,13 @@ impl TryFrom<&str> for Operator {
 			"*" => Ok(Operator::Mul), "/" => Ok(Operator::Div),
 			"%" => Ok(Operator::Mod),
 			"^" => Ok(Operator::Pow), "√" => Ok(Operator::Rot),
-			_ => Err("Could not parse operator. Unknown operator?"),
+			"&" => Ok(Operator::BitAnd), "|" => Ok(Operator::BitOr), "xor" => Ok(Operator::Xor), "~" => Ok(Operator::BitNot),
+			"<<" => Ok(Operator::Shl), ">>" => Ok(Operator::Shr),
+			"<" => Ok(Operator::Lt), "<=" => Ok(Operator::Le), ">" => Ok(Operator::Gt), ">=" => Ok(Operator::Ge), "==" => Ok(Operator::Eq), "!=" => Ok(Operator::Ne),
+			"and" => Ok(Operator::And), "or" => Ok(Operator::Or), "not" => Ok(Operator::Not),
+			"to" => Ok(Operator::To),
+			"°" | "deg" => Ok(Operator::Angle(AngleUnit::Degrees)), "rad" => Ok(Operator::Angle(AngleUnit::Radians)), "grad" => Ok(Operator::Angle(AngleUnit::Gradians)),
+			_ => Err(CalcError::Lex { message: "Could not parse operator. Unknown operator?", span: None }),
 		}
 	}
 }
@@ -32,25 +50,130 @@ impl TryFrom<&str> for Operator {
 pub enum Function {
 	Sin, Cos, Tan,
 	Csc, Sec, Cot,
+	Asin, Acos, Atan,
+	Acsc, Asec, Acot,
+	Sinh, Cosh, Tanh,
+	Asinh, Acosh, Atanh,
+	Sqrt, Cbrt, Exp,
 	Log, Ln,
 	Abs, Sgn,
 	Pi, E,
+	Min, Max, Clamp,
+	Atan2, LogBase, Root,
+	Sum, Mean, Hypot,
+	Gcd, Lcm,
+	Re, Im, Arg, Conj, Cis,
+	Det, Inv, Transpose,
+	Dot, Cross, Norm,
+	/// `solve(A, b)` is `x` in `A x = b`, and `lstsq(A, b)` is the `x` that gets `A x` as close to `b` as it can.
+	Solve, Lstsq,
+	/// The pieces of `P A = L U`, `A = Q R`, and `A = L L^T` (which is `chol`). Each function is one piece, so `lu_l(A) * lu_u(A)` is `lu_p(A) * A`.
+	LuL, LuU, LuP, QrQ, QrR, Chol,
+	/// `eig(A)` is a column of eigenvalues, smallest first, and `eigvec(A)` has their eigenvectors as its columns, in the same order.
+	Rank, Eig, Eigvec,
+	/// `if(condition, a, b)` is `a` if the condition's true (not 0), and `b` if it isn't. Only the side it picks gets calculated, so functions can call themselves in one. (See `Expression::branches`.)
+	If,
+	/// `if(condition, a)`, which has nothing at all when the condition's false. (What "nothing" is depends on the kind of number, see `Number::missing`.)
+	/// Piecewise expressions end with one of these when their last piece has a condition, like `{x < 0: -x}`.
+	IfThen,
 }
 impl TryFrom<&str> for Function {
-	type Error = &'static str;
+	type Error = CalcError;
 	
 	fn try_from(s: &str) -> Result<Self, Self::Error> {
 		match s {
 			"sin" => Ok(Function::Sin), "cos" => Ok(Function::Cos), "tan" => Ok(Function::Tan),
 			"csc" => Ok(Function::Csc), "sec" => Ok(Function::Sec), "cot" => Ok(Function::Cot),
+			"asin" => Ok(Function::Asin), "acos" => Ok(Function::Acos), "atan" => Ok(Function::Atan),
+			"acsc" => Ok(Function::Acsc), "asec" => Ok(Function::Asec), "acot" => Ok(Function::Acot),
+			"sinh" => Ok(Function::Sinh), "cosh" => Ok(Function::Cosh), "tanh" => Ok(Function::Tanh),
+			"asinh" => Ok(Function::Asinh), "acosh" => Ok(Function::Acosh), "atanh" => Ok(Function::Atanh),
+			"sqrt" => Ok(Function::Sqrt), "cbrt" => Ok(Function::Cbrt), "exp" => Ok(Function::Exp),
 			"log" => Ok(Function::Log), "ln"  => Ok(Function::Ln),
 			"abs" => Ok(Function::Abs), "sgn" => Ok(Function::Sgn),
 			"Pi"  => Ok(Function::Pi),  "E"   => Ok(Function::E),
-			_ => Err("Could not parse function. Unknown function?"),
+			"min" => Ok(Function::Min), "max" => Ok(Function::Max), "clamp" => Ok(Function::Clamp),
+			"atan2" => Ok(Function::Atan2), "root" => Ok(Function::Root),
+			"sum" => Ok(Function::Sum), "mean" => Ok(Function::Mean), "hypot" => Ok(Function::Hypot),
+			"gcd" => Ok(Function::Gcd), "lcm" => Ok(Function::Lcm),
+			"re" => Ok(Function::Re), "im" => Ok(Function::Im), "arg" => Ok(Function::Arg), "conj" => Ok(Function::Conj), "cis" => Ok(Function::Cis),
+			"if" => Ok(Function::If),
+			"det" => Ok(Function::Det), "inv" => Ok(Function::Inv), "transpose" => Ok(Function::Transpose),
+			"dot" => Ok(Function::Dot), "cross" => Ok(Function::Cross), "norm" => Ok(Function::Norm),
+			"solve" => Ok(Function::Solve), "lstsq" => Ok(Function::Lstsq),
+			"lu_l" => Ok(Function::LuL), "lu_u" => Ok(Function::LuU), "lu_p" => Ok(Function::LuP),
+			"qr_q" => Ok(Function::QrQ), "qr_r" => Ok(Function::QrR), "chol" => Ok(Function::Chol),
+			"rank" => Ok(Function::Rank), "eig" => Ok(Function::Eig), "eigvec" => Ok(Function::Eigvec),
+			// log(x, base) is LogBase, but we can't know that until we've seen the commas.
+			_ => Err(CalcError::Lex { message: "Could not parse function. Unknown function?", span: None }),
 		}
 	}
 }
 
+/// What angles are measured in. Trig functions take angles in whatever unit the context (usually an `Environment`) says, and things like `atan2` give them back in it too.
+/// 
+/// Without a context, it's radians.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AngleUnit {
+	#[default]
+	Radians,
+	Degrees,
+	Gradians,
+}
+impl TryFrom<&str> for AngleUnit {
+	type Error = CalcError;
+	
+	fn try_from(s: &str) -> Result<Self, Self::Error> {
+		match s {
+			"rad" | "radians" => Ok(AngleUnit::Radians),
+			"deg" | "degrees" | "°" => Ok(AngleUnit::Degrees),
+			"grad" | "gradians" | "gon" => Ok(AngleUnit::Gradians),
+			_ => Err(CalcError::Parse { message: "Angles can be in rad, deg, or grad.", span: None }),
+		}
+	}
+}
+
+impl AngleUnit {
+	/// How many of these make a full circle.
+	pub fn full_turn(&self) -> f64 {
+		match self {
+			AngleUnit::Radians => core::f64::consts::TAU,
+			AngleUnit::Degrees => 360.0,
+			AngleUnit::Gradians => 400.0,
+		}
+	}
+	
+	/// What to multiply an angle in this unit by to get it in `to`.
+	pub fn factor(&self, to: AngleUnit) -> f64 {
+		if *self == to { 1.0 } else { to.full_turn() / self.full_turn() }
+	}
+	
+	/// Gets the name used to write this unit. Degrees also have `°`, which is what `Operator::symbol` uses.
+	pub fn name(&self) -> &'static str {
+		match self {
+			AngleUnit::Radians => "rad",
+			AngleUnit::Degrees => "deg",
+			AngleUnit::Gradians => "grad",
+		}
+	}
+	
+	/// Writes an angle in this unit as degrees, minutes, and seconds, like `12°30'45.5"`. Seconds get rounded to 4 decimal places.
+	pub fn to_dms_string(self, angle: f64) -> String {
+		let degrees = angle * self.factor(AngleUnit::Degrees);
+		if !degrees.is_finite() {
+			return format!("{}°", degrees);
+		}
+		
+		// Rounding everything at once means 59.99999 seconds carries over into the minutes, instead of showing up as 60.
+		let ticks = (degrees.abs() * 3600.0 * 1e4).round();
+		let sign = if degrees < 0.0 && ticks != 0.0 { "-" } else { "" };
+		let seconds = (ticks % 600_000.0) / 1e4;
+		let minutes = (ticks / 600_000.0).floor() % 60.0;
+		let whole = (ticks / 36_000_000.0).floor();
+		format!("{}{}°{}'{}\"", sign, whole, minutes, seconds)
+	}
+}
+
 /// Simple Operator Associativity enum.
 /// 
 /// Associativity dictates how an operator behaves in the absence of parenthesis. ([More info](https://en.wikipedia.org/wiki/Operator_associativity))
@@ -60,12 +183,34 @@ pub enum OperatorAssociativity {
 }
 
 impl Operator {
+	/// Gets the symbol used to write this operator.
+	pub fn symbol(&self) -> &'static str {
+		match self {
+			Operator::Add | Operator::Unp => "+",
+			Operator::Sub | Operator::Unm => "-",
+			Operator::Mul => "*", Operator::Div => "/",
+			Operator::Mod => "%",
+			Operator::Pow => "^", Operator::Rot => "√",
+			Operator::BitAnd => "&", Operator::BitOr => "|", Operator::Xor => "xor", Operator::BitNot => "~",
+			Operator::Shl => "<<", Operator::Shr => ">>",
+			Operator::Lt => "<", Operator::Le => "<=", Operator::Gt => ">", Operator::Ge => ">=", Operator::Eq => "==", Operator::Ne => "!=",
+			Operator::And => "and", Operator::Or => "or", Operator::Not => "not",
+			Operator::To => "to",
+			Operator::Angle(AngleUnit::Degrees) => "°",
+			Operator::Angle(unit) => unit.name(),
+		}
+	}
+	
 	/// Gets the amount of parameters an operator requires.
 	pub fn get_parameters(&self) -> usize {
 		match self {
 			Operator::Add | Operator::Sub |
 			Operator::Mul | Operator::Div | Operator::Mod |
-			Operator::Pow | Operator::Rot => 2,
+			Operator::Pow | Operator::Rot |
+			Operator::BitAnd | Operator::BitOr | Operator::Xor |
+			Operator::Shl | Operator::Shr |
+			Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge | Operator::Eq | Operator::Ne |
+			Operator::And | Operator::Or | Operator::To => 2,
 			_ => 1,
 		}
 	}
@@ -75,28 +220,60 @@ impl Operator {
 	/// This is extremely useful for coverting infix expressions into reverse polish notation ones. It controls which functions get evaluated first, essentially implementing PEMDAS.
 	pub fn get_precedence(&self) -> usize {
 		match self {
-			Operator::Add | Operator::Sub => 2,
-			Operator::Mul | Operator::Div | Operator::Mod => 3,
-			Operator::Pow | Operator::Rot => 5,
-			// Unary operators should have the highest precedence.
-			_ => 7,
+			// Logic is lowest, and comparisons go right above it, like in Python. So x < 0 or x > 1 and not y == 2 is (x < 0) or ((x > 1) and (not (y == 2))).
+			// Converting goes last of all, so 1 km + 1 mi to m converts the whole thing.
+			Operator::To => 0,
+			Operator::Or => 1,
+			Operator::And => 2,
+			Operator::Not => 3,
+			Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge | Operator::Eq | Operator::Ne => 4,
+			// Bitwise stuff goes below arithmetic, like in C, so 1 << 2 + 3 is 1 << 5. (But & is above |, so masks work the way they look.)
+			// Unlike C, it's above comparisons, so x & 1 == 0 checks if x is even.
+			Operator::BitOr => 5,
+			Operator::Xor => 6,
+			Operator::BitAnd => 7,
+			Operator::Shl | Operator::Shr => 8,
+			Operator::Add | Operator::Sub => 9,
+			Operator::Mul | Operator::Div | Operator::Mod => 10,
+			// Unary operators go between multiplication and powers, so -x^2 is -(x^2) but -2*3 is (-2)*3.
+			Operator::Unp | Operator::Unm | Operator::BitNot => 11,
+			Operator::Pow | Operator::Rot => 12,
+			// Units stick to whatever's right before them, so 2x° is 2(x°).
+			Operator::Angle(_) => 13,
 		}
 	}
 	
+	/// Whether this is a comparison or logic, which only ever give back 1 or 0 (or NaN).
+	pub fn is_logic(&self) -> bool {
+		matches!(self,
+			Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge | Operator::Eq | Operator::Ne |
+			Operator::And | Operator::Or | Operator::Not
+		)
+	}
+	
+	/// Whether this goes after what it works on, like `30°`, instead of before it, like `-x`.
+	pub fn is_postfix(&self) -> bool {
+		matches!(self, Operator::Angle(_))
+	}
+	
 	/// Gets the associativity of an operator.
 	/// 
 	/// For more information on operator associativity, check the OperatorAssociativity enum page.
 	pub fn get_associativity(&self) -> OperatorAssociativity {
 		match self {
-			Operator::Add | Operator::Sub | Operator::Mul | Operator::Div | Operator::Mod => OperatorAssociativity::Left,
+			Operator::Add | Operator::Sub | Operator::Mul | Operator::Div | Operator::Mod |
+			Operator::BitAnd | Operator::BitOr | Operator::Xor | Operator::Shl | Operator::Shr |
+			// Comparisons don't chain like in Python. 1 < 2 < 3 is (1 < 2) < 3, which happens to be true, but 3 > 2 > 1 isn't.
+			Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge | Operator::Eq | Operator::Ne |
+			Operator::And | Operator::Or | Operator::To => OperatorAssociativity::Left,
 			_ => OperatorAssociativity::Right,
 		}
 	}
 	
 	/// Calculates the result of using this operator on a stack.
 	/// 
-	/// There's definitely a better way of doing this. I need to look into how to pass a variable amount of parameters.
-	pub fn calculate(&self, args: Vec<f64>) -> Result<f64, &'static str> {
+	/// `args` is just the top of the stack (in the order they were pushed), so nothing has to be allocated for this.
+	pub fn calculate(&self, args: &[f64]) -> Result<f64, CalcError> {
 		match self {
 			Operator::Add => Ok(args[0] + args[1]),
 			Operator::Sub => Ok(args[0] - args[1]),
@@ -107,21 +284,157 @@ impl Operator {
 			Operator::Rot => Ok(args[1].powf(args[0].recip())),
 			Operator::Unp => Ok( args[0]),
 			Operator::Unm => Ok(-args[0]),
+			// Nothing here knows what unit the context wants, so it's radians. `Expression::evaluate` knows better.
+			Operator::Angle(unit) => Ok(args[0] * unit.factor(AngleUnit::Radians)),
+			// Floats don't have bits to twiddle, so these work on the i64 with the same value. (Integer mode has the real thing.)
+			Operator::BitAnd => Ok((whole(args[0])? & whole(args[1])?) as f64),
+			Operator::BitOr  => Ok((whole(args[0])? | whole(args[1])?) as f64),
+			Operator::Xor    => Ok((whole(args[0])? ^ whole(args[1])?) as f64),
+			Operator::BitNot => Ok(!whole(args[0])? as f64),
+			Operator::Shl | Operator::Shr => {
+				let (x, n) = (whole(args[0])?, whole(args[1])?);
+				if n < 0 {
+					return Err(CalcError::Domain { message: "Can't shift by a negative amount.", span: None });
+				}
+				// Shifting is just multiplying or dividing by a power of 2, and floats can do that without running out of bits.
+				let scale = 2f64.powi(n.min(i32::MAX as i64) as i32);
+				Ok(if *self == Operator::Shl { x as f64 * scale } else { (x as f64 / scale).floor() })
+			},
+			Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge | Operator::Eq | Operator::Ne |
+			Operator::And | Operator::Or | Operator::Not => Ok(super::number::logic(*self, args)),
+			Operator::To => Err(CalcError::Domain { message: "Converting units needs units mode.", span: None }),
 		}
 	}
 }
 
+/// Gets the i64 that `x` is, if it's a whole number that fits in one.
+fn whole(x: f64) -> Result<i64, CalcError> {
+	if x.fract() == 0.0 && x >= i64::MIN as f64 && x < i64::MAX as f64 {
+		Ok(x as i64)
+	} else {
+		Err(CalcError::Domain { message: "Bitwise operators only work on whole numbers.", span: None })
+	}
+}
+
 impl Function {
+	/// Gets the name used to write this function.
+	pub fn name(&self) -> &'static str {
+		match self {
+			Function::Sin => "sin", Function::Cos => "cos", Function::Tan => "tan",
+			Function::Csc => "csc", Function::Sec => "sec", Function::Cot => "cot",
+			Function::Asin => "asin", Function::Acos => "acos", Function::Atan => "atan",
+			Function::Acsc => "acsc", Function::Asec => "asec", Function::Acot => "acot",
+			Function::Sinh => "sinh", Function::Cosh => "cosh", Function::Tanh => "tanh",
+			Function::Asinh => "asinh", Function::Acosh => "acosh", Function::Atanh => "atanh",
+			Function::Sqrt => "sqrt", Function::Cbrt => "cbrt", Function::Exp => "exp",
+			Function::Log => "log", Function::Ln  => "ln",
+			Function::Abs => "abs", Function::Sgn => "sgn",
+			Function::Pi  => "Pi",  Function::E   => "E",
+			Function::Min => "min", Function::Max => "max", Function::Clamp => "clamp",
+			Function::Atan2 => "atan2", Function::LogBase => "log", Function::Root => "root",
+			Function::Sum => "sum", Function::Mean => "mean", Function::Hypot => "hypot",
+			Function::Gcd => "gcd", Function::Lcm => "lcm",
+			Function::Re => "re", Function::Im => "im", Function::Arg => "arg", Function::Conj => "conj", Function::Cis => "cis",
+			Function::If | Function::IfThen => "if",
+			Function::Det => "det", Function::Inv => "inv", Function::Transpose => "transpose",
+			Function::Dot => "dot", Function::Cross => "cross", Function::Norm => "norm",
+			Function::Solve => "solve", Function::Lstsq => "lstsq",
+			Function::LuL => "lu_l", Function::LuU => "lu_u", Function::LuP => "lu_p",
+			Function::QrQ => "qr_q", Function::QrR => "qr_r", Function::Chol => "chol",
+			Function::Rank => "rank", Function::Eig => "eig", Function::Eigvec => "eigvec",
+		}
+	}
+	
 	/// Gets the amount of parameters a function requires.
 	/// 
-	/// Sometimes functions will have parameters separated by commas, so we need a system in place for that.
+	/// Parameters are separated by commas, like `clamp(x, 0, 1)`. For variadic functions, this is the least amount they'll take.
 	pub fn get_parameters(&self) -> usize {
 		match self {
 			Function::Pi | Function::E => 0,
+			Function::Atan2 | Function::LogBase | Function::Root |
+			Function::Dot | Function::Cross | Function::Solve | Function::Lstsq | Function::IfThen => 2,
+			Function::Clamp | Function::If => 3,
 			_ => 1,
 		}
 	}
 	
+	/// Whether this function takes any amount of parameters, like `max(a, b, c, d)`.
+	/// 
+	/// In an `Expression`, these always come right after a `Token::ArgCount` saying how many they got.
+	pub fn is_variadic(&self) -> bool {
+		matches!(self,
+			Function::Min | Function::Max |
+			Function::Sum | Function::Mean | Function::Hypot |
+			Function::Gcd | Function::Lcm
+		)
+	}
+	
+	/// Gets the version of this function that takes `count` arguments, for functions that have more than one.
+	/// 
+	/// Right now that's just `log(x)` and `log(x, base)`, and `if` with or without a side for false. Everything else just returns itself.
+	pub fn with_arguments(&self, count: usize) -> Function {
+		match (self, count) {
+			(Function::Log, 2) => Function::LogBase,
+			(Function::LogBase, 1) => Function::Log,
+			(Function::If, 2) => Function::IfThen,
+			(Function::IfThen, 3) => Function::If,
+			_ => *self,
+		}
+	}
+	
+	/// Whether this function's argument is an angle, so it cares what unit angles are in.
+	pub fn takes_angle(&self) -> bool {
+		matches!(self,
+			Function::Sin | Function::Cos | Function::Tan |
+			Function::Csc | Function::Sec | Function::Cot |
+			Function::Cis
+		)
+	}
+	
+	/// Whether this function only counts as one when it's called with parentheses, like `re(z)`.
+	/// These are the ones for complex numbers and matrices (and `if`), whose names make perfectly good variables the rest of the time.
+	pub fn needs_parentheses(&self) -> bool {
+		matches!(self,
+			Function::Re | Function::Im | Function::Arg | Function::Conj | Function::Cis |
+			Function::If | Function::IfThen |
+			Function::Det | Function::Inv | Function::Transpose |
+			Function::Dot | Function::Cross | Function::Norm |
+			Function::Solve | Function::Lstsq |
+			Function::LuL | Function::LuU | Function::LuP |
+			Function::QrQ | Function::QrR | Function::Chol |
+			Function::Rank | Function::Eig | Function::Eigvec
+		)
+	}
+	
+	/// Whether this function gives back an angle, which should come out in whatever unit angles are in.
+	pub fn gives_angle(&self) -> bool {
+		matches!(self,
+			Function::Asin | Function::Acos | Function::Atan |
+			Function::Acsc | Function::Asec | Function::Acot |
+			Function::Atan2 | Function::Arg
+		)
+	}
+	
+	/// The function that undoes this one, if there is one. So `inverse(f)(f(x)) = x`, at least for the `x` where `f` is one-to-one.
+	/// 
+	/// Trig functions repeat, so their inverses only give back the principal value: `asin(sin(x)) = x` only for `x` between -π/2 and π/2.
+	pub fn inverse(&self) -> Option<Function> {
+		match self {
+			Function::Sin => Some(Function::Asin), Function::Asin => Some(Function::Sin),
+			Function::Cos => Some(Function::Acos), Function::Acos => Some(Function::Cos),
+			Function::Tan => Some(Function::Atan), Function::Atan => Some(Function::Tan),
+			Function::Csc => Some(Function::Acsc), Function::Acsc => Some(Function::Csc),
+			Function::Sec => Some(Function::Asec), Function::Asec => Some(Function::Sec),
+			Function::Cot => Some(Function::Acot), Function::Acot => Some(Function::Cot),
+			Function::Sinh => Some(Function::Asinh), Function::Asinh => Some(Function::Sinh),
+			Function::Cosh => Some(Function::Acosh), Function::Acosh => Some(Function::Cosh),
+			Function::Tanh => Some(Function::Atanh), Function::Atanh => Some(Function::Tanh),
+			Function::Exp => Some(Function::Ln), Function::Ln => Some(Function::Exp),
+			Function::Conj => Some(Function::Conj),
+			_ => None,
+		}
+	}
+	
 	/// Uh
 	pub fn get_precedence(&self) -> usize {
 		4
@@ -130,7 +443,7 @@ impl Function {
 	/// Calculates the result of using this function on a stack.
 	/// 
 	/// See Operator comment for despair about code smell.
-	pub fn calculate(&self, args: Vec<f64>) -> Result<f64, &'static str> {
+	pub fn calculate(&self, args: &[f64]) -> Result<f64, CalcError> {
 		match self {
 			Function::Sin => Ok(args[0].sin()),
 			Function::Cos => Ok(args[0].cos()),
@@ -138,12 +451,83 @@ impl Function {
 			Function::Csc => Ok(args[0].sin().recip()),
 			Function::Sec => Ok(args[0].cos().recip()),
 			Function::Cot => Ok(args[0].tan().recip()),
+			Function::Asin => Ok(args[0].asin()),
+			Function::Acos => Ok(args[0].acos()),
+			Function::Atan => Ok(args[0].atan()),
+			Function::Acsc => Ok(args[0].recip().asin()),
+			Function::Asec => Ok(args[0].recip().acos()),
+			// This is the one that's continuous at 0, so acot(x) goes from 0 to π.
+			Function::Acot => Ok(core::f64::consts::FRAC_PI_2 - args[0].atan()),
+			Function::Sinh => Ok(args[0].sinh()),
+			Function::Cosh => Ok(args[0].cosh()),
+			Function::Tanh => Ok(args[0].tanh()),
+			Function::Asinh => Ok(args[0].asinh()),
+			Function::Acosh => Ok(args[0].acosh()),
+			Function::Atanh => Ok(args[0].atanh()),
+			Function::Sqrt => Ok(args[0].sqrt()),
+			Function::Cbrt => Ok(args[0].cbrt()),
+			Function::Exp => Ok(args[0].exp()),
 			Function::Log => Ok(args[0].log10()),
 			Function::Ln  => Ok(args[0].log(core::f64::consts::E)),
 			Function::Abs => Ok(args[0].abs()),
 			Function::Sgn => Ok(args[0].signum()),
 			Function::Pi  => Ok(core::f64::consts::PI),
 			Function::E   => Ok(core::f64::consts::E),
+			// f64::min and f64::max skip over NaN, but a missing value shouldn't just disappear. (Like the gaps in a piecewise expression.)
+			Function::Min | Function::Max if args.iter().any(|a| a.is_nan()) => Ok(f64::NAN),
+			Function::Min => Ok(args.iter().cloned().fold(f64::INFINITY, f64::min)),
+			Function::Max => Ok(args.iter().cloned().fold(f64::NEG_INFINITY, f64::max)),
+			Function::Sum => Ok(args.iter().sum()),
+			Function::Mean => Ok(args.iter().sum::<f64>() / (args.len() as f64)),
+			Function::Hypot => Ok(args.iter().cloned().fold(0.0, f64::hypot)),
+			Function::Gcd | Function::Lcm => {
+				if args.iter().any(|a| a.fract() != 0.0) {
+					return Err(CalcError::Domain { message: "gcd and lcm only work on integers.", span: None });
+				}
+				
+				fn gcd(mut a: f64, mut b: f64) -> f64 {
+					while b != 0.0 {
+						let t = a % b;
+						a = b;
+						b = t;
+					}
+					a.abs()
+				}
+				
+				if *self == Function::Gcd {
+					Ok(args.iter().cloned().fold(0.0, gcd))
+				} else {
+					Ok(args.iter().cloned().fold(1.0, |a, b| if b == 0.0 { 0.0 } else { (a * b / gcd(a, b)).abs() }))
+				}
+			},
+			Function::Clamp => Ok(crate::util::clamp(args[0], args[1], args[2])),
+			Function::Atan2 => Ok(args[0].atan2(args[1])),
+			Function::LogBase => Ok(args[0].log(args[1])),
+			Function::Root => Ok(args[0].powf(args[1].recip())),
+			// Real numbers are complex numbers with no imaginary part, so these are pretty boring.
+			Function::Re => Ok(args[0]),
+			Function::Im => Ok(0.0),
+			Function::Arg => Ok(0f64.atan2(args[0])),
+			Function::Conj => Ok(args[0]),
+			Function::Cis => Err(CalcError::Domain { message: "cis needs complex mode.", span: None }),
+			Function::If | Function::IfThen => super::number::choose(args),
+			// A number is a 1x1 matrix, as far as these care.
+			Function::Det | Function::Transpose => Ok(args[0]),
+			Function::Inv => Ok(args[0].recip()),
+			Function::Dot => Ok(args[0] * args[1]),
+			Function::Norm => Ok(args[0].abs()),
+			Function::Cross => Err(CalcError::Domain { message: "cross needs two 3D vectors, in matrix mode.", span: None }),
+			Function::Solve if args[0] == 0.0 => Err(CalcError::Domain { message: super::matrix::SINGULAR, span: None }),
+			Function::Lstsq if args[0] == 0.0 => Err(CalcError::Domain { message: super::matrix::RANK_DEFICIENT, span: None }),
+			Function::Solve | Function::Lstsq => Ok(args[1] / args[0]),
+			Function::LuL | Function::LuP | Function::Eigvec => Ok(1.0),
+			Function::LuU | Function::Eig => Ok(args[0]),
+			// R's diagonal is never negative, so Q picks up the sign.
+			Function::QrQ => Ok(if args[0] < 0.0 { -1.0 } else { 1.0 }),
+			Function::QrR => Ok(args[0].abs()),
+			Function::Chol if args[0] > 0.0 => Ok(args[0].sqrt()),
+			Function::Chol => Err(CalcError::Domain { message: super::matrix::NOT_POSITIVE_DEFINITE, span: None }),
+			Function::Rank => Ok(if args[0] == 0.0 { 0.0 } else { 1.0 }),
 			// _ => Err("Unknown function."),
 		}
 	}
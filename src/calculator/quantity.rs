@@ -0,0 +1,404 @@
+use std::fmt;
+
+use super::error::CalcError;
+use super::number::{Number, Settings, logic, choose};
+use super::operator::{Operator, Function};
+
+/// The SI base units, in the order `Dimension` keeps them in.
+const BASE_UNITS: [&str; 7] = ["kg", "m", "s", "A", "K", "mol", "cd"];
+
+/// How many of each SI base unit something has, in the same order as `BASE_UNITS`. Acceleration (m/s^2) is `[0, 1, -2, 0, 0, 0, 0]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Dimension(pub [i8; 7]);
+
+impl Dimension {
+	/// No units at all, like a plain number.
+	pub const NONE: Dimension = Dimension([0; 7]);
+	
+	pub fn is_none(&self) -> bool {
+		*self == Dimension::NONE
+	}
+	
+	fn times(self, other: Dimension) -> Result<Dimension, CalcError> {
+		self.combine(other, i8::checked_add)
+	}
+	
+	fn over(self, other: Dimension) -> Result<Dimension, CalcError> {
+		self.combine(other, i8::checked_sub)
+	}
+	
+	/// Puts each unit's powers together with `f`, as long as they don't get too big. Same limit as `pow`, so they can always be flipped over.
+	fn combine(self, other: Dimension, f: fn(i8, i8) -> Option<i8>) -> Result<Dimension, CalcError> {
+		let mut result = self;
+		for (a, b) in result.0.iter_mut().zip(other.0.iter()) {
+			*a = match f(*a, *b) {
+				Some(p) if p != i8::MIN => p,
+				_ => return Err(CalcError::Domain { message: "That's too many of one unit to keep track of.", span: None }),
+			};
+		}
+		Ok(result)
+	}
+	
+	/// This to the power of `power`, if that's still a whole number of each unit. `m^2` can be square rooted, but `m` can't.
+	fn pow(self, power: f64) -> Option<Dimension> {
+		let mut result = self;
+		for a in result.0.iter_mut() {
+			let p = *a as f64 * power;
+			if p.fract() != 0.0 || p.abs() > i8::MAX as f64 {
+				return None;
+			}
+			*a = p as i8;
+		}
+		Some(result)
+	}
+}
+
+impl fmt::Display for Dimension {
+	/// Writes this in base units, like `kg*m/s^2` or `s^-1`.
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let power = |unit: &str, p: i8| if p == 1 { unit.to_owned() } else { format!("{}^{}", unit, p) };
+		let top: Vec<String> = BASE_UNITS.iter().zip(self.0.iter()).filter(|(_, p)| **p > 0).map(|(u, p)| power(u, *p)).collect();
+		let bottom: Vec<String> = BASE_UNITS.iter().zip(self.0.iter()).filter(|(_, p)| **p < 0).map(|(u, p)| power(u, -*p)).collect();
+		
+		match (top.len(), bottom.len()) {
+			(0, 0) => write!(f, "no units"),
+			(0, _) => {
+				let negative: Vec<String> = BASE_UNITS.iter().zip(self.0.iter()).filter(|(_, p)| **p < 0).map(|(u, p)| power(u, *p)).collect();
+				write!(f, "{}", negative.join("*"))
+			},
+			(_, 0) => write!(f, "{}", top.join("*")),
+			(_, 1) => write!(f, "{}/{}", top.join("*"), bottom[0]),
+			_ => write!(f, "{}/({})", top.join("*"), bottom.join("*")),
+		}
+	}
+}
+
+/// A unit's name, how much of the SI base units it is, its dimension, and whether it can have a prefix like `k` or `m`.
+type UnitDefinition = (&'static str, f64, [i8; 7], bool);
+
+/// Every unit there is. The dimensions are in the same order as `BASE_UNITS`.
+/// 
+/// `min` is already the minimum function, so minutes are `minute`. Temperatures are only kelvin, since °C and °F don't start at zero.
+const UNITS: &[UnitDefinition] = &[
+	// SI base units. Kilograms are grams with a prefix, so they get `mg` and friends for free.
+	("m", 1.0, [0, 1, 0, 0, 0, 0, 0], true),
+	("g", 1e-3, [1, 0, 0, 0, 0, 0, 0], true),
+	("s", 1.0, [0, 0, 1, 0, 0, 0, 0], true),
+	("A", 1.0, [0, 0, 0, 1, 0, 0, 0], true),
+	("K", 1.0, [0, 0, 0, 0, 1, 0, 0], true),
+	("mol", 1.0, [0, 0, 0, 0, 0, 1, 0], true),
+	("cd", 1.0, [0, 0, 0, 0, 0, 0, 1], true),
+	// Named SI units.
+	("N", 1.0, [1, 1, -2, 0, 0, 0, 0], true),
+	("J", 1.0, [1, 2, -2, 0, 0, 0, 0], true),
+	("W", 1.0, [1, 2, -3, 0, 0, 0, 0], true),
+	("Pa", 1.0, [1, -1, -2, 0, 0, 0, 0], true),
+	("Hz", 1.0, [0, 0, -1, 0, 0, 0, 0], true),
+	("C", 1.0, [0, 0, 1, 1, 0, 0, 0], true),
+	("V", 1.0, [1, 2, -3, -1, 0, 0, 0], true),
+	("Ω", 1.0, [1, 2, -3, -2, 0, 0, 0], true),
+	("ohm", 1.0, [1, 2, -3, -2, 0, 0, 0], true),
+	("S", 1.0, [-1, -2, 3, 2, 0, 0, 0], true),
+	("F", 1.0, [-1, -2, 4, 2, 0, 0, 0], true),
+	("T", 1.0, [1, 0, -2, -1, 0, 0, 0], true),
+	("Wb", 1.0, [1, 2, -2, -1, 0, 0, 0], true),
+	("H", 1.0, [1, 2, -2, -2, 0, 0, 0], true),
+	// Everything else.
+	("L", 1e-3, [0, 3, 0, 0, 0, 0, 0], true),
+	("bar", 1e5, [1, -1, -2, 0, 0, 0, 0], true),
+	("eV", 1.602176634e-19, [1, 2, -2, 0, 0, 0, 0], true),
+	("Wh", 3600.0, [1, 2, -2, 0, 0, 0, 0], true),
+	("cal", 4.184, [1, 2, -2, 0, 0, 0, 0], true),
+	("minute", 60.0, [0, 0, 1, 0, 0, 0, 0], false),
+	("h", 3600.0, [0, 0, 1, 0, 0, 0, 0], false),
+	("day", 86400.0, [0, 0, 1, 0, 0, 0, 0], false),
+	("in", 0.0254, [0, 1, 0, 0, 0, 0, 0], false),
+	("ft", 0.3048, [0, 1, 0, 0, 0, 0, 0], false),
+	("yd", 0.9144, [0, 1, 0, 0, 0, 0, 0], false),
+	("mi", 1609.344, [0, 1, 0, 0, 0, 0, 0], false),
+	("nmi", 1852.0, [0, 1, 0, 0, 0, 0, 0], false),
+	("mph", 0.44704, [0, 1, -1, 0, 0, 0, 0], false),
+	("kn", 1852.0 / 3600.0, [0, 1, -1, 0, 0, 0, 0], false),
+	("lb", 0.45359237, [1, 0, 0, 0, 0, 0, 0], false),
+	("oz", 0.028349523125, [1, 0, 0, 0, 0, 0, 0], false),
+	("lbf", 4.4482216152605, [1, 1, -2, 0, 0, 0, 0], false),
+	("psi", 6894.757293168361, [1, -1, -2, 0, 0, 0, 0], false),
+	("atm", 101325.0, [1, -1, -2, 0, 0, 0, 0], false),
+	("gal", 3.785411784e-3, [0, 3, 0, 0, 0, 0, 0], false),
+	("hp", 745.6998715822702, [1, 2, -3, 0, 0, 0, 0], false),
+];
+
+/// SI prefixes. `da` has to come before `d`, or `dam` would be looked for as a deci-`am`.
+const PREFIXES: &[(&str, f64)] = &[
+	("Y", 1e24), ("Z", 1e21), ("E", 1e18), ("P", 1e15), ("T", 1e12), ("G", 1e9), ("M", 1e6), ("k", 1e3), ("h", 1e2), ("da", 1e1),
+	("d", 1e-1), ("c", 1e-2), ("m", 1e-3), ("µ", 1e-6), ("μ", 1e-6), ("u", 1e-6), ("n", 1e-9), ("p", 1e-12), ("f", 1e-15), ("a", 1e-18), ("z", 1e-21), ("y", 1e-24),
+];
+
+/// Units that answers without a unit of their own get shown in, if their dimension matches. Anything else is in base units.
+const SHOWN_UNITS: &[&str] = &["N", "J", "W", "Pa", "Hz", "C", "V", "Ω", "F", "T", "Wb", "H"];
+
+/// Finds a unit by name, with or without a prefix. Gives how much of the SI base units it is, and its dimension.
+fn lookup(name: &str) -> Option<(f64, Dimension)> {
+	// A whole name always wins, so `cd` is candelas and not centidays, and `Pa` isn't a peta-year.
+	if let Some((_, scale, dimension, _)) = UNITS.iter().find(|u| u.0 == name) {
+		return Some((*scale, Dimension(*dimension)));
+	}
+	
+	PREFIXES.iter().find_map(|(prefix, factor)| {
+		let rest = name.strip_prefix(prefix)?;
+		UNITS.iter().find(|u| u.3 && u.0 == rest).map(|(_, scale, dimension, _)| (factor * scale, Dimension(*dimension)))
+	})
+}
+
+/// Rounds off the last couple of digits, so 100 km/h shows as 27.7777777777778 m/s instead of 27.777777777777775 m/s.
+fn tidy(x: f64) -> f64 {
+	format!("{:.14e}", x).parse().unwrap_or(x)
+}
+
+/// A unit somebody wrote, like `km` or `m/s^2`. This is only for showing answers in. The math all happens in SI.
+#[derive(Debug, Clone, PartialEq)]
+struct Unit {
+	name: String,
+	/// How much of the SI base units one of this is, like 1000 for `km`.
+	scale: f64,
+}
+
+impl Unit {
+	/// This unit times `other`, or divided by it if `divide`.
+	fn combine(&self, other: &Unit, divide: bool) -> Unit {
+		if divide {
+			let bottom = if other.name.contains(['*', '/']) { format!("({})", other.name) } else { other.name.clone() };
+			Unit { name: format!("{}/{}", self.name, bottom), scale: self.scale / other.scale }
+		} else {
+			Unit { name: format!("{}*{}", self.name, other.name), scale: self.scale * other.scale }
+		}
+	}
+	
+	fn pow(&self, power: f64) -> Unit {
+		let base = if self.name.contains(['*', '/', '^']) { format!("({})", self.name) } else { self.name.clone() };
+		Unit { name: format!("{}^{}", base, power), scale: self.scale.powf(power) }
+	}
+}
+
+/// How much of a `Quantity` is still being written as its unit. This is how `100 km/h` ends up in km/h, but `9.81 m/s^2 * 3 kg` doesn't end up in m/s^2*kg.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum UnitState {
+	/// Just a unit, like `km/h`. Only these can be converted `to`.
+	Bare,
+	/// An amount of something, with a unit that's still being written, like the `100 km` in `100 km/h`.
+	Open,
+	/// Anything else. Units after this are a new thing to multiply by, not more of the unit.
+	Closed,
+}
+
+/// A number with units, for "units mode": `Expression::evaluate::<Quantity>`.
+/// 
+/// Unit names (like `km`, `N`, or `mph`) are only looked up when there isn't a variable with that name, so they don't get in the way anywhere else.
+/// Everything's kept in SI base units, so `1 km` is 1000 m underneath. Adding or comparing things with different units is an error.
+/// 
+/// Answers are shown in whatever they were written in (or converted `to`), so `1 km + 500 m` is `1.5 km`.
+/// If that doesn't make sense, like after multiplying two things with units, they're shown in SI: `9.81 m/s^2 * 3 kg` is `29.43 N`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+	/// In SI base units.
+	pub value: f64,
+	pub dimension: Dimension,
+	unit: Option<Unit>,
+	state: UnitState,
+}
+
+impl Quantity {
+	/// A quantity of `value` SI base units.
+	pub fn new(value: f64, dimension: Dimension) -> Quantity {
+		Quantity { value, dimension, unit: None, state: UnitState::Closed }
+	}
+	
+	/// A number without any units.
+	pub fn plain(value: f64) -> Quantity {
+		Quantity::new(value, Dimension::NONE)
+	}
+	
+	/// One of the unit called `name`, like `km` or `µs`, if there's a unit by that name.
+	pub fn unit(name: &str) -> Option<Quantity> {
+		let (scale, dimension) = lookup(name)?;
+		Some(Quantity { value: scale, dimension, unit: Some(Unit { name: name.to_owned(), scale }), state: UnitState::Bare })
+	}
+	
+	fn is_plain(&self) -> bool {
+		self.unit.is_none() && self.dimension.is_none()
+	}
+	
+	fn same_dimension(&self, other: &Quantity) -> Result<(), CalcError> {
+		if self.dimension == other.dimension {
+			Ok(())
+		} else {
+			Err(CalcError::Dimension { left: self.dimension.to_string(), right: other.dimension.to_string(), span: None })
+		}
+	}
+	
+	/// The values of `args`, as long as none of them have units.
+	fn plain_values(args: &[Quantity]) -> Result<Vec<f64>, CalcError> {
+		if args.iter().all(|a| a.dimension.is_none()) {
+			Ok(args.iter().map(|a| a.value).collect())
+		} else {
+			Err(CalcError::Domain { message: "That only works on plain numbers, not units.", span: None })
+		}
+	}
+	
+	/// `self * other`, or `self / other` if `divide`. Works out what the answer should be shown in, too.
+	fn product(&self, other: &Quantity, divide: bool) -> Result<Quantity, CalcError> {
+		let (value, dimension) = if divide {
+			(self.value / other.value, self.dimension.over(other.dimension)?)
+		} else {
+			(self.value * other.value, self.dimension.times(other.dimension)?)
+		};
+		
+		let (unit, state) = match (&self.unit, &other.unit) {
+			// Plain numbers just scale things, so 3 km is still in km.
+			_ if other.is_plain() => (self.unit.clone(), UnitState::Closed),
+			(None, Some(unit)) if self.is_plain() && !divide => (Some(unit.clone()), UnitState::Open),
+			// Tacking a bare unit on builds a bigger unit, so 100 km/h is in km/h.
+			(Some(a), Some(b)) if other.state == UnitState::Bare && self.state != UnitState::Closed => (Some(a.combine(b, divide)), self.state),
+			// Anything else gets shown in SI, so 9.81 m/s^2 * 3 kg is in N.
+			_ => (None, UnitState::Closed),
+		};
+		Ok(Quantity { value, dimension, unit, state })
+	}
+	
+	fn pow(&self, power: &Quantity) -> Result<Quantity, CalcError> {
+		if !power.dimension.is_none() {
+			return Err(CalcError::Domain { message: "Powers can't have units.", span: None });
+		}
+		let dimension = match self.dimension.pow(power.value) {
+			Some(d) => d,
+			None => return Err(CalcError::Domain { message: "That would need part of a unit, like m^0.5.", span: None }),
+		};
+		
+		let (unit, state) = match &self.unit {
+			Some(unit) if self.state == UnitState::Bare && power.unit.is_none() => (Some(unit.pow(power.value)), UnitState::Bare),
+			_ => (None, UnitState::Closed),
+		};
+		Ok(Quantity { value: self.value.powf(power.value), dimension, unit, state })
+	}
+	
+	/// Same as `self`, but with a different value. That makes it an amount, and its unit's done.
+	fn with_value(&self, value: f64) -> Quantity {
+		Quantity { value, dimension: self.dimension, unit: self.unit.clone(), state: UnitState::Closed }
+	}
+}
+
+impl Number for Quantity {
+	fn from_f64(value: f64) -> Quantity {
+		Quantity::plain(value)
+	}
+	
+	fn to_f64(&self) -> Option<f64> {
+		if self.dimension.is_none() { Some(self.value) } else { None }
+	}
+	
+	fn named(name: &str) -> Option<Quantity> {
+		Quantity::unit(name)
+	}
+	
+	fn to_quantity(&self) -> Option<Quantity> {
+		Some(self.clone())
+	}
+	
+	fn from_quantity(quantity: &Quantity) -> Option<Quantity> {
+		Some(quantity.clone())
+	}
+	
+	fn operate(operator: Operator, args: &[Quantity], _settings: &Settings) -> Result<Quantity, CalcError> {
+		match operator {
+			Operator::Add | Operator::Sub | Operator::Mod => {
+				args[0].same_dimension(&args[1])?;
+				let value = operator.calculate(&[args[0].value, args[1].value])?;
+				let mut result = args[0].with_value(value);
+				result.unit = args[0].unit.clone().or_else(|| args[1].unit.clone());
+				Ok(result)
+			},
+			Operator::Mul => args[0].product(&args[1], false),
+			Operator::Div => args[0].product(&args[1], true),
+			Operator::Pow => args[0].pow(&args[1]),
+			Operator::Rot => {
+				let n = Quantity::plain_values(&args[..1])?[0];
+				args[1].pow(&Quantity::plain(n.recip()))
+			},
+			Operator::Unp => Ok(args[0].with_value(args[0].value)),
+			Operator::Unm => Ok(args[0].with_value(-args[0].value)),
+			Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge | Operator::Eq | Operator::Ne => {
+				args[0].same_dimension(&args[1])?;
+				Ok(Quantity::plain(logic(operator, &[args[0].value, args[1].value])))
+			},
+			Operator::And | Operator::Or | Operator::Not |
+			Operator::BitAnd | Operator::BitOr | Operator::Xor | Operator::BitNot |
+			Operator::Shl | Operator::Shr => Ok(Quantity::plain(operator.calculate(&Quantity::plain_values(args)?)?)),
+			Operator::To => match &args[1].unit {
+				Some(unit) if args[1].state == UnitState::Bare => {
+					args[0].same_dimension(&args[1])?;
+					let mut result = args[0].with_value(args[0].value);
+					result.unit = Some(unit.clone());
+					Ok(result)
+				},
+				_ => Err(CalcError::Domain { message: "Things can only be converted to units, like 60 mph to m/s.", span: None }),
+			},
+			// `number::operate_in` turns angles into the right unit itself, and never passes them along.
+			Operator::Angle(_) => unreachable!("angles are handled by operate_in"),
+		}
+	}
+	
+	fn call(function: Function, args: &[Quantity], _settings: &Settings) -> Result<Quantity, CalcError> {
+		match function {
+			Function::If | Function::IfThen => choose(args),
+			Function::Abs | Function::Re | Function::Conj | Function::Im |
+			Function::Det | Function::Transpose | Function::Norm => Ok(args[0].with_value(function.calculate(&[args[0].value])?)),
+			Function::Sgn => Ok(Quantity::plain(args[0].value.signum())),
+			Function::Min | Function::Max | Function::Clamp |
+			Function::Sum | Function::Mean | Function::Hypot => {
+				for a in &args[1..] {
+					args[0].same_dimension(a)?;
+				}
+				let values: Vec<f64> = args.iter().map(|a| a.value).collect();
+				let mut result = args[0].with_value(function.calculate(&values)?);
+				result.unit = args.iter().find_map(|a| a.unit.clone());
+				Ok(result)
+			},
+			Function::Inv => Quantity::plain(1.0).product(&args[0], true),
+			Function::Dot => args[0].product(&args[1], false),
+			// The units cancel out, so this is fine as long as they match.
+			Function::Atan2 => {
+				args[0].same_dimension(&args[1])?;
+				Ok(Quantity::plain(function.calculate(&[args[0].value, args[1].value])?))
+			},
+			Function::Sqrt | Function::Cbrt | Function::Root => {
+				let n = match function {
+					Function::Sqrt => 2.0,
+					Function::Cbrt => 3.0,
+					_ => Quantity::plain_values(&args[1..])?[0],
+				};
+				// Units come from the power, but the value comes from the real thing, so cbrt(-8 m^3) is still -2 m.
+				let mut result = args[0].pow(&Quantity::plain(n.recip()))?;
+				result.value = function.calculate(&[args[0].value, n][..function.get_parameters()])?;
+				Ok(result)
+			},
+			_ => Ok(Quantity::plain(function.calculate(&Quantity::plain_values(args)?)?)),
+		}
+	}
+}
+
+impl fmt::Display for Quantity {
+	/// Writes this like `29.43 N` or `1.5 km`. Plain numbers are written just like an `f64` would be.
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.dimension.is_none() {
+			return write!(f, "{}", tidy(self.value));
+		}
+		
+		match &self.unit {
+			Some(unit) => write!(f, "{} {}", tidy(self.value / unit.scale), unit.name),
+			None => match SHOWN_UNITS.iter().find(|name| lookup(name).map(|u| u.1) == Some(self.dimension)) {
+				Some(name) => write!(f, "{} {}", tidy(self.value), name),
+				None => write!(f, "{} {}", tidy(self.value), self.dimension),
+			},
+		}
+	}
+}
@@ -0,0 +1,309 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_traits::{One, Pow, Signed, ToPrimitive, Zero};
+
+use super::error::CalcError;
+use super::number::{Number, Settings, logic, choose};
+use super::integer::bitwise;
+use super::operator::{Operator, Function};
+
+/// An exact fraction, for when `1/3 + 1/6` has to be `1/2` and not `0.49999999999999994`. Calculating with these is "rational mode": `Expression::evaluate::<Rational>`.
+/// 
+/// `+ - * /`, whole number powers, and roots that come out even stay exact. Anything transcendental (`sin`, `ln`, `Pi`, ...) can't be a fraction,
+/// so it gives back an `Approximate` instead, and everything calculated from that is approximate too. `is_exact` says which one you got.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rational {
+	/// Always in lowest terms, with a positive denominator. Make these with `Rational::new` so that stays true.
+	Exact { numerator: BigInt, denominator: BigInt },
+	Approximate(f64),
+}
+
+impl Rational {
+	/// The most bits a whole number power can make before we give up and use floats. (Which is still a number with hundreds of thousands of digits.)
+	const MAX_POWER_BITS: u64 = 1 << 20;
+	
+	/// Makes a fraction, in lowest terms. Dividing by zero gives back an approximate infinity (or NaN), same as it would with floats.
+	pub fn new(numerator: BigInt, denominator: BigInt) -> Rational {
+		if denominator.is_zero() {
+			return Rational::Approximate(match numerator.sign() {
+				num_bigint::Sign::Plus => f64::INFINITY,
+				num_bigint::Sign::Minus => f64::NEG_INFINITY,
+				num_bigint::Sign::NoSign => f64::NAN,
+			});
+		}
+		
+		let gcd = numerator.gcd(&denominator);
+		let (mut numerator, mut denominator) = (numerator / &gcd, denominator / &gcd);
+		if denominator.is_negative() {
+			numerator = -numerator;
+			denominator = -denominator;
+		}
+		Rational::Exact { numerator, denominator }
+	}
+	
+	pub fn integer(n: impl Into<BigInt>) -> Rational {
+		Rational::Exact { numerator: n.into(), denominator: BigInt::one() }
+	}
+	
+	pub fn is_exact(&self) -> bool {
+		matches!(self, Rational::Exact { .. })
+	}
+	
+	pub fn is_integer(&self) -> bool {
+		matches!(self, Rational::Exact { denominator, .. } if denominator.is_one())
+	}
+	
+	/// The closest `f64`.
+	pub fn float(&self) -> f64 {
+		match self {
+			Rational::Exact { numerator, denominator } => ratio_to_f64(numerator, denominator),
+			Rational::Approximate(x) => *x,
+		}
+	}
+	
+	/// Writes this as a mixed number, like `-2 1/3` instead of `-7/3`. Anything that isn't a fraction bigger than 1 is written the same as usual.
+	pub fn to_mixed_string(&self) -> String {
+		match self {
+			Rational::Exact { numerator, denominator } if !denominator.is_one() && numerator.abs() > *denominator => {
+				let (whole, remainder) = numerator.div_rem(denominator);
+				format!("{} {}/{}", whole, remainder.abs(), denominator)
+			},
+			_ => self.to_string(),
+		}
+	}
+	
+	/// Raises this to a whole number power. Exact unless the result would be ridiculously big.
+	fn pow_int(&self, exponent: &BigInt) -> Rational {
+		if let Rational::Exact { numerator, denominator } = self {
+			let bits = numerator.bits() + denominator.bits();
+			if let Some(n) = exponent.abs().to_u64().filter(|n| n.saturating_mul(bits) <= Rational::MAX_POWER_BITS) {
+				let (numerator, denominator) = (Pow::pow(numerator, n), Pow::pow(denominator, n));
+				return if exponent.is_negative() {
+					Rational::new(denominator, numerator)
+				} else {
+					Rational::new(numerator, denominator)
+				};
+			}
+		}
+		Rational::Approximate(self.float().powf(exponent.to_f64().unwrap_or(f64::NAN)))
+	}
+	
+	/// The `n`th root, if it comes out exact. (So `4/9` has a square root, but `2` doesn't.)
+	fn exact_root(&self, n: u32) -> Option<Rational> {
+		if !self.is_exact() || n == 0 || (n & 1 == 0 && self.float() < 0.0) {
+			return None;
+		}
+		
+		// Guess with floats, then check the guess exactly.
+		let x = self.float();
+		let guess = x.signum() * x.abs().powf(1.0 / n as f64);
+		let guess = Rational::from_f64(guess);
+		if guess.pow_int(&BigInt::from(n)) == *self { Some(guess) } else { None }
+	}
+	
+	/// `self^exponent`. Exact for whole number powers, and for fractional powers that come out even, like `(8/27)^(2/3)`.
+	fn pow(&self, exponent: &Rational) -> Rational {
+		if let Rational::Exact { numerator, denominator } = exponent {
+			if denominator.is_one() {
+				return self.pow_int(numerator);
+			}
+			if let Some(root) = denominator.to_u32().and_then(|d| self.exact_root(d)) {
+				return root.pow_int(numerator);
+			}
+		}
+		Rational::Approximate(self.float().powf(exponent.float()))
+	}
+}
+
+/// Divides two big integers as floats, even when they're too big to be floats on their own.
+fn ratio_to_f64(numerator: &BigInt, denominator: &BigInt) -> f64 {
+	let extra = numerator.bits().max(denominator.bits()).saturating_sub(1000);
+	let (numerator, denominator) = (numerator >> extra, denominator >> extra);
+	numerator.to_f64().unwrap_or(f64::NAN) / denominator.to_f64().unwrap_or(f64::NAN)
+}
+
+/// The fraction with the smallest denominator (out of the convergents of its continued fraction, anyway) that rounds to exactly `x`.
+fn simplest_fraction(x: f64) -> Rational {
+	// Every finite float is exactly mantissa * 2^exponent.
+	let bits = x.abs().to_bits();
+	let exponent = ((bits >> 52) & 0x7ff) as i64;
+	let mantissa = if exponent == 0 { (bits & 0xf_ffff_ffff_ffff) << 1 } else { (bits & 0xf_ffff_ffff_ffff) | 0x10_0000_0000_0000 };
+	let exponent = exponent - 1075;
+	let (mut p, mut q) = if exponent >= 0 {
+		(BigInt::from(mantissa) << exponent as usize, BigInt::one())
+	} else {
+		(BigInt::from(mantissa), BigInt::one() << (-exponent) as usize)
+	};
+	
+	let (mut h, mut h_previous) = (BigInt::one(), BigInt::zero());
+	let (mut k, mut k_previous) = (BigInt::zero(), BigInt::one());
+	while !q.is_zero() {
+		let (a, remainder) = p.div_rem(&q);
+		let next_h = &a * &h + &h_previous;
+		let next_k = &a * &k + &k_previous;
+		h_previous = std::mem::replace(&mut h, next_h);
+		k_previous = std::mem::replace(&mut k, next_k);
+		
+		if ratio_to_f64(&h, &k) == x.abs() {
+			break;
+		}
+		p = std::mem::replace(&mut q, remainder);
+	}
+	
+	Rational::new(if x < 0.0 { -h } else { h }, k)
+}
+
+/// The fraction `x` is written as in decimal, so `0.1` is `1/10`.
+fn decimal_fraction(x: f64) -> Rational {
+	// f64's Display never uses exponents, so this is always just digits with maybe a point.
+	let written = x.to_string();
+	let (whole, fraction) = written.split_once('.').unwrap_or((&written, ""));
+	let digits: BigInt = format!("{}{}", whole, fraction).parse().unwrap();
+	Rational::new(digits, Pow::pow(BigInt::from(10u8), fraction.len()))
+}
+
+impl PartialOrd for Rational {
+	fn partial_cmp(&self, other: &Rational) -> Option<Ordering> {
+		match (self, other) {
+			(Rational::Exact { numerator: a, denominator: b }, Rational::Exact { numerator: c, denominator: d }) => Some((a * d).cmp(&(c * b))),
+			_ => self.float().partial_cmp(&other.float()),
+		}
+	}
+}
+
+impl Number for Rational {
+	/// Numbers get typed in (and variables get stored) as floats, so this finds the fraction that was most likely meant:
+	/// whichever of its decimal digits or the simplest fraction that rounds to it has the smaller denominator. So `0.1` is `1/10` and `0.3333333333333333` is `1/3`.
+	fn from_f64(value: f64) -> Rational {
+		if !value.is_finite() {
+			return Rational::Approximate(value);
+		}
+		
+		let (decimal, simplest) = (decimal_fraction(value), simplest_fraction(value));
+		match (&decimal, &simplest) {
+			(Rational::Exact { denominator: a, .. }, Rational::Exact { denominator: b, .. }) if b < a => simplest,
+			_ => decimal,
+		}
+	}
+	
+	fn from_integer(value: &BigInt, _settings: &Settings) -> Result<Rational, CalcError> {
+		Ok(Rational::new(value.clone(), BigInt::one()))
+	}
+	
+	fn to_f64(&self) -> Option<f64> {
+		Some(self.float())
+	}
+	
+	fn operate(operator: Operator, args: &[Rational], _settings: &Settings) -> Result<Rational, CalcError> {
+		use Rational::Exact;
+		
+		match (operator, args) {
+			(Operator::Add, [Exact { numerator: a, denominator: b }, Exact { numerator: c, denominator: d }]) => Ok(Rational::new(a * d + c * b, b * d)),
+			(Operator::Sub, [Exact { numerator: a, denominator: b }, Exact { numerator: c, denominator: d }]) => Ok(Rational::new(a * d - c * b, b * d)),
+			(Operator::Mul, [Exact { numerator: a, denominator: b }, Exact { numerator: c, denominator: d }]) => Ok(Rational::new(a * c, b * d)),
+			(Operator::Div, [Exact { numerator: a, denominator: b }, Exact { numerator: c, denominator: d }]) => Ok(Rational::new(a * d, b * c)),
+			(Operator::Mod, [x @ Exact { numerator: a, denominator: b }, y @ Exact { numerator: c, denominator: d }]) if !c.is_zero() => {
+				// Same as f64's %, which is x - y * trunc(x / y).
+				let quotient = Rational::integer((a * d) / (b * c));
+				Rational::operate(Operator::Sub, &[x.clone(), Rational::operate(Operator::Mul, &[y.clone(), quotient], _settings)?], _settings)
+			},
+			(Operator::Pow, [x, y]) => Ok(x.pow(y)),
+			(Operator::Rot, [n, x]) => Ok(x.pow(&Rational::operate(Operator::Div, &[Rational::integer(1), n.clone()], _settings)?)),
+			(Operator::Unp, [x]) => Ok(x.clone()),
+			(Operator::Unm, [Exact { numerator, denominator }]) => Ok(Exact { numerator: -numerator, denominator: denominator.clone() }),
+			(Operator::BitAnd | Operator::BitOr | Operator::Xor | Operator::BitNot | Operator::Shl | Operator::Shr, _) if args.iter().all(Rational::is_integer) => {
+				let integers: Vec<BigInt> = args.iter().map(|a| match a {
+					Exact { numerator, .. } => numerator.clone(),
+					Rational::Approximate(_) => unreachable!(),
+				}).collect();
+				Ok(Rational::integer(bitwise(operator, &integers)?))
+			},
+			// Comparing doesn't lose anything, so true and false are always exact, even for approximate numbers.
+			_ if operator.is_logic() => Ok(logic(operator, args)),
+			_ => Ok(Rational::Approximate(operator.calculate(&floats(args))?)),
+		}
+	}
+	
+	fn call(function: Function, args: &[Rational], settings: &Settings) -> Result<Rational, CalcError> {
+		// The condition being approximate doesn't make the answer approximate.
+		if matches!(function, Function::If | Function::IfThen) {
+			return choose(args);
+		}
+		if !args.iter().all(Rational::is_exact) {
+			return Ok(Rational::Approximate(function.calculate(&floats(args))?));
+		}
+		
+		let zero = Rational::integer(0);
+		match function {
+			Function::Abs => Ok(if args[0] < zero { Rational::operate(Operator::Unm, args, settings)? } else { args[0].clone() }),
+			Function::Sgn => Ok(Rational::integer(match args[0].partial_cmp(&zero) {
+				Some(Ordering::Less) => -1,
+				_ => 1,
+			})),
+			Function::Min => Ok(args.iter().cloned().reduce(|a, b| if b < a { b } else { a }).unwrap()),
+			Function::Max => Ok(args.iter().cloned().reduce(|a, b| if b > a { b } else { a }).unwrap()),
+			Function::Clamp => {
+				let max = if args[1] > args[0] { &args[1] } else { &args[0] };
+				Ok(if args[2] < *max { args[2].clone() } else { max.clone() })
+			},
+			Function::Sum => args.iter().cloned().try_fold(zero, |a, b| Rational::operate(Operator::Add, &[a, b], settings)),
+			Function::Mean => {
+				let sum = Rational::call(Function::Sum, args, settings)?;
+				Rational::operate(Operator::Div, &[sum, Rational::integer(args.len())], settings)
+			},
+			Function::Hypot => {
+				let squares: Vec<Rational> = args.iter().map(|a| Rational::operate(Operator::Mul, &[a.clone(), a.clone()], settings)).collect::<Result<_, _>>()?;
+				let half = Rational::new(BigInt::one(), BigInt::from(2u8));
+				Ok(Rational::call(Function::Sum, &squares, settings)?.pow(&half))
+			},
+			Function::Gcd | Function::Lcm if args.iter().all(Rational::is_integer) => {
+				let integers = args.iter().map(|a| match a {
+					Rational::Exact { numerator, .. } => numerator,
+					Rational::Approximate(_) => unreachable!(),
+				});
+				Ok(Rational::integer(if function == Function::Gcd {
+					integers.fold(BigInt::zero(), |a, b| a.gcd(b))
+				} else {
+					integers.fold(BigInt::one(), |a, b| a.lcm(b))
+				}))
+			},
+			Function::Sqrt | Function::Cbrt => match args[0].exact_root(if function == Function::Sqrt { 2 } else { 3 }) {
+				Some(root) => Ok(root),
+				None => Ok(Rational::Approximate(function.calculate(&floats(args))?)),
+			},
+			Function::Root => Ok(args[0].pow(&Rational::operate(Operator::Div, &[Rational::integer(1), args[1].clone()], settings)?)),
+			Function::Re | Function::Conj | Function::Det | Function::Transpose => Ok(args[0].clone()),
+			Function::Norm => Rational::call(Function::Abs, args, settings),
+			Function::Inv => Rational::operate(Operator::Div, &[Rational::integer(1), args[0].clone()], settings),
+			Function::Dot => Rational::operate(Operator::Mul, args, settings),
+			Function::Solve | Function::Lstsq if args[0] != zero => Rational::operate(Operator::Div, &[args[1].clone(), args[0].clone()], settings),
+			Function::LuU | Function::Eig => Ok(args[0].clone()),
+			Function::LuL | Function::LuP | Function::Eigvec => Ok(Rational::integer(1)),
+			Function::QrQ => Ok(Rational::integer(if args[0] < zero { -1 } else { 1 })),
+			Function::QrR => Rational::call(Function::Abs, args, settings),
+			Function::Rank => Ok(Rational::integer(if args[0] == zero { 0 } else { 1 })),
+			Function::Im => Ok(zero),
+			Function::Arg if args[0] >= zero => Ok(zero),
+			// Everything else is transcendental (or an error), so it's floats from here on.
+			_ => Ok(Rational::Approximate(function.calculate(&floats(args))?)),
+		}
+	}
+}
+
+fn floats(args: &[Rational]) -> Vec<f64> {
+	args.iter().map(Rational::float).collect()
+}
+
+impl fmt::Display for Rational {
+	/// Writes this as a fraction in lowest terms, like `1/2` or `-7/3`. Whole numbers don't get a `/1`, and approximate numbers are written like an `f64`.
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Rational::Exact { numerator, denominator } if denominator.is_one() => write!(f, "{}", numerator),
+			Rational::Exact { numerator, denominator } => write!(f, "{}/{}", numerator, denominator),
+			Rational::Approximate(x) => write!(f, "{}", x),
+		}
+	}
+}
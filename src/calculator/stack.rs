@@ -0,0 +1,252 @@
+use std::fmt;
+
+use super::environment::{Environment, Statement};
+use super::error::{CalcError, Span};
+use super::expression::{Expression, Token};
+use super::history::History;
+use super::number::Number;
+
+/// An HP-style RPN calculator: a stack of numbers that sticks around between lines.
+/// 
+/// Numbers get pushed, operators and functions pop their arguments and push the result, and there's a few commands for shuffling the stack around:
+/// `dup`, `drop`, `swap`, `over`, `rot`, `roll n`, `clear`, and `depth`.
+/// Variadic functions need an argument count first, same as `Expression::from_rpn_str`, so `1 2 3 [3] sum`.
+/// 
+/// Variables push their value, and `sto a` takes the top of the stack and stores it in `a`. `ans` is whatever was on top after the last line.
+/// Lines with an `=` (not `==` or `<=` and so on, those are just comparisons) are read as infix, so `a = 3` and `f(x) = x^2` work like they do everywhere else.
+/// Then those functions can be called with an argument count, so `f(x, y)` is `x y [2] f`.
+/// 
+/// A line that's just `u` (or `undo`) takes back the last line that changed anything (stack or variables), and `redo` puts it back.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RpnStack {
+	values: Vec<f64>,
+	environment: Environment,
+	history: History<(Vec<f64>, Environment)>,
+}
+
+impl RpnStack {
+	pub fn new() -> RpnStack {
+		RpnStack::with_undo_depth(History::<(Vec<f64>, Environment)>::DEFAULT_DEPTH)
+	}
+	
+	/// Makes a stack that remembers the last `depth` lines for undoing.
+	pub fn with_undo_depth(depth: usize) -> RpnStack {
+		RpnStack { values: Vec::new(), environment: Environment::new(), history: History::new(depth) }
+	}
+	
+	/// Changes how many lines can be undone.
+	pub fn set_undo_depth(&mut self, depth: usize) {
+		self.history.set_depth(depth);
+	}
+	
+	/// The stack, bottom first. The last one is the top.
+	pub fn values(&self) -> &[f64] {
+		&self.values
+	}
+	
+	/// The variables that have been stored so far.
+	pub fn environment(&self) -> &Environment {
+		&self.environment
+	}
+	
+	/// Runs every word in `line`, left to right.
+	/// 
+	/// If any of them fail, the whole line is thrown out and the stack is left how it was before, so one typo doesn't eat your numbers.
+	pub fn enter(&mut self, line: &str) -> Result<(), CalcError> {
+		let trimmed = line.trim();
+		let start = line.find(trimmed).unwrap_or(0);
+		let span = Some(Span::new(start, start + trimmed.len()));
+		match trimmed {
+			"u" | "undo" => return if self.undo() { Ok(()) } else { Err(CalcError::Parse { message: "Nothing to undo.", span }) },
+			"redo" => return if self.redo() { Ok(()) } else { Err(CalcError::Parse { message: "Nothing to redo.", span }) },
+			_ => {},
+		}
+		
+		let mut stack = self.values.clone();
+		let mut environment = self.environment.clone();
+		
+		if Statement::find_equals(line).is_some() {
+			let statement = Statement::from_infix_str(line, Some(&environment))?;
+			environment.run(&statement)?;
+			self.commit(stack, environment);
+			return Ok(());
+		}
+		let mut arg_count: Option<(usize, Span)> = None;
+		
+		let mut words = line.split_whitespace().map(|word| {
+			// Same trick as `from_rpn_str`, to figure out where each word was.
+			let start = word.as_ptr() as usize - line.as_ptr() as usize;
+			(word, Span::new(start, start + word.len()))
+		});
+		
+		while let Some((word, span)) = words.next() {
+			if let Some(n) = Expression::parse_rpn_arg_count(word).map_err(|e| e.with_span(span))? {
+				arg_count = Some((n, span));
+				continue;
+			}
+			if let Some((_, count_span)) = arg_count {
+				if !matches!(Expression::token_from_rpn_word(word), Some(Token::Function(_) | Token::Variable(_))) {
+					return Err(CalcError::Parse { message: "Argument counts have to come right before a function.", span: Some(count_span) });
+				}
+			}
+			
+			match word {
+				"dup" => {
+					RpnStack::need(&stack, "dup", 1, span)?;
+					stack.push(stack[stack.len() - 1]);
+				},
+				"drop" => {
+					RpnStack::need(&stack, "drop", 1, span)?;
+					stack.pop();
+				},
+				"swap" => {
+					RpnStack::need(&stack, "swap", 2, span)?;
+					let len = stack.len();
+					stack.swap(len - 1, len - 2);
+				},
+				"over" => {
+					RpnStack::need(&stack, "over", 2, span)?;
+					stack.push(stack[stack.len() - 2]);
+				},
+				"rot" => {
+					RpnStack::need(&stack, "rot", 3, span)?;
+					let third = stack.remove(stack.len() - 3);
+					stack.push(third);
+				},
+				"roll" => {
+					// roll n brings the nth thing from the top up to the top. So roll 2 is swap, and roll 3 is rot.
+					let n = match words.next().and_then(|(w, _)| w.parse::<usize>().ok()) {
+						Some(n) if n > 0 => n,
+						_ => return Err(CalcError::Parse { message: "roll needs a count after it, like roll 3.", span: Some(span) }),
+					};
+					RpnStack::need(&stack, "roll", n, span)?;
+					let nth = stack.remove(stack.len() - n);
+					stack.push(nth);
+				},
+				"sto" => {
+					let name = match words.next() {
+						Some((w, _)) if matches!(Expression::token_from_rpn_word(w), Some(Token::Variable(_))) && w != "ans" && w != "Ans" => w,
+						_ => return Err(CalcError::Parse { message: "sto needs a variable name after it, like sto a.", span: Some(span) }),
+					};
+					RpnStack::need(&stack, "sto", 1, span)?;
+					environment.insert(name.to_owned(), stack.pop().unwrap());
+				},
+				"clear" => stack.clear(),
+				"depth" => stack.push(stack.len() as f64),
+				_ => match Expression::token_from_rpn_word(word) {
+					Some(Token::Constant(c)) => stack.push(c),
+					Some(Token::Integer(n)) => stack.push(f64::from_integer(&n, environment.settings()).map_err(|e| e.with_span(span))?),
+					Some(Token::Operator(o)) => {
+						RpnStack::need(&stack, o.symbol(), o.get_parameters(), span)?;
+						let start = stack.len() - o.get_parameters();
+						let result = o.calculate(&stack[start..]).map_err(|e| e.with_span(span))?;
+						stack.truncate(start);
+						stack.push(result);
+					},
+					Some(Token::Function(f)) => {
+						let (f, arg_length) = match arg_count.take() {
+							Some((n, _)) => {
+								let f = f.with_arguments(n);
+								if (f.is_variadic() && n < f.get_parameters()) || (!f.is_variadic() && n != f.get_parameters()) {
+									return Err(CalcError::Arity { name: f.name(), expected: f.get_parameters(), found: n, span: Some(span) });
+								}
+								(f, n)
+							},
+							None if f.is_variadic() => return Err(CalcError::Parse { message: "This function needs an argument count before it, like [3].", span: Some(span) }),
+							None => (f, f.get_parameters()),
+						};
+						RpnStack::need(&stack, f.name(), arg_length, span)?;
+						let start = stack.len() - arg_length;
+						let result = f.calculate(&stack[start..]).map_err(|e| e.with_span(span))?;
+						stack.truncate(start);
+						stack.push(result);
+					},
+					Some(Token::Variable(name)) if arg_count.is_some() => {
+						let (n, _) = arg_count.take().unwrap();
+						if stack.len() < n {
+							return Err(CalcError::UserFunction { name, message: "Not enough arguments for", span: Some(span) });
+						}
+						let args = stack.split_off(stack.len() - n);
+						stack.push(environment.call(&name, &args, Some(span), 0)?);
+					},
+					Some(Token::Variable(name)) => match environment.get(&name) {
+						Some(value) => stack.push(value),
+						None => return Err(CalcError::UndefinedVariable { name, span: Some(span) }),
+					},
+					_ => return Err(CalcError::Lex { message: "Unknown RPN token.", span: Some(span) }),
+				},
+			}
+		}
+		
+		if let Some((_, count_span)) = arg_count {
+			return Err(CalcError::Parse { message: "Argument counts have to come right before a function.", span: Some(count_span) });
+		}
+		
+		if let Some(top) = stack.last() {
+			environment.set_ans(*top);
+		}
+		
+		self.commit(stack, environment);
+		Ok(())
+	}
+	
+	/// Swaps in the results of a line, remembering the old ones for undo if anything changed.
+	fn commit(&mut self, stack: Vec<f64>, environment: Environment) {
+		if stack != self.values || environment != self.environment {
+			let old_values = std::mem::replace(&mut self.values, stack);
+			let old_environment = std::mem::replace(&mut self.environment, environment);
+			self.history.record((old_values, old_environment));
+		}
+	}
+	
+	/// Takes back the last change to the stack. Returns `false` if there wasn't one.
+	pub fn undo(&mut self) -> bool {
+		match self.history.undo((self.values.clone(), self.environment.clone())) {
+			Some((values, environment)) => {
+				self.values = values;
+				self.environment = environment;
+				true
+			},
+			None => false,
+		}
+	}
+	
+	/// Puts back the last change that was undone. Returns `false` if there wasn't one.
+	pub fn redo(&mut self) -> bool {
+		match self.history.redo((self.values.clone(), self.environment.clone())) {
+			Some((values, environment)) => {
+				self.values = values;
+				self.environment = environment;
+				true
+			},
+			None => false,
+		}
+	}
+	
+	/// Makes sure there's at least `count` things on the stack for `name`.
+	fn need(stack: &[f64], name: &'static str, count: usize, span: Span) -> Result<(), CalcError> {
+		if stack.len() < count {
+			return Err(CalcError::Arity { name, expected: count, found: stack.len(), span: Some(span) });
+		}
+		Ok(())
+	}
+}
+
+impl fmt::Display for RpnStack {
+	/// Prints the stack like an HP calculator would, numbered from the top (which is 1, at the bottom of the printout).
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.values.is_empty() {
+			return write!(f, "(empty)");
+		}
+		
+		let width = self.values.len().to_string().len();
+		for (i, value) in self.values.iter().enumerate() {
+			let level = self.values.len() - i;
+			if i > 0 {
+				writeln!(f)?;
+			}
+			write!(f, "{:>width$}: {}", level, value, width = width)?;
+		}
+		Ok(())
+	}
+}
@@ -2,6 +2,8 @@ use std::convert::From;
 use raylib::prelude::*;
 use crate::util;
 use crate::calculator::expression;
+use crate::calculator::error::CalcError;
+use crate::calculator::environment::Environment;
 use crate::graph::common;
 
 const SCALE: i32 = 4;
@@ -31,6 +33,12 @@ impl From<Point> for Vector2 {
 	}
 }
 
+impl Point {
+	pub fn is_finite(&self) -> bool {
+		self.x.is_finite() && self.y.is_finite()
+	}
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Window {
 	pub minimum: Point,
@@ -64,21 +72,20 @@ impl Graph {
 		}
 	}
 	
-	pub fn calculate_expression(&mut self, expr: &Vec<expression::Expression>, points: usize) -> Result<(), &'static str> {
-		let mut variables = expression::ExpressionVariables::new();
-		
+	pub fn calculate_expression(&mut self, expr: &Vec<expression::Expression>, points: usize, variables: Option<&Environment>) -> Result<(), CalcError> {
 		self.data.clear();
 		
 		match self.args {
 			GraphArgs2D::Cartesian => {
 				assert!(expr.len() == 1);
 				
+				let mut f = expr[0].compile(&["x"], variables)?;
+				
 				let mut x;
 				for i in 0..=points {
 					x = util::lerp(self.window.minimum.x, self.window.maximum.x, (i as f64) / (points as f64));
-					variables.insert('x', x);
 					
-					let y = expr[0].calculate(Some(&variables))?;
+					let y = f.calculate(&[x])?;
 					
 					self.data.push(Point { x, y });
 				}
@@ -86,13 +93,15 @@ impl Graph {
 			GraphArgs2D::Parametric(t_minmax) => {
 				assert!(expr.len() == 2);
 				
+				let mut fx = expr[0].compile(&["t"], variables)?;
+				let mut fy = expr[1].compile(&["t"], variables)?;
+				
 				let mut t;
 				for i in 0..=points {
 					t = util::lerp(t_minmax.min, t_minmax.max, (i as f64) / (points as f64));
-					variables.insert('t', t);
 					
-					let x = expr[0].calculate(Some(&variables))?;
-					let y = expr[1].calculate(Some(&variables))?;
+					let x = fx.calculate(&[t])?;
+					let y = fy.calculate(&[t])?;
 					
 					self.data.push(Point { x, y });
 				}
@@ -100,12 +109,13 @@ impl Graph {
 			GraphArgs2D::Polar(t_minmax) => {
 				assert!(expr.len() == 1);
 				
+				let mut f = expr[0].compile(&["t"], variables)?;
+				
 				let mut theta;
 				for i in 0..=points {
 					theta = util::lerp(t_minmax.min, t_minmax.max, (i as f64) / (points as f64));
-					variables.insert('t', theta);
 					
-					let r = expr[0].calculate(Some(&variables))?;
+					let r = f.calculate(&[theta])?;
 					
 					self.data.push(Point { x: r * theta.cos(), y: r * theta.sin(), });
 				}
@@ -129,6 +139,11 @@ impl Graph {
 		let y_axis = self.graph_to_screen_y(0.0) as f32;
 		
 		for i in 1..self.data.len() {
+			// Anything that isn't a real point (like the missing parts of a piecewise function) leaves a gap, instead of a line off to nowhere.
+			if !self.data[i - 1].is_finite() || !self.data[i].is_finite() {
+				continue;
+			}
+			
 			d.draw_line_ex(
 				Vector2::from(self.graph_to_screen_point(&self.data[i - 1])),
 				Vector2::from(self.graph_to_screen_point(&self.data[i    ])),
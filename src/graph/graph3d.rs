@@ -5,6 +5,8 @@ use std::convert::From;
 use raylib::prelude::*;
 use crate::util;
 use crate::calculator::expression;
+use crate::calculator::error::CalcError;
+use crate::calculator::environment::Environment;
 use crate::graph::common;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -68,9 +70,7 @@ impl Graph3D {
 		}
 	}
 	
-	pub fn calculate_expression(&mut self, expr: &Vec<expression::Expression>, points: usize) -> Result<(), &'static str> {
-		let mut variables = expression::ExpressionVariables::new();
-		
+	pub fn calculate_expression(&mut self, expr: &Vec<expression::Expression>, points: usize, variables: Option<&Environment>) -> Result<(), CalcError> {
 		self.data.clear();
 		self.tmp_data_width = points + 1;
 		
@@ -78,17 +78,17 @@ impl Graph3D {
 			GraphArgs3D::Cartesian => {
 				assert!(expr.len() == 1);
 				
+				let mut f = expr[0].compile(&["x", "z"], variables)?;
+				
 				let mut x;
 				let mut z;
 				for i in 0..=points {
 					x = util::lerp(self.window.minimum.x, self.window.maximum.x, (i as f64) / (points as f64));
-					variables.insert('x', x);
 					
 					for j in 0..=points {
 						z = util::lerp(self.window.minimum.z, self.window.maximum.z, (j as f64) / (points as f64));
-						variables.insert('z', z);
 						
-						let y = expr[0].calculate(Some(&variables))?;
+						let y = f.calculate(&[x, z])?;
 						
 						self.data.push(Point3D { x, y, z });
 					}
@@ -97,14 +97,17 @@ impl Graph3D {
 			GraphArgs3D::Parametric(t_minmax) => {
 				assert!(expr.len() == 3);
 				
+				let mut fx = expr[0].compile(&["t"], variables)?;
+				let mut fy = expr[1].compile(&["t"], variables)?;
+				let mut fz = expr[2].compile(&["t"], variables)?;
+				
 				let mut t;
 				for i in 0..=points {
 					t = util::lerp(t_minmax.min, t_minmax.max, (i as f64) / (points as f64));
-					variables.insert('t', t);
 					
-					let x = expr[0].calculate(Some(&variables))?;
-					let y = expr[1].calculate(Some(&variables))?;
-					let z = expr[2].calculate(Some(&variables))?;
+					let x = fx.calculate(&[t])?;
+					let y = fy.calculate(&[t])?;
+					let z = fz.calculate(&[t])?;
 					
 					self.data.push(Point3D { x, y, z });
 				}
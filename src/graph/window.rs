@@ -1,6 +1,7 @@
 use raylib::prelude::*;
 
-use crate::calculator::expression;
+use crate::calculator::{expression, history};
+use crate::calculator::environment::{Environment, Statement};
 use super::{graph3d, common};
 
 const SCALE: i32 = 4;
@@ -21,17 +22,18 @@ pub fn start() {
 		String::from("10sin(2Pi*t)"), String::from("10cos(3t)"), String::from("10sin(t)")
 	];
 	let mut active = 0;
+	let mut input_history: history::History<Vec<String>> = history::History::default();
 	let mut expressions_to_graph: Vec<expression::Expression> = Vec::new();
 	for s in &function_input_strings {
 		expressions_to_graph.push(expression::Expression::new_from_infix(expression::Expression::infix_tokens_from_str(s).unwrap()).unwrap());
 	}
 	let mut graph3d = graph3d::Graph3D::new(graph3d::GraphArgs3D::Parametric(common::MinMax { min: -3.0, max: 3.0 }));
 	graph3d.position = graph3d::Point3D { x: -6.0, y: 0.0, z: 0.0 };
-	graph3d.calculate_expression(&expressions_to_graph, 1024).unwrap();
+	graph3d.calculate_expression(&expressions_to_graph, 1024, None).unwrap();
 	
 	let mut other_graph = graph3d::Graph3D::new(graph3d::GraphArgs3D::Cartesian);
 	other_graph.position = graph3d::Point3D { x: 6.0, y: 0.0, z: 0.0 };
-	other_graph.calculate_expression(&vec![expression::Expression::new_from_infix(expression::Expression::infix_tokens_from_str("8sin(x/8)cos(z/4)").unwrap()).unwrap()], 16).unwrap();
+	other_graph.calculate_expression(&vec![expression::Expression::new_from_infix(expression::Expression::infix_tokens_from_str("8sin(x/8)cos(z/4)").unwrap()).unwrap()], 16, None).unwrap();
 	
 	let mut cam = raylib::core::camera::Camera3D::perspective(
 		Vector3::new(0.0, 1.8, 0.0),
@@ -58,16 +60,35 @@ pub fn start() {
 		
 		// -- Wacky input -- \\
 		
+		let control = rl.is_key_down(KeyboardKey::KEY_LEFT_CONTROL) || rl.is_key_down(KeyboardKey::KEY_RIGHT_CONTROL);
+		
 		if let Some(key) = pressed_key {
 			if let Some(key_char) = std::char::from_u32(key) {
-				function_input_strings[active].push(key_char);
+				// Ctrl+whatever is a shortcut, not typing.
+				if !control {
+					input_history.record(function_input_strings.clone());
+					function_input_strings[active].push(key_char);
+				}
 			}
 		}
 		
-		if rl.is_key_pressed(KeyboardKey::KEY_BACKSPACE) {
+		if rl.is_key_pressed(KeyboardKey::KEY_BACKSPACE) && !function_input_strings[active].is_empty() {
+			input_history.record(function_input_strings.clone());
 			function_input_strings[active].pop();
 		}
 		
+		// Ctrl+Z to undo, Ctrl+Shift+Z or Ctrl+Y to redo. Press enter afterwards to graph it again.
+		if control && rl.is_key_pressed(KeyboardKey::KEY_Z) && !rl.is_key_down(KeyboardKey::KEY_LEFT_SHIFT) {
+			if let Some(old) = input_history.undo(function_input_strings.clone()) {
+				function_input_strings = old;
+			}
+		} else if control && (rl.is_key_pressed(KeyboardKey::KEY_Y) || rl.is_key_pressed(KeyboardKey::KEY_Z)) {
+			if let Some(new) = input_history.redo(function_input_strings.clone()) {
+				function_input_strings = new;
+			}
+		}
+		active = active.min(function_input_strings.len() - 1);
+		
 		if rl.is_key_pressed(KeyboardKey::KEY_TAB) {
 			if rl.is_key_down(KeyboardKey::KEY_LEFT_SHIFT) {
 				if active < 1 {
@@ -80,36 +101,51 @@ pub fn start() {
 			}
 		}
 		
-		if rl.is_key_pressed(KeyboardKey::KEY_ENTER) {
+		// Shift+Enter makes a new box, for things like `a = 3`.
+		if rl.is_key_pressed(KeyboardKey::KEY_ENTER) && rl.is_key_down(KeyboardKey::KEY_LEFT_SHIFT) {
+			input_history.record(function_input_strings.clone());
+			active += 1;
+			function_input_strings.insert(active, String::new());
+		} else if rl.is_key_pressed(KeyboardKey::KEY_ENTER) {
 			let mut is_okay = true;
+			let mut parsed_expressions: Vec<expression::Expression> = Vec::new();
 			
 			angry_error = String::new();
+			// Start over every time, so assignments that got deleted don't stick around.
+			let mut environment = Environment::new();
 			
-			for i in 0..function_input_strings.len() {
+			for input in function_input_strings.iter() {
+				if input.trim().is_empty() {
+					continue;
+				}
 				
-				match expression::Expression::infix_tokens_from_str(&function_input_strings[i]) {
-					Ok(t) => {
-						match expression::Expression::new_from_infix(t) {
-							Ok(expr) => {
-								expressions_to_graph[i] = expr;
-								println!("{}: {:?}", i, expressions_to_graph[i]);
-							},
-							Err(e) => {
-								angry_error = e.to_owned();
-								is_okay = false;
-							},
-						}
-					},
-					Err(e) => {
-						angry_error = e.to_owned();
-						is_okay = false;
+				let result = Statement::from_infix_str(input, Some(&environment)).and_then(|statement| match statement {
+					// Assignments and definitions happen top to bottom, so later boxes can use earlier ones.
+					Statement::Assign { .. } | Statement::Define { .. } => environment.run(&statement).map(|_| ()),
+					Statement::Evaluate(mut expr) => {
+						// Simplifying now means less work for every point we graph.
+						expr.simplify()?;
+						parsed_expressions.push(expr);
+						Ok(())
 					},
+				});
+				
+				if let Err(e) = result {
+					angry_error = e.diagnostic(input);
+					is_okay = false;
+					break;
 				}
 			}
 			
+			if is_okay && parsed_expressions.len() != 3 {
+				angry_error = format!("Need 3 things to graph, not {}.", parsed_expressions.len());
+				is_okay = false;
+			}
+			
 			if is_okay {
-				if let Err(e) = graph3d.calculate_expression(&expressions_to_graph, 1024) {
-					angry_error = e.to_owned();
+				expressions_to_graph = parsed_expressions;
+				if let Err(e) = graph3d.calculate_expression(&expressions_to_graph, 1024, Some(&environment)) {
+					angry_error = e.to_string();
 				}
 			}
 		}
@@ -4,64 +4,209 @@
 #![allow(clippy::tabs_in_doc_comments)]
 
 use std::io;
+use core::convert::TryFrom;
 
 mod util;
 
 mod calculator;
-use crate::calculator::expression;
+use crate::calculator::stack;
+use crate::calculator::environment::{Environment, Statement};
+use crate::calculator::complex::Complex;
+use crate::calculator::rational::Rational;
+use crate::calculator::decimal::Decimal;
+use crate::calculator::integer::{Integer, IntegerType, Overflow};
+use crate::calculator::quantity::Quantity;
+use crate::calculator::matrix::Matrix;
+use crate::calculator::operator::AngleUnit;
 
 mod graph;
 use crate::graph::window;
 
 fn main() {
-	/*let mut input_buffer = String::new();
+	println!("g for graphing, r for an RPN stack, anything else for plain old expressions.");
 	
-	input_buffer.clear();
+	let mut input_buffer = String::new();
 	io::stdin().read_line(&mut input_buffer)
 		.expect("Can't read.");
 	
-	if input_buffer.to_lowercase().trim().starts_with("g") {*/
+	let choice = input_buffer.to_lowercase();
+	if choice.trim().starts_with('g') {
 		window::start();
-	/*} else {
-		//worst_calculator();
+	} else if choice.trim().starts_with('r') {
+		rpn_calc();
+	} else {
 		best_calc();
-	}*/
+	}
+}
+
+fn rpn_calc() {
+	println!("RPN mode. Numbers go on the stack, operators take them off. (dup, drop, swap, over, rot, roll n, clear, depth, sto a, vars, u, redo)");
+	
+	let mut rpn_stack = stack::RpnStack::new();
+	let mut input_buffer = String::new();
+	loop {
+		input_buffer.clear();
+		if io::stdin().read_line(&mut input_buffer).expect("Can't read.") == 0 {
+			break;
+		}
+		
+		if input_buffer.trim() == "vars" {
+			println!("{}", rpn_stack.environment());
+			continue;
+		}
+		
+		if let Err(e) = rpn_stack.enter(&input_buffer) {
+			println!("Nope! Error:\n{}", e.diagnostic(&input_buffer));
+		}
+		println!("{}", rpn_stack);
+	}
+}
+
+/// What kind of numbers `best_calc` calculates with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mode {
+	Real,
+	Complex,
+	/// Exact fractions. `mixed` writes them as mixed numbers, like `1 1/2` instead of `3/2`.
+	Rational { mixed: bool },
+	/// As many digits as `precision` says.
+	Decimal,
+	/// Whole numbers with a fixed number of bits, shown in a bunch of bases.
+	Integer,
+	/// Numbers with physical units, like `9.81 m/s^2`.
+	Units,
+	/// Vectors and matrices, like `[1, 2; 3, 4]`.
+	Matrix,
 }
 
 fn best_calc() {
-	println!("Slap an expression in. I evaluate it.");
+	println!("Slap an expression in. I evaluate it. (a = 3.5 to make a variable, f(x) = x^2 to make a function, vars to see them all, mode real/complex/rational/mixed/decimal/units/matrix/i32/u8/... to switch modes, precision 60 for more digits, overflow wrap/check for integers, angle rad/deg/grad for angles, dms on/off to see angles as degrees, minutes, and seconds)");
 	
+	let mut environment = Environment::new();
+	let mut mode = Mode::Real;
+	let mut dms = false;
 	let mut input_buffer = String::new();
 	loop {
 		input_buffer.clear();
 		io::stdin().read_line(&mut input_buffer)
 			.expect("Can't read.");
 		
-		let tokens = match expression::Expression::infix_tokens_from_str(&input_buffer) {
-			Ok(t) => {
-				println!("infix tokens: {:?}", t);
-				t
+		match input_buffer.trim() {
+			"vars" => {
+				println!("{}", environment);
+				continue;
 			},
-			Err(e) => {
-				println!("Couldn't read! Error: {}", e);
+			"mode real" => {
+				mode = Mode::Real;
+				println!("Real mode.");
 				continue;
-			}
-		};
+			},
+			"mode complex" => {
+				mode = Mode::Complex;
+				println!("Complex mode. i is the square root of -1.");
+				continue;
+			},
+			"mode rational" | "mode mixed" => {
+				mode = Mode::Rational { mixed: input_buffer.trim() == "mode mixed" };
+				println!("Rational mode. Answers are exact fractions, unless they start with ≈.");
+				continue;
+			},
+			"mode decimal" => {
+				mode = Mode::Decimal;
+				println!("Decimal mode. Answers have {} significant digits.", environment.precision());
+				continue;
+			},
+			"mode integer" => {
+				mode = Mode::Integer;
+				println!("Integer mode ({}). Answers are in decimal, hex, octal, and binary.", environment.integer_type());
+				continue;
+			},
+			"mode units" => {
+				mode = Mode::Units;
+				println!("Units mode. Things like 9.81 m/s^2 * 3 kg work, and 60 mph to m/s converts.");
+				continue;
+			},
+			"mode matrix" => {
+				mode = Mode::Matrix;
+				println!("Matrix mode. [1, 2; 3, 4] is a matrix, and v[2] or A[1, 2] picks out a value.");
+				continue;
+			},
+			command if command.starts_with("mode ") && IntegerType::try_from(&command["mode ".len()..]).is_ok() => {
+				environment.set_integer_type(IntegerType::try_from(&command["mode ".len()..]).unwrap());
+				mode = Mode::Integer;
+				println!("Integer mode ({}). Answers are in decimal, hex, octal, and binary.", environment.integer_type());
+				continue;
+			},
+			"overflow wrap" => {
+				environment.set_overflow(Overflow::Wrap);
+				println!("Integers will wrap around when they overflow.");
+				continue;
+			},
+			"overflow check" => {
+				environment.set_overflow(Overflow::Check);
+				println!("Integers will give an error when they overflow.");
+				continue;
+			},
+			command if command.starts_with("angle ") => {
+				match AngleUnit::try_from(command["angle ".len()..].trim()) {
+					Ok(unit) => {
+						environment.set_angle_unit(unit);
+						println!("Angles are in {}.", unit.name());
+					},
+					Err(e) => println!("{}", e),
+				}
+				continue;
+			},
+			"dms on" | "dms off" => {
+				dms = input_buffer.trim() == "dms on";
+				println!("{}", if dms { "Real answers will be shown in degrees, minutes, and seconds too." } else { "No more degrees, minutes, and seconds." });
+				continue;
+			},
+			command if command.starts_with("precision") => {
+				match command["precision".len()..].trim().parse::<usize>() {
+					Ok(digits) if digits > 0 => {
+						environment.set_precision(digits);
+						println!("Decimal mode answers will have {} significant digits.", digits);
+					},
+					_ => println!("That's not a precision. Try something like precision 60."),
+				}
+				continue;
+			},
+			_ => {},
+		}
 		
-		let expr = match expression::Expression::new_from_infix(tokens) {
-			Ok(ex) => {
-				println!("to expression: {:?}", ex);
-				ex
+		let statement = match Statement::from_infix_str(&input_buffer, Some(&environment)) {
+			Ok(s) => {
+				match &s {
+					Statement::Assign { name, expression } => println!("to assignment: {} = {:?}\nwhich is: {} = {}", name, expression, name, expression),
+					Statement::Define { name, parameters, body, .. } => println!("to function: {:?}\nwhich is: {}({}) = {}", body, name, parameters.join(", "), body),
+					Statement::Evaluate(ex) => println!("to expression: {:?}\nwhich is: {}", ex, ex),
+				}
+				s
 			},
 			Err(e) => {
-				println!("Couldn't make expression! Error: {}", e);
+				println!("Couldn't make expression! Error:\n{}", e.diagnostic(&input_buffer));
 				continue;
 			}
 		};
 		
-		match expr.calculate(None) {
-			Ok(r) => println!("= {}", r),
-			Err(e) => println!("Couldn't calculate! Error: {}", e),
+		let result = match mode {
+			Mode::Real if dms => environment.run(&statement).map(|r| r.map(|r| format!("= {} = {}", r, environment.angle_unit().to_dms_string(r)))),
+			Mode::Real => environment.run(&statement).map(|r| r.map(|r| format!("= {}", r))),
+			Mode::Complex => environment.run_as::<Complex>(&statement).map(|r| r.map(|r| format!("= {}", r))),
+			Mode::Rational { mixed } => environment.run_as::<Rational>(&statement).map(|r| r.map(|r| {
+				let written = if mixed { r.to_mixed_string() } else { r.to_string() };
+				format!("{} {}", if r.is_exact() { "=" } else { "≈" }, written)
+			})),
+			Mode::Decimal => environment.run_as::<Decimal>(&statement).map(|r| r.map(|r| format!("= {}", r))),
+			Mode::Integer => environment.run_as::<Integer>(&statement).map(|r| r.map(|r| format!("= {}", r.to_bases_string(environment.integer_type())))),
+			Mode::Units => environment.run_as::<Quantity>(&statement).map(|r| r.map(|r| format!("= {}", r))),
+			Mode::Matrix => environment.run_as::<Matrix>(&statement).map(|r| r.map(|r| format!("= {}", r))),
+		};
+		match result {
+			Ok(Some(r)) => println!("{}", r),
+			Ok(None) => println!("Got it."),
+			Err(e) => println!("Couldn't calculate! Error:\n{}", e.diagnostic(&input_buffer)),
 		}
 	}
 }
@@ -69,7 +214,9 @@ fn best_calc() {
 #[cfg(test)]
 mod tests {
 	use super::*;
-	use super::calculator::operator;
+	use super::calculator::{expression, operator};
+	use super::calculator::error::{CalcError, Span};
+	use super::calculator::environment;
 	
 	#[test]
 	fn calculate_expression() {
@@ -162,8 +309,1298 @@ mod tests {
 		]).unwrap().calculate(None).is_err());
 	}
 	
+	#[test]
+	fn error_spans() {
+		let err = expression::Expression::from_infix_str("2+2^5)/8").unwrap_err();
+		assert_eq!(err, CalcError::Parse { message: "Missing left parentheses.", span: Some(Span::new(5, 6)) });
+		assert_eq!(err.diagnostic("2+2^5)/8"), "2+2^5)/8\n     ^ Missing left parentheses.");
+		
+		let err = expression::Expression::from_infix_str("2*(x+y").unwrap_err();
+		assert_eq!(err.span(), Some(Span::new(2, 3)));
+		
+		// Used to panic instead.
+		assert!(expression::Expression::from_infix_str(")").is_err());
+	}
+	
+	#[test]
+	fn undefined_variable_error() {
+		let mut variables = environment::Environment::new();
+		variables.insert("x".into(), 2.0);
+		
+		let err = expression::Expression::from_infix_str("x + 3y").unwrap()
+			.calculate(Some(&variables)).unwrap_err();
+		assert_eq!(err, CalcError::UndefinedVariable { name: "y".into(), span: Some(Span::new(5, 6)) });
+		assert_eq!(err.diagnostic("x + 3y"), "x + 3y\n     ^ Undefined variable 'y'.");
+	}
+	
+	#[test]
+	fn unary_operators() {
+		let mut variables = environment::Environment::new();
+		variables.insert("x".into(), 3.0);
+		
+		let calc = |s: &str| expression::Expression::from_infix_str(s).unwrap().calculate(Some(&variables)).unwrap();
+		
+		assert_eq!(calc("-x^2"), -9.0);
+		assert_eq!(calc("-2*3"), -6.0);
+		assert_eq!(calc("2^-1"), 0.5);
+		assert_eq!(calc("-(2+3)"), -5.0);
+		assert_eq!(calc("--x"), 3.0);
+		assert_eq!(calc("+x - -x"), 6.0);
+		assert_eq!(calc("sin -x"), (-3.0f64).sin());
+		assert_eq!(calc("Pi - 1"), core::f64::consts::PI - 1.0);
+	}
+	
+	#[test]
+	fn test_txt_lines() {
+		let lines = [
+			("2 + 2", 4.0),
+			("2.00000 + -0.2", 1.8),
+			("2.00000 +-0.2", 1.8),
+			("2.00000-0.2", 1.8),
+			("2.0e8/5", 4.0e7),
+			("5 % 2", 1.0),
+		];
+		
+		for (line, result) in lines.iter() {
+			assert_eq!(expression::Expression::from_infix_str(line).unwrap().calculate(None).unwrap(), *result, "{}", line);
+		}
+	}
+	
+	#[test]
+	fn numeric_literals() {
+		let calc = |s: &str| expression::Expression::from_infix_str(s).unwrap().calculate(None).unwrap();
+		
+		assert_eq!(calc("1e-9"), 1e-9);
+		assert_eq!(calc("6.02E23"), 6.02e23);
+		assert_eq!(calc("1_000_000 + .5"), 1_000_000.5);
+		assert_eq!(calc("2.5e+2"), 250.0);
+		assert_eq!(calc("-inf"), f64::NEG_INFINITY);
+		assert!(calc("nan").is_nan());
+		
+		// Without digits after it, E is still the constant.
+		assert_eq!(calc("2E"), 2.0 * core::f64::consts::E);
+		assert_eq!(calc("2E+1"), 20.0);
+		assert_eq!(calc("2E*5"), 10.0 * core::f64::consts::E);
+		
+		let mut variables = environment::Environment::new();
+		variables.insert("x".into(), 2.0);
+		variables.insert("e".into(), 10.0);
+		let expr = expression::Expression::from_infix_str("3e2x + 2e").unwrap();
+		assert_eq!(expr.calculate(Some(&variables)).unwrap(), 620.0);
+	}
+	
+	#[test]
+	fn multi_argument_functions() {
+		let mut variables = environment::Environment::new();
+		variables.insert("x".into(), 3.0);
+		
+		let calc = |s: &str| expression::Expression::from_infix_str(s).unwrap().calculate(Some(&variables)).unwrap();
+		
+		assert_eq!(calc("max(-32x, x)"), 3.0);
+		assert_eq!(calc("min(2, x) + 1"), 3.0);
+		assert_eq!(calc("clamp(6, 0, 1)"), 1.0);
+		assert_eq!(calc("clamp(x^2, -x, 2x + 1)"), 7.0);
+		assert_eq!(calc("atan2(1, -1)"), 1.0f64.atan2(-1.0));
+		assert_eq!(calc("log(100)"), 2.0);
+		assert_eq!(calc("log(8, 2)"), 3.0);
+		assert_eq!(calc("root(27, x)"), 3.0);
+		assert_eq!(calc("2max(1, 2)^2"), 8.0);
+		assert_eq!(calc("max(min(1, 2), (3))"), 3.0);
+	}
+	
+	#[test]
+	fn multi_argument_errors() {
+		let err = expression::Expression::from_infix_str("1 + clamp(x, 2)").unwrap_err();
+		assert_eq!(err, CalcError::Arity { name: "clamp", expected: 3, found: 2, span: Some(Span::new(4, 9)) });
+		
+		let err = expression::Expression::from_infix_str("sin(1, 2)").unwrap_err();
+		assert!(matches!(err, CalcError::Arity { name: "sin", expected: 1, found: 2, .. }));
+		
+		assert!(expression::Expression::from_infix_str("max()").is_err());
+		assert!(expression::Expression::from_infix_str("(1, 2)").is_err());
+		assert!(expression::Expression::from_infix_str("1, 2").is_err());
+	}
+	
+	#[test]
+	fn variadic_functions() {
+		let calc = |s: &str| expression::Expression::from_infix_str(s).unwrap().calculate(None).unwrap();
+		
+		assert_eq!(calc("max(1, 5, 3, 2)"), 5.0);
+		assert_eq!(calc("min(4)"), 4.0);
+		assert_eq!(calc("sum(1, 2, 3, 4) * 2"), 20.0);
+		assert_eq!(calc("mean(1, 2, 3, 4)"), 2.5);
+		assert_eq!(calc("hypot(2, 3, 6)"), 7.0);
+		assert_eq!(calc("gcd(12, 18, -30)"), 6.0);
+		assert_eq!(calc("lcm(4, 6, 10)"), 60.0);
+		assert_eq!(calc("max(1, sum(2, 3), -max(1, 2))"), 5.0);
+		assert!(calc("max(nan, 1)").is_nan());
+		assert!(calc("min(1, 2, nan)").is_nan());
+		assert!(calc("max({-1 > 0: 1}, 0)").is_nan());
+		
+		// The count goes right in front of the function.
+		assert_eq!(expression::Expression::new(vec![
+			expression::Token::Constant(1.0),
+			expression::Token::Constant(2.0),
+			expression::Token::Constant(3.0),
+			expression::Token::ArgCount(3),
+			expression::Token::Function(operator::Function::Sum),
+		]).calculate(None).unwrap(), 6.0);
+	}
+	
+	#[test]
+	fn variadic_errors() {
+		assert!(matches!(
+			expression::Expression::from_infix_str("sum()").unwrap_err(),
+			CalcError::Arity { name: "sum", expected: 1, found: 0, .. }
+		));
+		assert!(expression::Expression::from_infix_str("max 2").is_err());
+		assert!(matches!(
+			expression::Expression::from_infix_str("gcd(2.5, 5)").unwrap().calculate(None).unwrap_err(),
+			CalcError::Domain { span: Some(_), .. }
+		));
+		assert!(expression::Expression::new(vec![
+			expression::Token::Constant(1.0),
+			expression::Token::Function(operator::Function::Max),
+		]).calculate(None).is_err());
+	}
+	
+	#[test]
+	fn multi_character_variables() {
+		let mut variables = environment::Environment::new();
+		for (name, value) in [("x", 2.0), ("y", 3.0), ("xy", 10.0), ("speed", 4.0), ("theta", 0.5), ("x_1", 7.0), ("v0", 1.5), ("θ", 0.25), ("sinc", 100.0)].iter() {
+			variables.insert(name.to_string(), *value);
+		}
+		
+		let calc = |s: &str| expression::Expression::from_infix_str(s).unwrap().calculate(Some(&variables)).unwrap();
+		
+		assert_eq!(calc("2speed"), 8.0);
+		assert_eq!(calc("xy"), 10.0);
+		assert_eq!(calc("x y"), 6.0);
+		assert_eq!(calc("x*y + x_1"), 13.0);
+		assert_eq!(calc("v0 theta"), 0.75);
+		assert_eq!(calc("sin(θ)"), 0.25f64.sin());
+		assert_eq!(calc("sinc"), 100.0);
+		assert_eq!(calc("max(x, y)(x)"), 6.0);
+		
+		let err = expression::Expression::from_infix_str("infinity + 1").unwrap().calculate(Some(&variables)).unwrap_err();
+		assert_eq!(err, CalcError::UndefinedVariable { name: "infinity".into(), span: Some(Span::new(0, 8)) });
+	}
+	
 	#[test]
 	fn basic_simplify() {
-		unimplemented!()
+		let mut variables = environment::Environment::new();
+		variables.insert("x".into(), 3.0);
+		
+		let mut my_expression = expression::Expression::from_infix_str("2 * 3 + x * 1 + 0 + sin(Pi/2)").unwrap();
+		let before = my_expression.calculate(Some(&variables)).unwrap();
+		
+		assert_eq!(my_expression.simplify().unwrap(), 9);
+		assert_eq!(my_expression.tokens(), &[
+			expression::Token::Constant(6.0),
+			expression::Token::Variable("x".into()),
+			expression::Token::Operator(operator::Operator::Add),
+			expression::Token::Constant(1.0),
+			expression::Token::Operator(operator::Operator::Add),
+		]);
+		assert_eq!(my_expression.calculate(Some(&variables)).unwrap(), before);
+		
+		// Nothing left to do.
+		assert_eq!(my_expression.simplify().unwrap(), 0);
+	}
+	
+	#[test]
+	fn derivatives() {
+		// Compares against a finite difference, since the exact tokens don't really matter.
+		let check = |s: &str, points: &[f64]| {
+			let f = expression::Expression::from_infix_str(s).unwrap();
+			let df = f.derivative("x").unwrap();
+			let mut variables = environment::Environment::new();
+			variables.insert("y".into(), 1.5);
+			
+			for x in points.iter() {
+				let h = 1e-6;
+				variables.insert("x".into(), x + h);
+				let above = f.calculate(Some(&variables)).unwrap();
+				variables.insert("x".into(), x - h);
+				let below = f.calculate(Some(&variables)).unwrap();
+				variables.insert("x".into(), *x);
+				
+				let expected = (above - below) / (2.0 * h);
+				let actual = df.calculate(Some(&variables)).unwrap();
+				assert!((expected - actual).abs() < 1e-4 * expected.abs().max(1.0), "d/dx {} at {}: expected {}, got {}", s, x, expected, actual);
+			}
+		};
+		let points = [-2.3, -0.7, 0.4, 1.1, 2.9];
+		let positive = [0.4, 1.1, 2.9];
+		
+		check("3x^2 - 2x + y", &points);
+		check("-x * y / (x^2 + 1)", &points);
+		check("x % 2 + 7 % x", &positive);
+		check("x^x + 2^x + x^-3", &positive);
+		check("sin(x) + cos(2x) + tan(x/3)", &points);
+		check("csc(x) + sec(x) + cot(x)", &points);
+		check("log(x) + ln(x^2) + log(x, 3) + log(9, x)", &positive);
+		check("abs(x^3) + sgn(x) * x + Pi*E*x", &points);
+		check("min(x, 1) + max(x^2, 2x, -x) + clamp(x, -1, 1)", &points);
+		check("atan2(x, y) + atan2(y, x) + root(x, 3) + root(2, x)", &positive);
+		check("sum(x, x^2, y) + mean(x, 2x) + hypot(x, y, 2)", &points);
+		check("gcd(12, 18) * x", &points);
+		check("asin(x/3) + acos(x/4) + atan(x) + acot(2x)", &points);
+		check("acsc(x^2 + 2) + asec(-x^2 - 2) + sinh(x) + cosh(x/2) + tanh(x)", &points);
+		check("asinh(x) + acosh(x^2 + 2) + atanh(x/3) + cbrt(x) + exp(-x)", &points);
+		check("sqrt(x) + cbrt(x)", &positive);
+	}
+	
+	#[test]
+	fn derivative_results() {
+		let derivative = |s: &str| expression::Expression::from_infix_str(s).unwrap().derivative("x").unwrap();
+		
+		assert_eq!(derivative("y^2 + 4").tokens(), &[expression::Token::Constant(0.0)]);
+		assert_eq!(derivative("5x + y").tokens(), &[expression::Token::Constant(5.0)]);
+		assert!(expression::Expression::from_infix_str("gcd(x, 4)").unwrap().derivative("x").is_err());
+		
+		// The lexer doesn't know about √, so this one's by hand. 2 √ x = sqrt(x)
+		let mut variables = environment::Environment::new();
+		variables.insert("x".into(), 4.0);
+		assert_eq!(expression::Expression::new(vec![
+			expression::Token::Constant(2.0),
+			expression::Token::Variable("x".into()),
+			expression::Token::Operator(operator::Operator::Rot),
+		]).derivative("x").unwrap().calculate(Some(&variables)).unwrap(), 0.25);
+	}
+	
+	#[test]
+	fn newton_root() {
+		let f = expression::Expression::from_infix_str("x^2 - 2").unwrap();
+		assert!((f.newton_root("x", 1.0, None).unwrap() - 2.0f64.sqrt()).abs() < 1e-12);
+		assert!((f.newton_root("x", -1.0, None).unwrap() + 2.0f64.sqrt()).abs() < 1e-12);
+		
+		let f = expression::Expression::from_infix_str("cos(x) - x").unwrap();
+		let root = f.newton_root("x", 0.0, None).unwrap();
+		assert!((root.cos() - root).abs() < 1e-12);
+		
+		let f = expression::Expression::from_infix_str("x^2 + 1").unwrap();
+		assert!(f.newton_root("x", 0.0, None).is_err());
+	}
+	
+	#[test]
+	fn compiled_matches_calculate() {
+		let sources = ["8sin(x/8)cos(z/4)", "max(x, z, 1) - hypot(x, z) + x % 3", "-x^2 + log(abs(z) + 1, 2)", "clamp(x z, -2, 2) + Pi"];
+		
+		for source in sources.iter() {
+			let expr = expression::Expression::from_infix_str(source).unwrap();
+			let mut compiled = expr.compile(&["x", "z"], None).unwrap();
+			let mut variables = environment::Environment::new();
+			
+			for i in -5..=5 {
+				for j in -5..=5 {
+					let (x, z) = (i as f64 * 1.3, j as f64 * 0.7);
+					variables.insert("x".into(), x);
+					variables.insert("z".into(), z);
+					assert_eq!(compiled.calculate(&[x, z]).unwrap(), expr.calculate(Some(&variables)).unwrap(), "{}", source);
+				}
+			}
+		}
+	}
+	
+	#[test]
+	fn compile_errors() {
+		let compile = |s: &str| expression::Expression::from_infix_str(s).unwrap().compile(&["x"], None);
+		
+		assert_eq!(compile("x + y").unwrap_err(), CalcError::UndefinedVariable { name: "y".into(), span: Some(Span::new(4, 5)) });
+		assert!(matches!(expression::Expression::new(vec![
+			expression::Token::Constant(1.0),
+			expression::Token::Operator(operator::Operator::Add),
+		]).compile(&[], None).unwrap_err(), CalcError::Arity { name: "+", expected: 2, found: 1, .. }));
+		assert_eq!(expression::Expression::new(vec![
+			expression::Token::Constant(1.0),
+			expression::Token::Constant(2.0),
+		]).compile(&[], None).unwrap_err(), CalcError::Leftover { count: 2 });
+		assert!(compile("gcd(x, 2)").unwrap().calculate(&[0.5]).is_err());
+	}
+	
+	#[test]
+	fn display_round_trip() {
+		let cases = [
+			("2 * 5 + 2", "2*5 + 2"),
+			("(2 + 5) * 6 ^ 2", "(2 + 5)*6^2"),
+			("2 - (3 - x) - (y - 1)", "2 - (3 - x) - (y - 1)"),
+			("(2 - x) - y", "2 - x - y"),
+			("a / (b * c) * d", "a/(b*c)*d"),
+			("2^3^x", "2^3^x"),
+			("(2^3)^x", "(2^3)^x"),
+			("-x^2 + (-x)^2 + 2^-x", "-x^2 + (-x)^2 + 2^(-x)"),
+			("--x * -(y + 1)", "--x*-(y + 1)"),
+			("10sin(2Pi*t)", "10*sin(2*Pi*t)"),
+			("sin(x)^2 + cos x", "sin(x)^2 + cos(x)"),
+			("max(1, x, y + 2) / log(x, 3)", "max(1, x, y + 2)/log(x, 3)"),
+			("3 √ (x + 1)", "3√(x + 1)"),
+			("theta_1 % 2.5e-3", "theta_1%0.0025"),
+		];
+		
+		for (input, output) in cases.iter() {
+			let expr = expression::Expression::from_infix_str(input).unwrap();
+			assert_eq!(expr.to_string(), *output);
+			
+			let again = expression::Expression::new_from_infix(expression::Expression::infix_tokens_from_str(&expr.to_string()).unwrap()).unwrap();
+			assert_eq!(again.tokens(), expr.tokens(), "{}", input);
+		}
+		
+		// Folded constants can be negative, which reads back in as a unary minus.
+		let mut expr = expression::Expression::from_infix_str("(1 - 3)^x - (0 - 2)").unwrap();
+		expr.simplify().unwrap();
+		assert_eq!(expr.to_string(), "(-2)^x - -2");
+		let mut again = expression::Expression::from_infix_str(&expr.to_string()).unwrap();
+		again.simplify().unwrap();
+		assert_eq!(again.tokens(), expr.tokens());
+	}
+	
+	#[test]
+	fn rpn_strings() {
+		let mut variables = environment::Environment::new();
+		variables.insert("x".into(), 0.5);
+		
+		let expr = expression::Expression::from_rpn_str("2 4 * x sin +").unwrap();
+		assert_eq!(expr.calculate(Some(&variables)).unwrap(), 8.0 + 0.5f64.sin());
+		assert_eq!(expr.to_string(), "2*4 + sin(x)");
+		
+		let calc = |s: &str| expression::Expression::from_rpn_str(s).unwrap().calculate(None).unwrap();
+		assert_eq!(calc("1 2 /"), 0.5);
+		assert_eq!(calc("-2.5 neg 1_000 +"), 1002.5);
+		assert_eq!(calc("1 5 3 [3] max"), 5.0);
+		assert_eq!(calc("8 2 [2] log"), 3.0);
+		assert_eq!(calc("Pi 2 / sin"), 1.0);
+		
+		// Everything should make it back out the way it came in.
+		for source in ["2*x^2 - 3x + 1", "max(1, x, -y) / log(x, 3)", "-(x + 1) % 2.5e-3 + +x"].iter() {
+			let expr = expression::Expression::from_infix_str(source).unwrap();
+			let again = expression::Expression::from_rpn_str(&expr.to_rpn_string()).unwrap();
+			assert_eq!(again.tokens(), expr.tokens(), "{} -> {}", source, expr.to_rpn_string());
+		}
+		assert_eq!(expression::Expression::from_infix_str("-x + max(1, 2)").unwrap().to_rpn_string(), "x neg 1 2 [2] max +");
+	}
+	
+	#[test]
+	fn rpn_string_errors() {
+		let err = |s: &str| expression::Expression::from_rpn_str(s).unwrap_err();
+		
+		assert_eq!(err("2 * 3"), CalcError::Arity { name: "*", expected: 2, found: 1, span: Some(Span::new(2, 3)) });
+		assert_eq!(err("1 2 3 +"), CalcError::Leftover { count: 2 });
+		assert_eq!(err(""), CalcError::Leftover { count: 0 });
+		assert!(matches!(err("1 2 max"), CalcError::Parse { span: Some(Span { start: 4, end: 7 }), .. }));
+		assert!(matches!(err("1 [2] sin"), CalcError::Arity { name: "sin", expected: 1, found: 2, .. }));
+		assert!(matches!(err("1 [2] 2"), CalcError::Parse { .. }));
+		assert!(matches!(err("1 ( +"), CalcError::Lex { span: Some(Span { start: 2, end: 3 }), .. }));
+	}
+	
+	#[test]
+	fn simplify_identities() {
+		let simplified = |s: &str| {
+			let mut expr = expression::Expression::from_infix_str(s).unwrap();
+			expr.simplify().unwrap();
+			expr.tokens().to_vec()
+		};
+		let x = || vec![expression::Token::Variable("x".into())];
+		
+		assert_eq!(simplified("1*x"), x());
+		assert_eq!(simplified("(0 + x - 0)/1"), x());
+		assert_eq!(simplified("x^(2-1)"), x());
+		assert_eq!(simplified("+x"), x());
+		assert_eq!(simplified("max(1, 2, 3) * x"), vec![
+			expression::Token::Constant(3.0),
+			expression::Token::Variable("x".into()),
+			expression::Token::Operator(operator::Operator::Mul),
+		]);
+		assert_eq!(simplified("max(x, 2)").len(), 4);
+		assert_eq!(simplified("x*0").len(), 3);
+		assert_eq!(simplified("0-x").len(), 3);
+		
+		assert!(expression::Expression::from_infix_str("x + gcd(1.5, 3)").unwrap().simplify().is_err());
+	}
+	
+	#[test]
+	fn rpn_stack() {
+		let mut s = stack::RpnStack::new();
+		
+		s.enter("1 2 3").unwrap();
+		assert_eq!(s.values(), &[1.0, 2.0, 3.0]);
+		s.enter("+").unwrap();
+		assert_eq!(s.values(), &[1.0, 5.0]);
+		s.enter("dup *").unwrap();
+		assert_eq!(s.values(), &[1.0, 25.0]);
+		s.enter("swap").unwrap();
+		assert_eq!(s.values(), &[25.0, 1.0]);
+		s.enter("over").unwrap();
+		assert_eq!(s.values(), &[25.0, 1.0, 25.0]);
+		s.enter("rot").unwrap();
+		assert_eq!(s.values(), &[1.0, 25.0, 25.0]);
+		s.enter("drop 7 roll 3").unwrap();
+		assert_eq!(s.values(), &[25.0, 7.0, 1.0]);
+		s.enter("depth").unwrap();
+		assert_eq!(s.values(), &[25.0, 7.0, 1.0, 3.0]);
+		s.enter("[4] sum neg").unwrap();
+		assert_eq!(s.values(), &[-36.0]);
+		s.enter("Pi cos neg 2 [2] log").unwrap();
+		assert_eq!(s.values(), &[-36.0, 0.0]);
+		s.enter("clear").unwrap();
+		assert_eq!(s.values(), &[] as &[f64]);
+		assert_eq!(s.to_string(), "(empty)");
+		
+		s.enter("4 -2.5 9 8 7 6 5 4 3 2 1").unwrap();
+		assert_eq!(s.to_string().lines().next(), Some("11: 4"));
+		assert_eq!(s.to_string().lines().last(), Some(" 1: 1"));
+	}
+	
+	#[test]
+	fn rpn_stack_errors() {
+		let mut s = stack::RpnStack::new();
+		s.enter("1 2").unwrap();
+		
+		// A bad line leaves the stack alone.
+		assert_eq!(s.enter("3 + + +"), Err(CalcError::Arity { name: "+", expected: 2, found: 1, span: Some(Span::new(6, 7)) }));
+		assert_eq!(s.values(), &[1.0, 2.0]);
+		
+		assert_eq!(s.enter("roll 3").unwrap_err().span(), Some(Span::new(0, 4)));
+		assert!(matches!(s.enter("roll"), Err(CalcError::Parse { .. })));
+		assert!(matches!(s.enter("sum"), Err(CalcError::Parse { .. })));
+		assert!(matches!(s.enter("[2] dup"), Err(CalcError::Parse { .. })));
+		assert!(matches!(s.enter("x"), Err(CalcError::UndefinedVariable { .. })));
+		assert!(matches!(s.enter("1 ("), Err(CalcError::Lex { .. })));
+		assert!(matches!(s.enter("1.5 [3] gcd"), Err(CalcError::Domain { .. })));
+		assert_eq!(s.values(), &[1.0, 2.0]);
+	}
+	
+	#[test]
+	fn rpn_stack_undo() {
+		let mut s = stack::RpnStack::with_undo_depth(2);
+		
+		s.enter("1 2").unwrap();
+		s.enter("3").unwrap();
+		s.enter("*").unwrap();
+		assert_eq!(s.values(), &[1.0, 6.0]);
+		
+		// Lines that fail or don't change anything aren't worth undoing.
+		assert!(s.enter("+ +").is_err());
+		s.enter("dup drop").unwrap();
+		
+		s.enter("u").unwrap();
+		assert_eq!(s.values(), &[1.0, 2.0, 3.0]);
+		s.enter("undo").unwrap();
+		assert_eq!(s.values(), &[1.0, 2.0]);
+		// Only two levels deep.
+		assert!(matches!(s.enter("u"), Err(CalcError::Parse { .. })));
+		
+		s.enter(" redo ").unwrap();
+		assert_eq!(s.values(), &[1.0, 2.0, 3.0]);
+		
+		// Doing something new forgets what could've been redone.
+		s.enter("-").unwrap();
+		assert!(s.enter("redo").is_err());
+		assert_eq!(s.values(), &[1.0, -1.0]);
+		
+		s.set_undo_depth(0);
+		assert!(!s.undo());
+	}
+	
+	#[test]
+	fn assignments() {
+		let mut env = environment::Environment::new();
+		let mut run = |s: &str| environment::Statement::from_infix_str(s, None).and_then(|st| env.run(&st)).unwrap().unwrap();
+		
+		assert_eq!(run("a = 3.5"), 3.5);
+		assert_eq!(run("b=2a"), 7.0);
+		assert_eq!(run("a + b"), 10.5);
+		assert_eq!(run("ans * 2"), 21.0);
+		assert_eq!(run("Ans - a"), 17.5);
+		assert_eq!(run("a = a + 1"), 4.5);
+		
+		assert_eq!(env.get("a"), Some(4.5));
+		assert_eq!(env.get("ans"), Some(4.5));
+		assert_eq!(env.to_string(), "a = 4.5\nb = 7\nans = 4.5");
+		assert_eq!(environment::Environment::new().to_string(), "No variables yet.");
+		
+		// Variables stick around for compiling, too.
+		assert_eq!(expression::Expression::from_infix_str("x + b").unwrap().compile(&["x"], Some(&env)).unwrap().calculate(&[1.0]).unwrap(), 8.0);
+	}
+	
+	#[test]
+	fn assignment_errors() {
+		let parse = |s: &str| environment::Statement::from_infix_str(s, None).unwrap_err();
+		
+		assert_eq!(parse("ans = 2").span(), Some(Span::new(0, 3)));
+		assert_eq!(parse("2 = 2").span(), Some(Span::new(0, 1)));
+		assert_eq!(parse("a b = 2").span(), Some(Span::new(0, 1)));
+		assert_eq!(parse(" = 2").span(), Some(Span::new(1, 2)));
+		// Errors on the right side still point into the whole line.
+		assert_eq!(parse("a = 2 + (3").span(), Some(Span::new(8, 9)));
+		assert_eq!(parse("a = 2 ) 3").span(), Some(Span::new(6, 7)));
+		
+		let mut env = environment::Environment::new();
+		let statement = environment::Statement::from_infix_str("a = b + 1", None).unwrap();
+		assert_eq!(env.run(&statement).unwrap_err(), CalcError::UndefinedVariable { name: "b".into(), span: Some(Span::new(4, 5)) });
+		assert_eq!(env.get("a"), None);
+		assert_eq!(env.ans(), None);
+	}
+	
+	#[test]
+	fn rpn_stack_variables() {
+		let mut s = stack::RpnStack::new();
+		
+		s.enter("2 3 sto a").unwrap();
+		assert_eq!(s.values(), &[2.0]);
+		s.enter("a a *").unwrap();
+		assert_eq!(s.values(), &[2.0, 9.0]);
+		s.enter("ans +").unwrap();
+		assert_eq!(s.values(), &[2.0, 18.0]);
+		
+		assert!(matches!(s.enter("sto"), Err(CalcError::Parse { .. })));
+		assert!(matches!(s.enter("sto 2"), Err(CalcError::Parse { .. })));
+		assert!(matches!(s.enter("sto ans"), Err(CalcError::Parse { .. })));
+		assert!(matches!(s.enter("b"), Err(CalcError::UndefinedVariable { .. })));
+		
+		// Undo puts the variables back too.
+		s.enter("sto a").unwrap();
+		assert_eq!(s.environment().get("a"), Some(18.0));
+		s.enter("u").unwrap();
+		assert_eq!(s.environment().get("a"), Some(3.0));
+		assert_eq!(s.values(), &[2.0, 18.0]);
+	}
+	
+	#[test]
+	fn user_functions() {
+		let mut env = environment::Environment::new();
+		let mut run = |s: &str| environment::Statement::from_infix_str(s, Some(&env)).and_then(|st| env.run(&st)).unwrap();
+		
+		assert_eq!(run("f(x) = x^2 - 3x"), None);
+		assert_eq!(run("g(a, b) = hypot(a, b)"), None);
+		assert_eq!(run("k() = 7"), None);
+		assert_eq!(run("f(4)"), Some(4.0));
+		assert_eq!(run("g(3, 4) + 2f(1)k()"), Some(5.0 - 28.0));
+		assert_eq!(run("a = f(f(2))"), Some(10.0));
+		// Parameters hide variables, and don't leak out.
+		assert_eq!(run("x = 100"), Some(100.0));
+		assert_eq!(run("f(2) + x"), Some(98.0));
+		
+		// Redefining a function changes everything that calls it.
+		assert_eq!(run("h(x) = f(x) + 1"), None);
+		assert_eq!(run("f(x) = 2x"), None);
+		assert_eq!(run("h(5)"), Some(11.0));
+		
+		let statement = environment::Statement::from_infix_str("f(x, 1)", Some(&env)).unwrap();
+		assert_eq!(statement_text(&statement), "f(x, 1)");
+		assert!(env.to_string().contains("f(x) = 2*x\ng(a, b) = hypot(a, b)\nh(x) = f(x) + 1\nk() = 7"));
+		
+		// Graphing goes through the compiled version, which should agree.
+		let expr = match environment::Statement::from_infix_str("h(t)*g(t, 2) + k()", Some(&env)).unwrap() {
+			environment::Statement::Evaluate(e) => e,
+			_ => unreachable!(),
+		};
+		let mut compiled = expr.compile(&["t"], Some(&env)).unwrap();
+		for t in [-2.0, 0.0, 0.5, 3.0] {
+			let mut with_t = env.clone();
+			with_t.insert("t".into(), t);
+			assert_eq!(compiled.calculate(&[t]).unwrap(), expr.calculate(Some(&with_t)).unwrap());
+		}
+		
+		// And RPN can call them too.
+		assert_eq!(expression::Expression::from_rpn_str("3 4 [2] g").unwrap().calculate(Some(&env)).unwrap(), 5.0);
+		assert_eq!(expr.to_rpn_string(), "t [1] h t 2 [2] g * [0] k +");
+	}
+	
+	fn statement_text(statement: &environment::Statement) -> String {
+		match statement {
+			environment::Statement::Evaluate(e) => e.to_string(),
+			_ => unreachable!(),
+		}
+	}
+	
+	#[test]
+	fn user_function_errors() {
+		let mut env = environment::Environment::new();
+		let mut run = |s: &str| environment::Statement::from_infix_str(s, Some(&env)).and_then(|st| env.run(&st));
+		
+		run("f(x) = x + 1").unwrap();
+		run("g(x, y) = f(x) * y").unwrap();
+		
+		assert_eq!(run("g(1)").unwrap_err(), CalcError::UserFunction { name: "g".into(), message: "Wrong number of arguments for", span: Some(Span::new(0, 1)) });
+		assert_eq!(run("2 + f(1, 2)").unwrap_err().span(), Some(Span::new(4, 5)));
+		
+		// Loops through other functions aren't allowed...
+		assert!(matches!(run("f(x) = g(x, 1)").unwrap_err(), CalcError::UserFunction { message: "This would make a loop through", .. }));
+		assert_eq!(run("f(3)").unwrap(), Some(4.0));
+		// ...but a function calling itself is, it just can't go on forever.
+		run("r(x) = r(x - 1) + 1").unwrap();
+		assert!(matches!(run("r(0)").unwrap_err(), CalcError::UserFunction { message: "Too much recursion in", .. }));
+		// Once it knows when to stop, it does.
+		run("r(x) = if(x <= 0, 0, r(x - 1) + 1)").unwrap();
+		assert_eq!(run("r(0)").unwrap(), Some(0.0));
+		assert_eq!(run("r(200)").unwrap(), Some(200.0));
+		assert!(matches!(run("r(1000)").unwrap_err(), CalcError::UserFunction { message: "Too much recursion in", .. }));
+		run("fib(n) = {n < 2: n, fib(n - 1) + fib(n - 2)}").unwrap();
+		assert_eq!(run("fib(15)").unwrap(), Some(610.0));
+		
+		let parse = |s: &str| environment::Statement::from_infix_str(s, None).unwrap_err();
+		assert_eq!(parse("sin(x) = x").span(), Some(Span::new(0, 3)));
+		assert_eq!(parse("f(x, x) = x").span(), Some(Span::new(5, 6)));
+		assert_eq!(parse("f(2) = 2").span(), Some(Span::new(2, 3)));
+		assert_eq!(parse("f(x y) = 2").span(), Some(Span::new(4, 5)));
+		assert!(matches!(parse("f(x = 2"), CalcError::Parse { message: "Missing right parentheses.", span: Some(Span { start: 2, end: 3 }) }));
+	}
+	
+	#[test]
+	fn user_function_compile_errors() {
+		let mut env = environment::Environment::new();
+		for s in ["f(x) = x + y", "r(x) = r(x)", "g(a, b) = a - b"] {
+			let st = environment::Statement::from_infix_str(s, Some(&env)).unwrap();
+			env.run(&st).unwrap();
+		}
+		let compile = |s: &str| match environment::Statement::from_infix_str(s, Some(&env)).unwrap() {
+			environment::Statement::Evaluate(e) => e.compile(&["x"], Some(&env)),
+			_ => unreachable!(),
+		};
+		
+		// The y inside f isn't defined anywhere, and the error points at where f was called.
+		assert_eq!(compile("2 + f(x)").unwrap_err(), CalcError::UndefinedVariable { name: "y".into(), span: Some(Span::new(4, 5)) });
+		assert!(matches!(compile("g(x)").unwrap_err(), CalcError::UserFunction { message: "Wrong number of arguments for", .. }));
+		assert!(matches!(compile("r(x)").unwrap().calculate(&[1.0]).unwrap_err(), CalcError::UserFunction { message: "Too much recursion in", .. }));
+		assert!(expression::Expression::from_rpn_str("1 [1] f").unwrap().compile(&[], None).is_err());
+		assert!(matches!(expression::Expression::from_rpn_str("x [1] g").unwrap().derivative("x"), Err(CalcError::UserFunction { .. })));
+	}
+	
+	#[test]
+	fn rpn_stack_user_functions() {
+		let mut s = stack::RpnStack::new();
+		
+		s.enter("f(x, y) = x - y").unwrap();
+		s.enter("a = 10").unwrap();
+		s.enter("a 4 [2] f").unwrap();
+		assert_eq!(s.values(), &[6.0]);
+		assert!(matches!(s.enter("[2] f"), Err(CalcError::UserFunction { message: "Not enough arguments for", .. })));
+		assert!(matches!(s.enter("1 [1] f"), Err(CalcError::UserFunction { message: "Wrong number of arguments for", .. })));
+		assert!(matches!(s.enter("[1] nope"), Err(CalcError::UserFunction { message: "Unknown function", .. })));
+		
+		s.enter("u").unwrap();
+		s.enter("u").unwrap();
+		assert_eq!(s.environment().get("a"), None);
+		assert!(s.environment().function("f").is_some());
+	}
+	
+	#[test]
+	fn complex_numbers() {
+		let complex = |s: &str| expression::Expression::from_infix_str(s).unwrap().evaluate::<Complex>(None).unwrap();
+		
+		assert_eq!(complex("(-1)^0.5"), Complex::I);
+		assert_eq!(complex("2√(-4)"), Complex::new(0.0, 2.0));
+		assert_eq!(complex("i^2"), Complex::real(-1.0));
+		assert_eq!(complex("(3 + 4i) * (1 - 2i)"), Complex::new(11.0, -2.0));
+		assert_eq!(complex("(3 + 4i) / i"), Complex::new(4.0, -3.0));
+		assert_eq!(complex("ln(-2)"), Complex::new(2f64.ln(), core::f64::consts::PI));
+		assert_eq!(complex("abs(3 + 4i)"), Complex::real(5.0));
+		assert_eq!(complex("re(3 - 4i) + im(3 - 4i)"), Complex::real(-1.0));
+		assert_eq!(complex("arg(-2i)"), Complex::real(-core::f64::consts::FRAC_PI_2));
+		assert_eq!(complex("conj(1 + i)"), Complex::new(1.0, -1.0));
+		assert!((complex("cis(Pi) + 1").norm()) < 1e-15);
+		// Real stuff still works the same.
+		assert_eq!(complex("max(2, 3) + 1/0"), Complex::real(f64::INFINITY));
+		assert!(expression::Expression::from_infix_str("max(i, 3)").unwrap().evaluate::<Complex>(None).is_err());
+		
+		assert_eq!(Complex::new(3.0, 4.0).to_string(), "3 + 4i");
+		assert_eq!(Complex::new(1.0, -1.0).to_string(), "1 - i");
+		assert_eq!(Complex::new(0.0, -2.5).to_string(), "-2.5i");
+		assert_eq!(Complex::real(7.0).to_string(), "7");
+		
+		// Real mode doesn't know about i, so it's just another variable there. Same for re and friends without their parentheses.
+		assert!(matches!(expression::Expression::from_infix_str("2i").unwrap().calculate(None), Err(CalcError::UndefinedVariable { .. })));
+		assert!(expression::Expression::from_infix_str("(-1)^0.5").unwrap().calculate(None).unwrap().is_nan());
+		let mut env = environment::Environment::new();
+		let mut run = |s: &str| environment::Statement::from_infix_str(s, None).and_then(|st| env.run(&st)).unwrap();
+		assert_eq!(run("i = 3"), Some(3.0));
+		assert_eq!(run("2i"), Some(6.0));
+		assert_eq!(run("re = 2"), Some(2.0));
+		assert_eq!(run("re i + re(i)"), Some(9.0));
+	}
+	
+	#[test]
+	fn complex_environment() {
+		let mut env = environment::Environment::new();
+		for s in ["f(z) = z^2 + 1", "a = 3"] {
+			let st = environment::Statement::from_infix_str(s, Some(&env)).unwrap();
+			env.run_as::<Complex>(&st).unwrap();
+		}
+		let run = |env: &mut environment::Environment, s: &str| {
+			let st = environment::Statement::from_infix_str(s, Some(env)).unwrap();
+			env.run_as::<Complex>(&st)
+		};
+		
+		assert_eq!(run(&mut env, "f(a i)").unwrap(), Some(Complex::real(-8.0)));
+		assert_eq!(env.ans(), Some(-8.0));
+		assert_eq!(run(&mut env, "f(1 + i)").unwrap(), Some(Complex::new(1.0, 2.0)));
+		assert_eq!(env.ans(), Some(-8.0));
+		assert!(matches!(run(&mut env, "b = 2i"), Err(CalcError::Domain { message: "Only real numbers can be stored in variables.", .. })));
+		assert_eq!(env.get("b"), None);
+		// A stored i wins over the real one.
+		assert_eq!(run(&mut env, "i = 3").unwrap(), Some(Complex::real(3.0)));
+		assert_eq!(run(&mut env, "2i").unwrap(), Some(Complex::real(6.0)));
+	}
+	
+	#[test]
+	fn rational_numbers() {
+		let rational = |s: &str| expression::Expression::from_infix_str(s).unwrap().evaluate::<Rational>(None).unwrap();
+		let exact = |s: &str| {
+			let r = rational(s);
+			assert!(r.is_exact(), "{} should be exact", s);
+			r.to_string()
+		};
+		
+		assert_eq!(exact("1/3 + 1/6"), "1/2");
+		assert_eq!(exact("0.1 + 0.2"), "3/10");
+		assert_eq!(exact("(2/3)^-2"), "9/4");
+		assert_eq!(exact("(8/27)^(2/3)"), "4/9");
+		assert_eq!(exact("3√(-1/8)"), "-1/2");
+		assert_eq!(exact("2^100"), "1267650600228229401496703205376");
+		assert_eq!(exact("-7/2 % 2"), "-3/2");
+		assert_eq!(exact("gcd(12, 18) + max(1/3, 1/4) + abs(-1/3)"), "20/3");
+		assert_eq!(exact("mean(1, 2) + hypot(3/5, 4/5)"), "5/2");
+		
+		assert_eq!(rational("-7/3").to_mixed_string(), "-2 1/3");
+		assert_eq!(rational("9/4").to_mixed_string(), "2 1/4");
+		assert_eq!(rational("3/4").to_mixed_string(), "3/4");
+		
+		// Anything transcendental falls back to floats, and stays that way.
+		assert_eq!(rational("2^0.5"), Rational::Approximate(2f64.sqrt()));
+		assert!(!rational("sin(Pi) + 1/3").is_exact());
+		assert!(!rational("1/0").is_exact());
+		
+		// Variables are stored as floats, but come back as the same fraction.
+		let mut env = environment::Environment::new();
+		let st = environment::Statement::from_infix_str("a = 1/3", None).unwrap();
+		env.run_as::<Rational>(&st).unwrap();
+		assert_eq!(expression::Expression::from_infix_str("3a").unwrap().evaluate::<Rational>(Some(&env)).unwrap(), Rational::integer(1));
+	}
+	
+	#[test]
+	fn decimal_numbers() {
+		let decimal = |s: &str| expression::Expression::from_infix_str(s).unwrap().evaluate::<Decimal>(None).unwrap().to_string();
+		
+		assert_eq!(environment::Environment::new().precision(), Decimal::DEFAULT_PRECISION);
+		assert_eq!(decimal("Pi"), "3.1415926535897932384626433832795028841971693993751");
+		assert_eq!(decimal("4 atan2(1, 1)"), "3.1415926535897932384626433832795028841971693993751");
+		assert_eq!(decimal("E"), "2.7182818284590452353602874713526624977572470937");
+		assert_eq!(decimal("2√2"), "1.4142135623730950488016887242096980785696718753769");
+		assert_eq!(decimal("2^1.5"), "2.8284271247461900976033774484193961571393437507539");
+		assert_eq!(decimal("ln(2)"), "0.69314718055994530941723212145817656807550013436026");
+		assert_eq!(decimal("ln(10^30 + 7)"), "69.077552789821370520539743640537926228033044658863");
+		assert_eq!(decimal("sin(1)"), "0.84147098480789650665250232163029899962256306079837");
+		assert_eq!(decimal("cos(10^20)"), "0.763970404441728300400146802737881122834473441747");
+		assert_eq!(decimal("sin(10^-30)"), "1e-30");
+		assert_eq!(decimal("3/7"), "0.42857142857142857142857142857142857142857142857143");
+		
+		// Things that come out exact stay exact.
+		assert_eq!(decimal("0.1 + 0.2"), "0.3");
+		assert_eq!(decimal("log(1000) + 3√(-8) + hypot(3, 4)"), "6");
+		assert_eq!(decimal("2^100"), "1267650600228229401496703205376");
+		assert_eq!(decimal("-7.5 % 2"), "-1.5");
+		assert_eq!(decimal("1/0"), "inf");
+		assert_eq!(decimal("ln(-1)"), "NaN");
+		
+		// Precision comes from the environment, so it only changes things that are calculated with it.
+		let mut env = environment::Environment::new();
+		env.set_precision(20);
+		let decimal_in = |s: &str| expression::Expression::from_infix_str(s).unwrap().evaluate::<Decimal>(Some(&env)).unwrap().to_string();
+		assert_eq!(decimal_in("1/7"), "0.14285714285714285714");
+		assert_eq!(decimal_in("Pi * 10^40"), "3.1415926535897932385e40");
+		assert_eq!(decimal("1/7"), "0.14285714285714285714285714285714285714285714285714");
+	}
+	
+	#[test]
+	fn integer_numbers() {
+		let mut env = environment::Environment::new();
+		let integer = |s: &str, env: &environment::Environment| expression::Expression::from_infix_str(s).unwrap().evaluate::<Integer>(Some(env)).map(|i| i.to_string());
+		let real = |s: &str| expression::Expression::from_infix_str(s).unwrap().evaluate::<f64>(None).unwrap();
+		
+		// Literals and operators work everywhere, not just in integer mode.
+		assert_eq!(real("0xFF + 0b1010_0101 + 0o17"), 255.0 + 165.0 + 15.0);
+		assert_eq!(real("1 << 2 + 3"), 32.0);
+		assert_eq!(real("0xF0 | 0x0F & 0x3C"), 252.0);
+		assert_eq!(real("6 xor 3"), 5.0);
+		assert_eq!(real("~5"), -6.0);
+		assert_eq!(real("-7 >> 1"), -4.0);
+		// Too big to be an exact f64, so only modes that can keep it exact take it.
+		assert!(expression::Expression::from_infix_str("0x1_0000_0000_0000_0001").unwrap().evaluate::<f64>(None).is_err());
+		assert!(expression::Expression::from_infix_str("9007199254740993").unwrap().evaluate::<f64>(None).is_err());
+		assert_eq!(real("18446744073709551616"), 2f64.powi(64));
+		assert!(expression::Expression::from_infix_str("1.5 & 1").unwrap().evaluate::<f64>(None).is_err());
+		assert_eq!(expression::Expression::from_rpn_str("0xFF 4 >> ~").unwrap().evaluate::<f64>(None).unwrap(), -16.0);
+		
+		assert_eq!(env.integer_type(), IntegerType::DEFAULT);
+		assert_eq!(integer("7 / 2", &env).unwrap(), "3");
+		assert_eq!(integer("-7 / 2", &env).unwrap(), "-3");
+		assert_eq!(integer("-7 % 2", &env).unwrap(), "-1");
+		assert_eq!(integer("3 ^ 39", &env).unwrap(), "4052555153018976267");
+		assert!(integer("3 ^ 40", &env).is_err());
+		assert!(integer("1 / 2 * 0.5", &env).is_err());
+		assert!(integer("1 / 0", &env).is_err());
+		assert!(integer("sin(1)", &env).is_err());
+		
+		// Big literals stay exact, all the way to the edges.
+		assert_eq!(integer("9007199254740993", &env).unwrap(), "9007199254740993");
+		assert_eq!(integer("9223372036854775807", &env).unwrap(), "9223372036854775807");
+		assert_eq!(integer("0x7FFF_FFFF_FFFF_FFFF", &env).unwrap(), "9223372036854775807");
+		assert_eq!(integer("-9223372036854775807 - 1", &env).unwrap(), "-9223372036854775808");
+		assert!(integer("9223372036854775808", &env).is_err());
+		let big = expression::Expression::from_infix_str("9007199254740993 + x").unwrap();
+		assert_eq!(big.to_string(), "9007199254740993 + x");
+		assert_eq!(expression::Expression::from_rpn_str(&big.to_rpn_string()).unwrap().tokens(), big.tokens());
+		assert_eq!(expression::Expression::from_rpn_str("-9007199254740993 1 -").unwrap().evaluate::<Integer>(None).unwrap().to_string(), "-9007199254740994");
+		let mut big = expression::Expression::from_infix_str("9007199254740993 + 1").unwrap();
+		big.simplify().unwrap();
+		assert_eq!(big.evaluate::<Integer>(None).unwrap().to_string(), "9007199254740994");
+		env.set_integer_type(IntegerType::try_from("u64").unwrap());
+		assert_eq!(integer("18446744073709551615", &env).unwrap(), "18446744073709551615");
+		assert_eq!(integer("0xFFFF_FFFF_FFFF_FFFF", &env).unwrap(), "18446744073709551615");
+		assert!(integer("18446744073709551616", &env).is_err());
+		env.set_integer_type(IntegerType::try_from("u128").unwrap());
+		assert_eq!(integer("340282366920938463463374607431768211455", &env).unwrap(), "340282366920938463463374607431768211455");
+		assert!(integer("340282366920938463463374607431768211456", &env).is_err());
+		
+		env.set_integer_type(IntegerType::try_from("u8").unwrap());
+		assert_eq!(integer("~0", &env).unwrap(), "255");
+		assert!(integer("200 + 100", &env).is_err());
+		assert!(integer("0 - 1", &env).is_err());
+		assert_eq!(integer("1 << 7", &env).unwrap(), "128");
+		
+		env.set_overflow(Overflow::Wrap);
+		assert_eq!(integer("200 + 100", &env).unwrap(), "44");
+		assert_eq!(integer("0 - 1", &env).unwrap(), "255");
+		assert_eq!(integer("1 << 1000000", &env).unwrap(), "0");
+		assert_eq!(integer("3 ^ 1000001", &env).unwrap(), "3");
+		
+		env.set_integer_type(IntegerType::try_from("i8").unwrap());
+		assert_eq!(integer("127 + 1", &env).unwrap(), "-128");
+		// Those were only this environment's settings.
+		assert_eq!(integer("127 + 1", &environment::Environment::new()).unwrap(), "128");
+		assert_eq!(expression::Expression::from_infix_str("-1").unwrap().evaluate::<Integer>(Some(&env)).unwrap().to_bases_string(env.integer_type()), "-1   0xFF   0o377   0b1111_1111");
+		assert_eq!(integer("-128 >> 100", &env).unwrap(), "-1");
+		
+		env.set_overflow(Overflow::Check);
+		assert!(integer("abs(-128)", &env).is_err());
+		assert!(IntegerType::try_from("i7").is_err());
+		
+		// Modes without a width get to use as many bits as they want.
+		assert_eq!(expression::Expression::from_infix_str("1 << 100 | 1").unwrap().evaluate::<Rational>(None).unwrap().to_string(), "1267650600228229401496703205377");
+		assert_eq!(expression::Expression::from_infix_str("~(1 << 70)").unwrap().evaluate::<Decimal>(None).unwrap().to_string(), "-1180591620717411303425");
+		assert_eq!(expression::Expression::from_infix_str("123456789012345678901 + 1").unwrap().evaluate::<Decimal>(None).unwrap().to_string(), "123456789012345678902");
+		assert_eq!(expression::Expression::from_infix_str("123456789012345678901 / 2").unwrap().evaluate::<Rational>(None).unwrap().to_string(), "123456789012345678901/2");
+	}
+	
+	#[test]
+	fn inverse_functions() {
+		let calc = |s: &str| expression::Expression::from_infix_str(s).unwrap().calculate(None).unwrap();
+		let close = |a: f64, b: f64| (a - b).abs() < 1e-12;
+		
+		// Whole names only, so nothing gets split up.
+		assert_eq!(expression::Expression::infix_tokens_from_str("asinh(x)").unwrap()[0], expression::Token::Function(operator::Function::Asinh));
+		assert_eq!(expression::Expression::infix_tokens_from_str("asin").unwrap()[0], expression::Token::Function(operator::Function::Asin));
+		
+		assert!(close(calc("asin(1)"), std::f64::consts::FRAC_PI_2));
+		assert!(close(calc("acos(-1) + acot(0) + acot(-1)"), std::f64::consts::PI * 2.25));
+		assert!(close(calc("acsc(2) + asec(2)"), std::f64::consts::FRAC_PI_2));
+		assert!(close(calc("cosh(2)^2 - sinh(2)^2"), 1.0));
+		assert!(close(calc("tanh(asinh(3) - asinh(3)) + acosh(1) + atanh(0)"), 0.0));
+		assert_eq!(calc("sqrt(16) + cbrt(-8) + exp(0)"), 3.0);
+		assert!(calc("asin(2)").is_nan());
+		
+		let mut env = environment::Environment::new();
+		env.set_angle_unit(AngleUnit::Degrees);
+		assert_eq!(expression::Expression::from_infix_str("asin(1) + atan(1)").unwrap().calculate(Some(&env)).unwrap(), 135.0);
+		assert_eq!(expression::Expression::from_infix_str("sinh(1)").unwrap().calculate(Some(&env)).unwrap(), 1f64.sinh());
+		
+		// Every function and its inverse undo each other, at least somewhere.
+		for name in ["sin", "cos", "tan", "csc", "sec", "cot", "sinh", "cosh", "tanh", "exp", "ln"] {
+			let f = operator::Function::try_from(name).unwrap();
+			let inverse = f.inverse().unwrap();
+			assert_eq!(inverse.inverse(), Some(f));
+			let x = if f == operator::Function::Csc || f == operator::Function::Sec { 1.3 } else { 0.7 };
+			assert!(close(inverse.calculate(&[f.calculate(&[x]).unwrap()]).unwrap(), x), "{}", name);
+		}
+		assert_eq!(operator::Function::Sqrt.inverse(), None);
+		
+		// Other kinds of numbers have them too.
+		let complex = |s: &str| expression::Expression::from_infix_str(s).unwrap().evaluate::<Complex>(None).unwrap();
+		let z = complex("sin(asin(2 + i))");
+		assert!((z.re - 2.0).abs() < 1e-12 && (z.im - 1.0).abs() < 1e-12);
+		let z = complex("asin(2)");
+		assert!(close(z.re, std::f64::consts::FRAC_PI_2) && !z.is_real());
+		assert_eq!(complex("sqrt(-4)"), Complex::new(0.0, 2.0));
+		assert_eq!(complex("cbrt(-27)"), Complex::real(-3.0));
+		let z = complex("tanh(atanh(0.5 + 2i))");
+		assert!((z.re - 0.5).abs() < 1e-12 && (z.im - 2.0).abs() < 1e-12);
+		
+		let decimal = |s: &str| expression::Expression::from_infix_str(s).unwrap().evaluate::<Decimal>(None).unwrap().to_string();
+		assert_eq!(decimal("asin(1)"), decimal("Pi/2"));
+		assert_eq!(decimal("sinh(1)"), "1.1752011936438014568823818505956008151557179813341");
+		assert_eq!(decimal("asinh(10^-30)"), "1e-30");
+		assert_eq!(decimal("sqrt(2)"), decimal("2√2"));
+		assert_eq!(decimal("cbrt(-8) + exp(0)"), "-1");
+		
+		let rational = |s: &str| expression::Expression::from_infix_str(s).unwrap().evaluate::<Rational>(None).unwrap();
+		assert_eq!(rational("sqrt(9/4) + cbrt(-1/8)").to_string(), "1");
+		assert!(!rational("sqrt(2)").is_exact());
+	}
+	
+	#[test]
+	fn angle_units() {
+		let mut env = environment::Environment::new();
+		let calc = |s: &str, env: &environment::Environment| expression::Expression::from_infix_str(s).unwrap().calculate(Some(env)).unwrap();
+		
+		// Radians unless somebody says otherwise, but units after a number always win.
+		assert_eq!(calc("sin(Pi/2)", &env), 1.0);
+		assert!((calc("sin(30°)", &env) - 0.5).abs() < 1e-15);
+		assert_eq!(calc("180deg", &env), std::f64::consts::PI);
+		assert_eq!(calc("200grad - Pi", &env), 0.0);
+		
+		env.set_angle_unit(AngleUnit::Degrees);
+		assert_eq!(calc("sin(90)", &env), 1.0);
+		assert_eq!(calc("sin(180)", &env), 0.0);
+		assert_eq!(calc("cos(-360)", &env), 1.0);
+		assert_eq!(calc("tan(90)", &env), f64::INFINITY);
+		assert!((calc("sin(30)", &env) - 0.5).abs() < 1e-15);
+		assert_eq!(calc("cos(Pi rad)", &env), -1.0);
+		assert_eq!(calc("100grad", &env), 90.0);
+		assert_eq!(calc("atan2(1, 1)", &env), 45.0);
+		// Units only count after something, so this is still a variable.
+		env.insert("rad".into(), 2.0);
+		assert_eq!(calc("rad * 3rad", &env), 6.0 * 180.0 / std::f64::consts::PI);
+		
+		env.set_angle_unit(AngleUnit::Gradians);
+		assert_eq!(calc("cos(200)", &env), -1.0);
+		assert_eq!(calc("90°", &env), 100.0);
+		
+		// Other kinds of numbers get their angles right too.
+		env.set_angle_unit(AngleUnit::Degrees);
+		let decimal = expression::Expression::from_infix_str("sin(30)").unwrap().evaluate::<Decimal>(Some(&env)).unwrap().to_string();
+		assert_eq!(decimal, "0.5");
+		let complex = expression::Expression::from_infix_str("arg(i)").unwrap().evaluate::<Complex>(Some(&env)).unwrap();
+		assert!((complex.re - 90.0).abs() < 1e-12);
+		
+		// Compiling, differentiating, and simplifying all agree.
+		let f = expression::Expression::from_infix_str("sin(x) + 2x°").unwrap();
+		let mut compiled = f.compile(&["x"], Some(&env)).unwrap();
+		env.insert("x".into(), 30.0);
+		assert!((compiled.calculate(&[30.0]).unwrap() - calc("sin(x) + 2x°", &env)).abs() < 1e-15);
+		let mut trig = expression::Expression::from_infix_str("sin(x) + 10cos(x/2)").unwrap().compile(&["x"], Some(&env)).unwrap();
+		env.insert("x".into(), 180.0);
+		assert_eq!(trig.calculate(&[180.0]).unwrap(), calc("sin(x) + 10cos(x/2)", &env));
+		assert_eq!(trig.calculate(&[180.0]).unwrap(), 0.0);
+		env.insert("x".into(), 30.0);
+		let slope = f.derivative_in("x", AngleUnit::Degrees).unwrap().calculate(Some(&env)).unwrap();
+		assert!((slope - (30f64.to_radians().cos() * std::f64::consts::PI / 180.0 + 2.0)).abs() < 1e-15);
+		let mut folded = expression::Expression::from_infix_str("cos(60)").unwrap();
+		folded.simplify_in(AngleUnit::Degrees).unwrap();
+		assert!((folded.calculate(None).unwrap() - 0.5).abs() < 1e-15);
+		
+		// Units survive being written back out.
+		for s in ["sin(x)°", "(-30)°", "2^30°", "x rad + 3 grad"] {
+			let written = expression::Expression::from_infix_str(s).unwrap().to_string();
+			assert_eq!(written, s);
+		}
+		assert_eq!(expression::Expression::from_rpn_str("30 deg sin").unwrap().to_string(), "sin(30°)");
+		
+		assert_eq!(AngleUnit::Degrees.to_dms_string(30.5125), "30°30'45\"");
+		assert_eq!(AngleUnit::Degrees.to_dms_string(-0.999999999), "-1°0'0\"");
+		assert_eq!(AngleUnit::Radians.to_dms_string(std::f64::consts::PI), "180°0'0\"");
+	}
+	
+	#[test]
+	fn comparisons_and_piecewise() {
+		let calc = |s: &str| expression::Expression::from_infix_str(s).unwrap().calculate(None).unwrap();
+		
+		assert_eq!(calc("1 < 2"), 1.0);
+		assert_eq!(calc("2 <= 1"), 0.0);
+		assert_eq!(calc("3 >= 3 and 4 != 5"), 1.0);
+		assert_eq!(calc("1 == 2 or not 0"), 1.0);
+		// Comparisons are below arithmetic and bitwise stuff, and logic is below them.
+		assert_eq!(calc("1 + 1 == 2"), 1.0);
+		assert_eq!(calc("6 & 1 == 0"), 1.0);
+		assert_eq!(calc("0 and 1 or 1"), 1.0);
+		assert_eq!(calc("not 1 == 2"), 1.0);
+		assert_eq!(calc("(1 < 2) + (3 < 4)"), 2.0);
+		assert!(calc("nan < 1").is_nan());
+		
+		assert_eq!(calc("if(2 > 1, 10, 20)"), 10.0);
+		assert_eq!(calc("if(0, 10, 20)"), 20.0);
+		assert_eq!(calc("{-3 < 0: 3, 4}"), 3.0);
+		assert_eq!(calc("2{1 > 2: 1, 2 > 3: 2, 3}"), 6.0);
+		assert_eq!(calc("{1: {0: 1, 2}, 3}"), 2.0);
+		assert!(calc("{1 > 2: 1}").is_nan());
+		
+		let expr = expression::Expression::from_infix_str("{x < 0: -x, x < 2: x^2}").unwrap();
+		assert_eq!(expr.to_string(), "if(x < 0, -x, if(x < 2, x^2))");
+		assert_eq!(expr.to_rpn_string(), "x 0 < x neg x 2 < x 2 ^ [2] if if");
+		assert_eq!(expression::Expression::from_rpn_str(&expr.to_rpn_string()).unwrap().to_string(), expr.to_string());
+		let mut f = expr.compile(&["x"], None).unwrap();
+		assert_eq!(f.calculate(&[-3.0]).unwrap(), 3.0);
+		assert_eq!(f.calculate(&[1.5]).unwrap(), 2.25);
+		assert!(f.calculate(&[5.0]).unwrap().is_nan());
+		let mut slope = expr.derivative("x").unwrap().compile(&["x"], None).unwrap();
+		assert_eq!(slope.calculate(&[-3.0]).unwrap(), -1.0);
+		assert_eq!(slope.calculate(&[1.5]).unwrap(), 3.0);
+		assert!(slope.calculate(&[5.0]).unwrap().is_nan());
+		
+		for s in ["not x and y", "(not x) + 1", "x < 1 or x >= 2", "x == (1 == 0)"] {
+			assert_eq!(expression::Expression::from_infix_str(s).unwrap().to_string(), s);
+		}
+		assert_eq!(expression::Expression::from_rpn_str("x 0 >= x x neg if").unwrap().to_string(), "if(x >= 0, x, -x)");
+		
+		let parse = |s: &str| expression::Expression::from_infix_str(s).unwrap_err();
+		assert_eq!(parse("{1: 2, 3, 4}"), CalcError::Parse { message: "Only the last piece can leave out its condition.", span: Some(Span::new(7, 8)) });
+		assert_eq!(parse("{x < 0: 1"), CalcError::Parse { message: "Missing right brace.", span: Some(Span::new(0, 1)) });
+		assert_eq!(parse("x: 1"), CalcError::Parse { message: "Colons only go in piecewise expressions, like {x < 0: -x, x}.", span: Some(Span::new(1, 2)) });
+		
+		// Comparisons aren't assignments.
+		let mut env = environment::Environment::new();
+		let mut run = |s: &str| environment::Statement::from_infix_str(s, Some(&env)).and_then(|st| env.run(&st)).unwrap();
+		assert_eq!(run("a = 3 == 3"), Some(1.0));
+		assert_eq!(run("a != 2"), Some(1.0));
+		assert_eq!(run("a <= 1 >= 1"), Some(1.0));
+		assert_eq!(run("f(x) = {x >= 0: x, -x}"), None);
+		
+		// Or on the RPN stack, either.
+		let mut s = stack::RpnStack::new();
+		s.enter("1 2 < 1 2 <= 1 2 > 1 2 >= 1 2 == 1 2 !=").unwrap();
+		assert_eq!(s.values(), &[1.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+		s.enter("clear 3 3 == 3 3 <= 3 3 >= 3 3 !=").unwrap();
+		assert_eq!(s.values(), &[1.0, 1.0, 1.0, 0.0]);
+		s.enter("a = 3 == 3").unwrap();
+		assert_eq!(s.environment().get("a"), Some(1.0));
+		
+		// Only the side that gets picked is calculated, so functions can call themselves.
+		assert_eq!(run("fact(n) = if(n <= 0, 1, n*fact(n - 1))"), None);
+		assert_eq!(run("fact(5)"), Some(120.0));
+		assert_eq!(run("p(x) = {x <= 0: 0, x + p(x - 1)}"), None);
+		assert_eq!(run("p(4)"), Some(10.0));
+		assert_eq!(run("if(nan, fact(-1), fact(-2))").map(f64::is_nan), Some(true));
+		let mut f = match environment::Statement::from_infix_str("fact(x) + p(x)", Some(&env)).unwrap() {
+			environment::Statement::Evaluate(e) => e.compile(&["x"], Some(&env)).unwrap(),
+			_ => unreachable!(),
+		};
+		assert_eq!(f.calculate(&[5.0]).unwrap(), 135.0);
+		assert_eq!(f.calculate(&[0.0]).unwrap(), 1.0);
+		
+		// And the other side can't go wrong.
+		assert_eq!(expression::Expression::from_infix_str("if(1 > 0, 5, 1/0)").unwrap().evaluate::<Integer>(None).unwrap().to_string(), "5");
+		assert!(expression::Expression::from_infix_str("if(1 < 0, 5, 1/0)").unwrap().evaluate::<Integer>(None).is_err());
+		let mut dead = expression::Expression::from_infix_str("if(1 > 0, x, gcd(1.5, x)) + if(x > 0, 1, gcd(1.5, 3))").unwrap();
+		dead.simplify().unwrap();
+		assert_eq!(dead.to_string(), "x + if(x > 0, 1, gcd(1.5, 3))");
+		let mut compiled = dead.compile(&["x"], None).unwrap();
+		assert_eq!(compiled.calculate(&[2.0]).unwrap(), 3.0);
+		assert!(compiled.calculate(&[-2.0]).is_err());
+		assert_eq!(expression::Expression::from_infix_str("if(1 > 0, x^2, gcd(1.5, x))").unwrap().derivative("x").unwrap().to_string(), "2*x");
+		
+		// Running out of pieces is only a problem if it happens, since not every kind of number has nan.
+		let integer = |s: &str| expression::Expression::from_infix_str(s).unwrap().evaluate::<Integer>(None);
+		assert_eq!(integer("{1 > 0: 5}").unwrap().to_string(), "5");
+		assert_eq!(integer("{1 < 0: 5, 2 > 1: 6}").unwrap().to_string(), "6");
+		assert_eq!(integer("{1 < 0: 5}"), Err(CalcError::Domain { message: "None of the pieces are true, and integer mode doesn't have nan.", span: Some(Span::new(0, 1)) }));
+		
+		// Other kinds of numbers compare exactly.
+		assert_eq!(expression::Expression::from_infix_str("1/3 < 0.3333333333333333").unwrap().evaluate::<Rational>(None).unwrap(), Rational::integer(0));
+		assert_eq!(expression::Expression::from_infix_str("if(0.5 > 1/3, 1/3, 0)").unwrap().evaluate::<Rational>(None).unwrap().to_string(), "1/3");
+		let decimal = expression::Expression::from_infix_str("{1 < 2: 1/3}").unwrap().evaluate::<Decimal>(None).unwrap().to_string();
+		assert!(decimal.starts_with("0.333333333333333333"));
+		assert_eq!(expression::Expression::from_infix_str("i == i").unwrap().evaluate::<Complex>(None).unwrap(), Complex::real(1.0));
+	}
+	
+	#[test]
+	fn units() {
+		let units = |s: &str| expression::Expression::from_infix_str(s).unwrap().evaluate::<Quantity>(None).map(|q| q.to_string());
+		
+		assert_eq!(units("9.81 m/s^2 * 3 kg").unwrap(), "29.43 N");
+		assert_eq!(units("60 mph to m/s").unwrap(), "26.8224 m/s");
+		assert_eq!(units("100 km/h to m/s").unwrap(), "27.7777777777778 m/s");
+		assert_eq!(units("1 km + 500 m").unwrap(), "1.5 km");
+		assert_eq!(units("1 mi + 1 km to m").unwrap(), "2609.344 m");
+		assert_eq!(units("2 kWh to MJ").unwrap(), "7.2 MJ");
+		assert_eq!(units("5 us to ns").unwrap(), "5000 ns");
+		assert_eq!(units("3 m * 2 m").unwrap(), "6 m^2");
+		assert_eq!(units("sqrt(9 m^2)").unwrap(), "3 m");
+		assert_eq!(units("12 V / (4 ohm)").unwrap(), "3 A");
+		assert_eq!(units("1 / (2 s)").unwrap(), "0.5 Hz");
+		assert_eq!(units("3 m/s / (2 s^2)").unwrap(), "1.5 m/s^3");
+		assert_eq!(units("2 kg / (4 m^3)").unwrap(), "0.5 kg/m^3");
+		assert_eq!(units("1 ft > 30 cm").unwrap(), "1");
+		assert_eq!(units("(1 h) / (30 minute)").unwrap(), "2");
+		assert_eq!(units("sin(30°) * 2").unwrap(), "1");
+		
+		// Things that don't make sense physically are errors.
+		assert_eq!(units("2 m + 3 s"), Err(CalcError::Dimension { left: "m".to_owned(), right: "s".to_owned(), span: Some(Span::new(4, 5)) }));
+		assert_eq!(units("60 mph to kg").unwrap_err().to_string(), "Units don't match. (m/s and kg)");
+		assert_eq!(units("1 < 2 kg"), Err(CalcError::Dimension { left: "no units".to_owned(), right: "kg".to_owned(), span: Some(Span::new(2, 3)) }));
+		assert!(units("sqrt(2 m)").is_err());
+		assert!(units("sin(3 m)").is_err());
+		assert!(units("2 m to 3 m").is_err());
+		assert!(matches!(units("5 furlongs"), Err(CalcError::UndefinedVariable { .. })));
+		assert_eq!(units("(m^100)*(m^100)"), Err(CalcError::Domain { message: "That's too many of one unit to keep track of.", span: Some(Span::new(7, 8)) }));
+		assert!(units("1/(m^100)/(m^28)").is_err());
+		assert_eq!(units("(m^100)*(m^27)/m^127").unwrap(), "1");
+		
+		// Variables win over units, and `to` only converts when there's something to convert.
+		let mut env = environment::Environment::new();
+		env.insert("m".into(), 2.0);
+		env.insert("to".into(), 3.0);
+		let q = expression::Expression::from_infix_str("m * 3 s + to s").unwrap().evaluate::<Quantity>(Some(&env)).unwrap();
+		assert_eq!(q.to_string(), "9 s");
+		
+		// Variables (and `ans`) can hold amounts with units too.
+		let statement = |s: &str, env: &environment::Environment| environment::Statement::from_infix_str(s, Some(env)).unwrap();
+		let mut env = environment::Environment::new();
+		env.run_as::<Quantity>(&statement("d = 5 km", &env)).unwrap();
+		assert_eq!(env.run_as::<Quantity>(&statement("d * 2", &env)).unwrap().unwrap().to_string(), "10 km");
+		assert_eq!(env.run_as::<Quantity>(&statement("ans to m", &env)).unwrap().unwrap().to_string(), "10000 m");
+		assert_eq!(env.run_as::<Quantity>(&statement("d / (2 h)", &env)).unwrap().unwrap().to_string(), "0.694444444444444 m/s");
+		assert_eq!(env.to_string(), "d = 5 km\nans = 0.694444444444444 m/s");
+		// Plain numbers still go in as plain numbers, and replace the quantity.
+		env.run_as::<Quantity>(&statement("d = 2", &env)).unwrap();
+		assert_eq!(env.get("d"), Some(2.0));
+		assert!(env.quantity("d").is_none());
+		
+		// Without units, there's nothing to convert.
+		assert!(expression::Expression::from_infix_str("1 to 2").unwrap().calculate(None).is_err());
+	}
+	
+	#[test]
+	fn matrices() {
+		use crate::calculator::error::{CalcError, Span};
+		
+		let matrix = |s: &str| expression::Expression::from_infix_str(s)?.evaluate::<Matrix>(None).map(|m| m.to_string());
+		
+		assert_eq!(matrix("[1, 2; 3, 4]").unwrap(), "[1, 2; 3, 4]");
+		assert_eq!(matrix("[1, 2] + [3, 4]").unwrap(), "[4, 6]");
+		assert_eq!(matrix("2[1, -2] - 1").unwrap(), "[1, -5]");
+		assert_eq!(matrix("[1, 2; 3, 4] * [5; 6]").unwrap(), "[17; 39]");
+		assert_eq!(matrix("[1, 1; 1, 0]^10").unwrap(), "[89, 55; 55, 34]");
+		assert_eq!(matrix("[2, 0; 0, 4]^-1").unwrap(), "[0.5, 0; 0, 0.25]");
+		assert_eq!(matrix("[1, 2] / [2, 0; 0, 4]").unwrap(), "[0.5, 0.5]");
+		assert_eq!(matrix("[1, 2, 3] < 2").unwrap(), "[1, 0, 0]");
+		assert_eq!(matrix("abs([-1, 2])").unwrap(), "[1, 2]");
+		assert_eq!(matrix("sum([1, 2; 3, 4])").unwrap(), "10");
+		assert_eq!(matrix("det([1, 2; 3, 4])").unwrap(), "-2");
+		assert_eq!(matrix("det([0, 1, 0; 0, 0, 1; 1, 0, 0])").unwrap(), "1");
+		assert_eq!(matrix("inv([2, 1; 1, 1])").unwrap(), "[1, -1; -1, 2]");
+		assert_eq!(matrix("transpose([1, 2, 3])").unwrap(), "[1; 2; 3]");
+		assert_eq!(matrix("dot([1, 2, 3], [4; 5; 6])").unwrap(), "32");
+		assert_eq!(matrix("cross([1, 0, 0], [0, 1, 0])").unwrap(), "[0, 0, 1]");
+		assert_eq!(matrix("norm([3, 4])").unwrap(), "5");
+		assert_eq!(matrix("2 + 3").unwrap(), "5");
+		
+		// Indexes count from 1. A matrix with one index gives back a row.
+		assert_eq!(matrix("[5, 6, 7][2]").unwrap(), "6");
+		assert_eq!(matrix("[1, 2; 3, 4][2]").unwrap(), "[3, 4]");
+		assert_eq!(matrix("[1, 2; 3, 4][2, 1] + 1").unwrap(), "4");
+		assert_eq!(matrix("-[1, 2][2]^2").unwrap(), "-4");
+		assert_eq!(matrix("[5, 6][3]").unwrap_err().to_string(), "That index is past the end.");
+		assert_eq!(matrix("[5, 6][0]").unwrap_err().to_string(), "Indexes have to be whole numbers, starting at 1.");
+		
+		// Sizes have to work out.
+		assert_eq!(matrix("[1, 2] + [1, 2, 3]"), Err(CalcError::Shape { left: (1, 2), right: (1, 3), span: Some(Span::new(7, 8)) }));
+		assert_eq!(matrix("[1, 2] * [3, 4]").unwrap_err().to_string(), "Sizes don't match. (1x2 and 1x2)");
+		assert_eq!(matrix("inv([1, 2; 2, 4])").unwrap_err().to_string(), "That matrix can't be inverted.");
+		assert_eq!(matrix("det([1, 2])").unwrap_err().to_string(), "That only works on square matrices.");
+		
+		// Parsing.
+		let parse_error = |s: &str| expression::Expression::from_infix_str(s).unwrap_err().to_string();
+		assert_eq!(parse_error("[1, 2; 3]"), "Every row of a matrix needs to be the same length.");
+		assert_eq!(parse_error("[]"), "Matrices can't be empty.");
+		assert_eq!(parse_error("[1, 2"), "Missing right bracket.");
+		assert_eq!(parse_error("(1; 2)"), "Semicolons only go in matrices, like [1, 2; 3, 4].");
+		assert_eq!(parse_error("v[1; 2]"), "Semicolons only go in matrices, like [1, 2; 3, 4].");
+		assert_eq!(parse_error("[1, (2]"), "Missing right parentheses.");
+		
+		// Infix and RPN both go back and forth.
+		let e = expression::Expression::from_infix_str("[1, x; -2, 4][2, 1] * A[1]").unwrap();
+		assert_eq!(e.to_rpn_string(), "1 x 2 neg 4 [2x2] 2 1 [3] index A 1 [2] index *");
+		assert_eq!(e.to_string(), "[1, x; -2, 4][2, 1]*A[1]");
+		assert_eq!(expression::Expression::from_rpn_str(&e.to_rpn_string()).unwrap().to_rpn_string(), e.to_rpn_string());
+		assert_eq!(expression::Expression::from_infix_str(&e.to_string()).unwrap().to_rpn_string(), e.to_rpn_string());
+		
+		// Matrices can only be built in matrix mode, but they still simplify around the outside.
+		let mut s = expression::Expression::from_infix_str("[1 + 1, 2] * (3 - 1)").unwrap();
+		s.simplify().unwrap();
+		assert_eq!(s.to_rpn_string(), "2 2 [1x2] 2 *");
+		assert!(expression::Expression::from_infix_str("[1, 2]").unwrap().calculate(None).is_err());
+		
+		// Variables can hold matrices.
+		let mut env = environment::Environment::new();
+		let statement = |s: &str, env: &environment::Environment| environment::Statement::from_infix_str(s, Some(env)).unwrap();
+		env.run_as::<Matrix>(&statement("A = [1, 2; 3, 4]", &env)).unwrap();
+		env.run_as::<Matrix>(&statement("v = A[2]", &env)).unwrap();
+		assert_eq!(env.run_as::<Matrix>(&statement("A * transpose(v)", &env)).unwrap().unwrap().to_string(), "[11; 25]");
+		assert_eq!(env.to_string(), "A = [1, 2; 3, 4]\nv = [3, 4]");
+		// In other modes, they might as well not be there.
+		assert!(matches!(env.run(&statement("A", &env)), Err(CalcError::UndefinedVariable { .. })));
+		env.insert("A".into(), 2.0);
+		assert_eq!(env.run_as::<Matrix>(&statement("A", &env)).unwrap().unwrap().to_string(), "2");
+	}
+	
+	#[test]
+	fn linear_algebra() {
+		let matrix = |s: &str| expression::Expression::from_infix_str(s).unwrap().evaluate::<Matrix>(None);
+		let error = |s: &str| matrix(s).unwrap_err().to_string();
+		// Rounding gets into everything here, so these only have to be close.
+		let close = |s: &str, size: (usize, usize), expected: &[f64]| {
+			let m = matrix(s).unwrap();
+			assert_eq!(m.size(), size, "{}", s);
+			assert!(m.values().iter().zip(expected.iter()).all(|(a, b)| (a - b).abs() < 1e-12), "{} gave {}", s, m);
+		};
+		
+		close("solve([2, 1; 1, 3], [3; 5])", (2, 1), &[0.8, 1.4]);
+		close("solve([2, 1; 1, 3], [3, 5])", (1, 2), &[0.8, 1.4]);
+		close("solve([2, 1; 1, 3], [3, 1; 5, 2])", (2, 2), &[0.8, 0.2, 1.4, 0.6]);
+		close("solve(2, [4, 6])", (1, 2), &[2.0, 3.0]);
+		close("lstsq([1, 0; 1, 1; 1, 2], [1, 2, 4])", (1, 2), &[5.0 / 6.0, 1.5]);
+		assert_eq!(error("solve([1, 2; 2, 4], [1, 2])"), "That matrix is singular, so there isn't just one solution.");
+		assert_eq!(error("solve([1, 1; 1, 1.0000000000001], [2, 2])"), "That matrix is too close to singular for an accurate answer.");
+		assert_eq!(error("inv([1, 1; 1, 1.0000000000001])"), "That matrix is too close to singular for an accurate answer.");
+		assert_eq!(error("solve([1, 2; 3, 4], [1, 2, 3])"), "Sizes don't match. (2x2 and 1x3)");
+		assert_eq!(error("lstsq([1, 1; 1, 1; 1, 1], [1, 2, 3])"), "Those columns aren't independent, so there isn't just one best fit.");
+		assert_eq!(error("lstsq([1, 2], 3)"), "lstsq needs at least as many rows as columns.");
+		
+		// The pieces multiply back into the original.
+		close("lu_l([1, 2; 3, 4])", (2, 2), &[1.0, 0.0, 1.0 / 3.0, 1.0]);
+		close("lu_u([1, 2; 3, 4])", (2, 2), &[3.0, 4.0, 0.0, 2.0 / 3.0]);
+		close("lu_p([1, 2; 3, 4])", (2, 2), &[0.0, 1.0, 1.0, 0.0]);
+		let a = "[2, -1, 0; -1, 2, -1; 0, -1, 2]";
+		close(&format!("lu_l({0}) * lu_u({0}) - lu_p({0}) * {0}", a), (3, 3), &[0.0; 9]);
+		close("qr_q([3, 1; 4, 2])", (2, 2), &[0.6, -0.8, 0.8, 0.6]);
+		close("qr_r([3, 1; 4, 2])", (2, 2), &[5.0, 2.2, 0.0, 0.4]);
+		close("qr_q([1, 2; 3, 4; 5, 6]) * qr_r([1, 2; 3, 4; 5, 6])", (3, 2), &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+		close("qr_q(-2)", (1, 1), &[-1.0]);
+		close("chol([4, 2; 2, 3])", (2, 2), &[2.0, 0.0, 1.0, 2f64.sqrt()]);
+		assert_eq!(error("chol([1, 2; 2, 1])"), "chol needs a positive definite matrix.");
+		assert_eq!(error("chol([1, 2; 3, 4])"), "chol needs a symmetric matrix.");
+		
+		close("rank([1, 2; 2, 4])", (1, 1), &[1.0]);
+		close("rank([1, 2, 3; 4, 5, 6; 7, 8, 9])", (1, 1), &[2.0]);
+		close("rank([1, 0; 0, 1; 1, 1])", (1, 1), &[2.0]);
+		
+		// Symmetric matrices, then everything else.
+		close("eig([6, -2, -1; -2, 6, -1; -1, -1, 5])", (3, 1), &[3.0, 6.0, 8.0]);
+		let (a, b, c) = (1.0 / 3f64.sqrt(), 1.0 / 6f64.sqrt(), 1.0 / 2f64.sqrt());
+		close("eigvec([6, -2, -1; -2, 6, -1; -1, -1, 5])", (3, 3), &[a, -b, c, a, -b, -c, a, 2.0 * b, 0.0]);
+		close("eig([4, 1; 2, 3])", (2, 1), &[2.0, 5.0]);
+		let d = 1.0 / 5f64.sqrt();
+		close("eigvec([4, 1; 2, 3])", (2, 2), &[-d, c, 2.0 * d, c]);
+		close("eig([1, 2, 3; 0, 4, 5; 0, 0, 6])", (3, 1), &[1.0, 4.0, 6.0]);
+		close("eig([2, 1; 0, 2])", (2, 1), &[2.0, 2.0]);
+		assert_eq!(error("eig([0, -1; 1, 0])"), "That matrix has complex eigenvalues, and matrices only hold real numbers.");
+		assert_eq!(error("eig([0, 0, 1; 1, 0, 0; 0, 1, 0])"), "That matrix has complex eigenvalues, and matrices only hold real numbers.");
+		assert_eq!(error("eig([1, 2, 3])"), "That only works on square matrices.");
+		
+		// Plain numbers are 1x1 matrices, in any mode.
+		let real = |s: &str| expression::Expression::from_infix_str(s).unwrap().calculate(None);
+		assert_eq!(real("solve(4, 2)"), Ok(0.5));
+		assert_eq!(real("chol(9) + rank(0) + eig(2) + qr_r(-3)"), Ok(8.0));
+		assert!(real("solve(0, 2)").is_err());
 	}
 }